@@ -5,17 +5,43 @@
 use anyhow::{Context, Result, bail};
 use hmac::{Hmac, Mac};
 use sha2::Sha256;
+use std::sync::Arc;
 use uuid::Uuid;
 
 use super::protocol::*;
+use super::ws::WsLink;
 
 type HmacSha256 = Hmac<Sha256>;
 
+/// Outcome of a delivery the server answered.
+/// Transport failures and 5xx/429 are returned as errors instead.
+pub enum Delivery {
+    Accepted,
+    Rejected(String),
+}
+
+/// A server frame with its payload kept as the bytes that were signed.
+#[derive(serde::Deserialize)]
+struct SignedFrame<'a> {
+    agent_id: String,
+    ts: i64,
+    nonce: String,
+    #[serde(rename = "type")]
+    msg_type: String,
+    #[serde(borrow)]
+    payload: &'a serde_json::value::RawValue,
+    hmac: String,
+}
+
+/// Accepted clock skew for server-signed frames (matches the backend window).
+const SERVER_FRAME_MAX_AGE_SEC: i64 = 300;
+
 pub struct AgentClient {
     http: reqwest::Client,
     base_url: String,
     agent_id: Option<String>,
     agent_secret: Option<String>,
+    ws: Arc<WsLink>,
 }
 
 impl AgentClient {
@@ -32,6 +58,7 @@ impl AgentClient {
             base_url: base_url.trim_end_matches('/').to_string(),
             agent_id: None,
             agent_secret: None,
+            ws: Arc::new(WsLink::new()),
         }
     }
 
@@ -40,11 +67,30 @@ impl AgentClient {
         self.agent_secret = Some(agent_secret);
     }
 
+    pub fn ws_link(&self) -> Arc<WsLink> {
+        Arc::clone(&self.ws)
+    }
+
+    pub fn ws_connected(&self) -> bool {
+        self.ws.is_connected()
+    }
+
+    pub fn ws_url(&self) -> String {
+        let base = if let Some(rest) = self.base_url.strip_prefix("https://") {
+            format!("wss://{}", rest)
+        } else if let Some(rest) = self.base_url.strip_prefix("http://") {
+            format!("ws://{}", rest)
+        } else {
+            self.base_url.clone()
+        };
+        format!("{}/agent-v2/ws", base)
+    }
+
     // ═══════════════════════════════════════════════════════════
     // HMAC Envelope Construction
     // ═══════════════════════════════════════════════════════════
 
-    fn build_envelope(&self, msg_type: &str, payload: serde_json::Value) -> Result<AgentEnvelope> {
+    pub fn build_envelope(&self, msg_type: &str, payload: serde_json::Value) -> Result<AgentEnvelope> {
         let agent_id = self.agent_id.as_ref()
             .context("Agent not enrolled: no agent_id")?;
         let secret = self.agent_secret.as_ref()
@@ -71,6 +117,54 @@ impl AgentClient {
         })
     }
 
+    /// Parse and check a server-signed frame (WebSocket) against our
+    /// secret. The server signs the payload as `JSON.stringify` wrote
+    /// it, in insertion order, so the HMAC is checked over the raw
+    /// payload bytes rather than a re-serialization (which would sort
+    /// the keys).
+    pub fn verify_frame(&self, text: &str) -> Result<AgentEnvelope> {
+        let agent_id = self.agent_id.as_ref()
+            .context("Agent not enrolled: no agent_id")?;
+        let secret = self.agent_secret.as_ref()
+            .context("Agent not enrolled: no agent_secret")?;
+
+        let frame: SignedFrame = serde_json::from_str(text).context("Malformed frame")?;
+        if &frame.agent_id != agent_id {
+            bail!("Envelope addressed to another agent");
+        }
+        if (chrono::Utc::now().timestamp() - frame.ts).abs() > SERVER_FRAME_MAX_AGE_SEC {
+            bail!("Envelope timestamp outside allowed window");
+        }
+
+        let sign_payload = format!(
+            "{}|{}|{}|{}|{}",
+            frame.agent_id, frame.ts, frame.nonce, frame.msg_type, frame.payload.get()
+        );
+        let expected = hex::decode(&frame.hmac).context("Malformed HMAC")?;
+        let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+            .context("Invalid HMAC key")?;
+        mac.update(sign_payload.as_bytes());
+        mac.verify_slice(&expected).map_err(|_| anyhow::anyhow!("HMAC verification failed"))?;
+
+        Ok(AgentEnvelope {
+            payload: serde_json::from_str(frame.payload.get()).context("Malformed payload")?,
+            agent_id: frame.agent_id,
+            ts: frame.ts,
+            nonce: frame.nonce,
+            msg_type: frame.msg_type,
+            hmac: frame.hmac,
+        })
+    }
+
+    /// Send the envelope over the WebSocket if it is up.
+    /// Returns false when the caller must fall back to HTTP.
+    fn push_ws(&self, envelope: &AgentEnvelope) -> Result<bool> {
+        if !self.ws.is_connected() {
+            return Ok(false);
+        }
+        Ok(self.ws.send(serde_json::to_string(envelope)?))
+    }
+
     // ═══════════════════════════════════════════════════════════
     // Enrollment
     // ═══════════════════════════════════════════════════════════
@@ -107,13 +201,30 @@ impl AgentClient {
     // Heartbeat
     // ═══════════════════════════════════════════════════════════
 
-    pub async fn heartbeat(&self, payload: HeartbeatPayload) -> Result<HeartbeatResponse> {
+    /// None when the heartbeat was only queued on the socket: the
+    /// server confirms it later with a `heartbeat_ack` frame, and
+    /// pushes pending jobs and cancellations instead of returning them.
+    pub async fn heartbeat(&self, payload: HeartbeatPayload) -> Result<Option<HeartbeatResponse>> {
         let envelope = self.build_envelope("heartbeat", serde_json::to_value(&payload)?)?;
+        if self.push_ws(&envelope)? {
+            return Ok(None);
+        }
+        self.post_heartbeat(&envelope).await.map(Some)
+    }
+
+    /// Heartbeat over HTTP even with the socket up, for callers that
+    /// need the server's answer rather than a queued frame.
+    pub async fn heartbeat_http(&self, payload: HeartbeatPayload) -> Result<HeartbeatResponse> {
+        let envelope = self.build_envelope("heartbeat", serde_json::to_value(&payload)?)?;
+        self.post_heartbeat(&envelope).await
+    }
+
+    async fn post_heartbeat(&self, envelope: &AgentEnvelope) -> Result<HeartbeatResponse> {
         let url = format!("{}/agent-v2/heartbeat", self.base_url);
 
         let response = self.http
             .post(&url)
-            .json(&envelope)
+            .json(envelope)
             .send()
             .await
             .context("Heartbeat request failed")?;
@@ -134,8 +245,11 @@ impl AgentClient {
     // Metrics
     // ═══════════════════════════════════════════════════════════
 
+    /// Over HTTP so that only a server answer counts as delivered;
+    /// anything else leaves the sample to the caller's outbox.
     pub async fn report_metrics(&self, payload: MetricsPayload) -> Result<()> {
         let envelope = self.build_envelope("metrics", serde_json::to_value(&payload)?)?;
+
         let url = format!("{}/agent-v2/metrics", self.base_url);
 
         let response = self.http
@@ -145,20 +259,54 @@ impl AgentClient {
             .await
             .context("Metrics report failed")?;
 
-        if !response.status().is_success() {
-            let status = response.status();
+        let status = response.status();
+        if is_transient(status) {
+            bail!("Metrics report failed (HTTP {})", status);
+        }
+        if !status.is_success() {
             tracing::warn!("Metrics rejected (HTTP {})", status);
         }
 
         Ok(())
     }
 
+    // ═══════════════════════════════════════════════════════════
+    // Alerts
+    // ═══════════════════════════════════════════════════════════
+
+    /// Always sent over HTTP, like job results: an alert queued on a
+    /// socket that then drops would never reach anyone, and a failed
+    /// request sends it to the outbox instead.
+    pub async fn report_alert(&self, payload: &AlertPayload) -> Result<()> {
+        let envelope = self.build_envelope("alert", serde_json::to_value(payload)?)?;
+        let url = format!("{}/agent-v2/alert", self.base_url);
+
+        let response = self.http
+            .post(&url)
+            .json(&envelope)
+            .send()
+            .await
+            .context("Alert report failed")?;
+
+        let status = response.status();
+        if is_transient(status) {
+            bail!("Alert report failed (HTTP {})", status);
+        }
+        if !status.is_success() {
+            tracing::warn!("Alert rejected (HTTP {})", status);
+        }
+
+        Ok(())
+    }
+
     // ═══════════════════════════════════════════════════════════
     // Inventory
     // ═══════════════════════════════════════════════════════════
 
+    /// HTTP only, like metrics: the outbox needs a failure it can see.
     pub async fn report_inventory(&self, payload: InventoryPayload) -> Result<()> {
         let envelope = self.build_envelope("inventory", serde_json::to_value(&payload)?)?;
+
         let url = format!("{}/agent-v2/inventory", self.base_url);
 
         let response = self.http
@@ -168,8 +316,11 @@ impl AgentClient {
             .await
             .context("Inventory report failed")?;
 
-        if !response.status().is_success() {
-            let status = response.status();
+        let status = response.status();
+        if is_transient(status) {
+            bail!("Inventory report failed (HTTP {})", status);
+        }
+        if !status.is_success() {
             tracing::warn!("Inventory rejected (HTTP {})", status);
         }
 
@@ -211,6 +362,9 @@ impl AgentClient {
     // Job Result
     // ═══════════════════════════════════════════════════════════
 
+    /// Always sent over HTTP, even with the socket up: a frame queued
+    /// on the socket is lost if it drops, and a job that already ran
+    /// must not lose its result.
     pub async fn report_job_result(&self, result: JobResult) -> Result<()> {
         let envelope = self.build_envelope("job_result", serde_json::to_value(&result)?)?;
         let url = format!("{}/agent-v2/job-result", self.base_url);
@@ -231,4 +385,219 @@ impl AgentClient {
         tracing::info!("Job result reported: {}", result.job_id);
         Ok(())
     }
+
+    pub async fn send_job_output(&self, chunk: JobOutputChunk) -> Result<()> {
+        let envelope = self.build_envelope("job_output", serde_json::to_value(&chunk)?)?;
+        if self.push_ws(&envelope)? {
+            return Ok(());
+        }
+
+        let url = format!("{}/agent-v2/job-output", self.base_url);
+
+        let response = self.http
+            .post(&url)
+            .json(&envelope)
+            .send()
+            .await
+            .context("Job output send failed")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            bail!("Job output rejected (HTTP {}): {}", status, body);
+        }
+
+        Ok(())
+    }
+
+    // ═══════════════════════════════════════════════════════════
+    // Outbox Replay
+    // ═══════════════════════════════════════════════════════════
+
+    /// Re-send a queued report over HTTP so delivery is acknowledged.
+    pub async fn deliver(&self, msg_type: &str, payload: serde_json::Value) -> Result<Delivery> {
+        let path = match msg_type {
+            "metrics" => "metrics",
+            "alert" => "alert",
+            "inventory" => "inventory",
+            "job_result" => "job-result",
+            other => return Ok(Delivery::Rejected(format!("No endpoint for message type: {}", other))),
+        };
+        let envelope = self.build_envelope(msg_type, payload)?;
+        let url = format!("{}/agent-v2/{}", self.base_url, path);
+
+        let response = self.http
+            .post(&url)
+            .json(&envelope)
+            .send()
+            .await
+            .with_context(|| format!("Delivery of {} failed", msg_type))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(Delivery::Accepted);
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        if is_transient(status) {
+            bail!("Delivery of {} failed (HTTP {}): {}", msg_type, status, body);
+        }
+        Ok(Delivery::Rejected(format!("HTTP {}: {}", status, body)))
+    }
+
+    // ═══════════════════════════════════════════════════════════
+    // Remote Shell
+    // ═══════════════════════════════════════════════════════════
+
+    pub async fn send_shell_output(&self, payload: ShellOutputPayload) -> Result<()> {
+        let envelope = self.build_envelope("shell_output", serde_json::to_value(&payload)?)?;
+        if self.push_ws(&envelope)? {
+            return Ok(());
+        }
+
+        let url = format!("{}/agent-v2/shell/output", self.base_url);
+
+        let response = self.http
+            .post(&url)
+            .json(&envelope)
+            .send()
+            .await
+            .context("Shell output send failed")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            bail!("Shell output rejected (HTTP {}): {}", status, body);
+        }
+
+        Ok(())
+    }
+
+    pub async fn poll_shell_events(&self, session_ids: Vec<String>) -> Result<Vec<ShellEvent>> {
+        let payload = ShellPollPayload { session_ids };
+        let envelope = self.build_envelope("shell_poll", serde_json::to_value(&payload)?)?;
+        let url = format!("{}/agent-v2/shell/poll", self.base_url);
+
+        let response = self.http
+            .post(&url)
+            .json(&envelope)
+            .send()
+            .await
+            .context("Shell poll failed")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            bail!("Shell poll failed (HTTP {}): {}", status, body);
+        }
+
+        let api_resp: ApiResponse<ShellPollResponse> = response.json().await
+            .context("Failed to parse shell poll response")?;
+
+        Ok(api_resp.data.events)
+    }
+
+    // ═══════════════════════════════════════════════════════════
+    // Artifact Upload
+    // ═══════════════════════════════════════════════════════════
+
+    pub async fn artifact_init(&self, init: &ArtifactUploadInit) -> Result<ArtifactUploadSession> {
+        self.artifact_call("artifact_init", "init", init).await
+    }
+
+    pub async fn artifact_chunk(&self, chunk: &ArtifactChunk) -> Result<u64> {
+        let ack: ArtifactChunkAck = self.artifact_call("artifact_chunk", "chunk", chunk).await?;
+        Ok(ack.received_bytes)
+    }
+
+    pub async fn artifact_complete(&self, artifact_id: &str) -> Result<JobArtifact> {
+        let payload = ArtifactUploadComplete { artifact_id: artifact_id.to_string() };
+        self.artifact_call("artifact_complete", "complete", &payload).await
+    }
+
+    /// Artifact traffic always uses HTTP: each call needs its reply.
+    async fn artifact_call<P, T>(&self, msg_type: &str, path: &str, payload: &P) -> Result<T>
+    where
+        P: serde::Serialize,
+        T: serde::de::DeserializeOwned,
+    {
+        let envelope = self.build_envelope(msg_type, serde_json::to_value(payload)?)?;
+        let url = format!("{}/agent-v2/artifacts/{}", self.base_url, path);
+
+        let response = self.http
+            .post(&url)
+            .json(&envelope)
+            .send()
+            .await
+            .with_context(|| format!("Artifact {} failed", path))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            bail!("Artifact {} rejected (HTTP {}): {}", path, status, body);
+        }
+
+        let api_resp: ApiResponse<T> = response.json().await
+            .with_context(|| format!("Failed to parse artifact {} response", path))?;
+
+        Ok(api_resp.data)
+    }
+}
+
+/// Server-side conditions worth retrying later.
+fn is_transient(status: reqwest::StatusCode) -> bool {
+    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AGENT_ID: &str = "agent-1";
+    const SECRET: &str = "secret";
+
+    fn client() -> AgentClient {
+        let mut client = AgentClient::new("http://localhost");
+        client.set_credentials(AGENT_ID.to_string(), SECRET.to_string());
+        client
+    }
+
+    /// A frame as the backend writes it: `JSON.stringify` output,
+    /// signed over the payload in insertion order.
+    fn js_frame(payload_json: &str) -> String {
+        let ts = chrono::Utc::now().timestamp();
+        let data = format!("{}|{}|nonce-1|job|{}", AGENT_ID, ts, payload_json);
+        let mut mac = HmacSha256::new_from_slice(SECRET.as_bytes()).unwrap();
+        mac.update(data.as_bytes());
+        let hmac = hex::encode(mac.finalize().into_bytes());
+        format!(
+            r#"{{"agent_id":"{}","ts":{},"nonce":"nonce-1","type":"job","payload":{},"hmac":"{}"}}"#,
+            AGENT_ID, ts, payload_json, hmac
+        )
+    }
+
+    #[test]
+    fn verifies_payload_in_js_key_order() {
+        let payload = r#"{"job_id":"j1","type":"run_script","timeout_sec":30,"payload":{"script":"echo é"}}"#;
+        let envelope = client().verify_frame(&js_frame(payload)).unwrap();
+        assert_eq!(envelope.msg_type, "job");
+        assert_eq!(envelope.payload["job_id"], "j1");
+        assert_eq!(envelope.payload["timeout_sec"], 30);
+        assert_eq!(envelope.payload["payload"]["script"], "echo é");
+    }
+
+    #[test]
+    fn rejects_tampered_payload() {
+        let frame = js_frame(r#"{"job_id":"j1","timeout_sec":30}"#)
+            .replace(r#""timeout_sec":30"#, r#""timeout_sec":31"#);
+        assert!(client().verify_frame(&frame).is_err());
+    }
+
+    #[test]
+    fn rejects_frames_for_another_agent() {
+        let frame = js_frame(r#"{"job_id":"j1"}"#);
+        let mut other = AgentClient::new("http://localhost");
+        other.set_credentials("agent-2".to_string(), SECRET.to_string());
+        assert!(other.verify_frame(&frame).is_err());
+    }
 }
@@ -1,2 +1,4 @@
 pub mod client;
 pub mod protocol;
+pub mod ws;
+pub mod outbox;
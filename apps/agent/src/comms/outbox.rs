@@ -0,0 +1,270 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r Agent - Durable Outbox
+// ─────────────────────────────────────────────────────────────
+//
+// Reports that could not be delivered are persisted here, one
+// file per entry, and replayed over HTTP once the server is
+// reachable again. Payloads are stored untouched so their own
+// timestamps survive; only the envelope is re-signed on replay.
+//
+// File names sort in drain order: job results first, then
+// everything else oldest-first.
+//   {priority}-{queued_at_ms}-{seq}.json
+// ─────────────────────────────────────────────────────────────
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use super::client::{AgentClient, Delivery};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OutboxEntry {
+    msg_type: String,
+    queued_at: i64,
+    payload: serde_json::Value,
+}
+
+pub struct Outbox {
+    dir: PathBuf,
+    max_bytes: u64,
+    max_age_ms: i64,
+    seq: AtomicU64,
+    // Serialises directory scans against concurrent pushes
+    lock: Mutex<()>,
+    /// Reports queued since start, by message type (i.e. failed sends).
+    queued: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Outbox {
+    pub fn open(dir: PathBuf, max_bytes: u64, max_age_sec: u64) -> Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create outbox directory {:?}", dir))?;
+
+        // Leftovers from a crash mid-write are never complete entries
+        for path in list_files(&dir, "tmp")? {
+            let _ = std::fs::remove_file(path);
+        }
+
+        let outbox = Self {
+            dir,
+            max_bytes,
+            max_age_ms: max_age_sec as i64 * 1000,
+            seq: AtomicU64::new(0),
+            lock: Mutex::new(()),
+            queued: Mutex::new(BTreeMap::new()),
+        };
+
+        let pending = outbox.len();
+        if pending > 0 {
+            tracing::info!("Outbox holds {} undelivered report(s)", pending);
+        }
+        Ok(outbox)
+    }
+
+    /// Persist a report for later delivery.
+    pub fn push<T: Serialize>(&self, msg_type: &str, payload: &T) -> Result<()> {
+        let entry = OutboxEntry {
+            msg_type: msg_type.to_string(),
+            queued_at: chrono::Utc::now().timestamp_millis(),
+            payload: serde_json::to_value(payload)?,
+        };
+        let priority = if msg_type == "job_result" { 0 } else { 1 };
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        let name = format!("{}-{:013}-{:06}", priority, entry.queued_at, seq % 1_000_000);
+
+        let _guard = self.lock.lock().unwrap();
+
+        // Write + fsync a temp file, then rename: entries are all or nothing
+        let tmp_path = self.dir.join(format!("{}.tmp", name));
+        let final_path = self.dir.join(format!("{}.json", name));
+        let mut file = std::fs::File::create(&tmp_path)
+            .with_context(|| format!("Failed to create {:?}", tmp_path))?;
+        file.write_all(&serde_json::to_vec(&entry)?)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, &final_path)?;
+
+        *self.queued.lock().unwrap().entry(msg_type.to_string()).or_default() += 1;
+        self.enforce_limits(&final_path)?;
+        tracing::debug!("Queued {} in outbox", msg_type);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        list_files(&self.dir, "json").map(|f| f.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reports queued since the agent started, by message type.
+    pub fn queued_counts(&self) -> BTreeMap<String, u64> {
+        self.queued.lock().unwrap().clone()
+    }
+
+    /// Disk space taken by undelivered reports.
+    pub fn size_bytes(&self) -> u64 {
+        list_files(&self.dir, "json")
+            .map(|files| files.iter().filter_map(|p| std::fs::metadata(p).ok()).map(|m| m.len()).sum())
+            .unwrap_or(0)
+    }
+
+    /// Replay queued reports in order. Stops at the first transient
+    /// failure so ordering is kept; rejected entries are discarded.
+    pub async fn drain(&self, client: &AgentClient) -> Result<usize> {
+        let entries = {
+            let _guard = self.lock.lock().unwrap();
+            list_files(&self.dir, "json")?
+        };
+
+        let now = chrono::Utc::now().timestamp_millis();
+        let mut delivered = 0;
+
+        for path in entries {
+            let entry = match read_entry(&path) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    tracing::warn!("Discarding unreadable outbox entry {:?}: {}", path, e);
+                    let _ = std::fs::remove_file(&path);
+                    continue;
+                }
+            };
+
+            if now - entry.queued_at > self.max_age_ms {
+                tracing::warn!("Discarding expired {} from outbox", entry.msg_type);
+                let _ = std::fs::remove_file(&path);
+                continue;
+            }
+
+            match client.deliver(&entry.msg_type, entry.payload).await? {
+                Delivery::Accepted => delivered += 1,
+                Delivery::Rejected(reason) => {
+                    tracing::warn!("Server rejected queued {}: {}", entry.msg_type, reason);
+                }
+            }
+            let _ = std::fs::remove_file(&path);
+        }
+
+        Ok(delivered)
+    }
+
+    /// Evict the oldest low-priority reports until the outbox fits in
+    /// `max_bytes`. Job results are never evicted: once they alone
+    /// fill the budget, new low-priority reports are refused instead.
+    fn enforce_limits(&self, new_entry: &Path) -> Result<()> {
+        let files: Vec<(PathBuf, u64)> = list_files(&self.dir, "json")?
+            .into_iter()
+            .map(|p| {
+                let size = std::fs::metadata(&p).map(|m| m.len()).unwrap_or(0);
+                (p, size)
+            })
+            .collect();
+
+        let mut total: u64 = files.iter().map(|(_, size)| size).sum();
+        if total <= self.max_bytes {
+            return Ok(());
+        }
+
+        // Priority 1 (metrics, inventory...), oldest first
+        for (path, size) in files.iter().filter(|(p, _)| file_name(p).starts_with('1')) {
+            if total <= self.max_bytes {
+                return Ok(());
+            }
+            std::fs::remove_file(path)?;
+            total = total.saturating_sub(*size);
+            if path == new_entry {
+                bail!("Outbox full of job results, report not queued");
+            }
+            tracing::warn!("Outbox full, dropping {:?}", file_name(path));
+        }
+
+        if total > self.max_bytes {
+            tracing::warn!("Outbox holds {} bytes of job results, over its {} byte limit", total, self.max_bytes);
+        }
+        Ok(())
+    }
+}
+
+fn read_entry(path: &Path) -> Result<OutboxEntry> {
+    let bytes = std::fs::read(path)?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Files with the given extension, sorted by name.
+fn list_files(dir: &Path, extension: &str) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == extension))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outbox(max_bytes: u64) -> Outbox {
+        let dir = std::env::temp_dir().join(format!("reap3r-outbox-{}", uuid::Uuid::new_v4()));
+        Outbox::open(dir, max_bytes, 3600).unwrap()
+    }
+
+    fn types(outbox: &Outbox) -> Vec<String> {
+        list_files(&outbox.dir, "json").unwrap()
+            .iter()
+            .map(|p| read_entry(p).unwrap().msg_type)
+            .collect()
+    }
+
+    #[test]
+    fn job_results_sort_first() {
+        let outbox = outbox(1 << 20);
+        outbox.push("metrics", &serde_json::json!({ "n": 1 })).unwrap();
+        outbox.push("job_result", &serde_json::json!({ "job_id": "j1" })).unwrap();
+        outbox.push("inventory", &serde_json::json!({ "n": 2 })).unwrap();
+        assert_eq!(types(&outbox), ["job_result", "metrics", "inventory"]);
+        let _ = std::fs::remove_dir_all(&outbox.dir);
+    }
+
+    #[test]
+    fn evicts_oldest_low_priority_first() {
+        let outbox = outbox(200);
+        for n in 0..4 {
+            outbox.push("metrics", &serde_json::json!({ "n": n })).unwrap();
+        }
+        outbox.push("job_result", &serde_json::json!({ "job_id": "j1" })).unwrap();
+        assert!(outbox.size_bytes() <= 200);
+        assert_eq!(types(&outbox).first().map(String::as_str), Some("job_result"));
+
+        let kept: Vec<i64> = list_files(&outbox.dir, "json").unwrap()
+            .iter()
+            .filter_map(|p| read_entry(p).unwrap().payload["n"].as_i64())
+            .collect();
+        assert!(!kept.is_empty() && kept.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(kept.last(), Some(&3));
+        let _ = std::fs::remove_dir_all(&outbox.dir);
+    }
+
+    #[test]
+    fn never_evicts_job_results() {
+        let outbox = outbox(100);
+        for n in 0..3 {
+            outbox.push("job_result", &serde_json::json!({ "job_id": n })).unwrap();
+        }
+        assert!(outbox.push("metrics", &serde_json::json!({ "n": 1 })).is_err());
+        assert_eq!(types(&outbox), ["job_result", "job_result", "job_result"]);
+        let _ = std::fs::remove_dir_all(&outbox.dir);
+    }
+}
@@ -3,8 +3,9 @@
 // ─────────────────────────────────────────────────────────────
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-/// Signed envelope wrapping all agent → backend messages.
+/// Signed envelope wrapping all agent ↔ backend messages.
 /// HMAC-SHA256(agent_secret, "{agent_id}|{ts}|{nonce}|{type}|{payload_json}")
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentEnvelope {
@@ -37,13 +38,112 @@ pub struct EnrollmentResponse {
     pub agent_id: String,
     pub agent_secret: String,
     #[serde(default)]
-    pub policy: Option<serde_json::Value>,
+    pub policy: Option<AgentPolicy>,
     #[serde(default)]
     pub heartbeat_interval_sec: Option<u64>,
     #[serde(default)]
     pub capabilities: Option<Vec<String>>,
 }
 
+/// Server-side limits for this agent. An absent field leaves the
+/// local setting in force; an absent `allowed_job_types` allows every
+/// type, an empty one allows none.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AgentPolicy {
+    #[serde(default)]
+    pub metrics_interval_sec: Option<u64>,
+    #[serde(default)]
+    pub inventory_interval_sec: Option<u64>,
+    #[serde(default)]
+    pub allowed_job_types: Option<Vec<String>>,
+    #[serde(default)]
+    pub max_concurrent_jobs: Option<usize>,
+    #[serde(default)]
+    pub update_channel: Option<String>,
+    /// Threshold rules evaluated locally against every metrics sample.
+    #[serde(default)]
+    pub alert_rules: Option<Vec<AlertRule>>,
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Alerts
+// ═══════════════════════════════════════════════════════════════
+
+/// "disk.available_percent < 5 for 2 samples", "cpu.usage_percent > 90 for 300s".
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    pub metric: String,
+    pub operator: AlertOperator,
+    pub threshold: f64,
+    /// The value must get back past this (not just the threshold)
+    /// before the alert resolves. Defaults to the threshold.
+    #[serde(default)]
+    pub clear_threshold: Option<f64>,
+    /// Consecutive samples the condition must hold, to fire and to resolve.
+    #[serde(default)]
+    pub for_samples: Option<u32>,
+    /// Time the condition must hold, to fire and to resolve.
+    #[serde(default)]
+    pub for_sec: Option<u64>,
+    /// Only this mount point, interface or sensor; every one otherwise.
+    #[serde(default)]
+    pub instance: Option<String>,
+    #[serde(default)]
+    pub severity: AlertSeverity,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AlertOperator {
+    #[serde(rename = ">")]
+    Above,
+    #[serde(rename = ">=")]
+    AtLeast,
+    #[serde(rename = "<")]
+    Below,
+    #[serde(rename = "<=")]
+    AtMost,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertSeverity {
+    Info,
+    #[default]
+    Warning,
+    Critical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+/// Sent as an `alert` envelope when a rule fires or resolves.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertPayload {
+    pub rule_id: String,
+    #[serde(default)]
+    pub rule_name: Option<String>,
+    pub state: AlertState,
+    pub severity: AlertSeverity,
+    pub metric: String,
+    /// Mount point, interface or sensor label for per-instance metrics.
+    #[serde(default)]
+    pub instance: Option<String>,
+    /// Latest value; null when the instance or rule went away.
+    pub value: Option<f64>,
+    pub operator: AlertOperator,
+    pub threshold: f64,
+    /// When the condition first held (Unix milliseconds).
+    pub started_at: i64,
+    pub timestamp: i64,
+}
+
 // ═══════════════════════════════════════════════════════════════
 // Heartbeat
 // ═══════════════════════════════════════════════════════════════
@@ -61,6 +161,11 @@ pub struct HeartbeatPayload {
 pub struct HeartbeatResponse {
     pub ack: bool,
     pub pending_job: Option<JobRequest>,
+    #[serde(default)]
+    pub cancel_jobs: Vec<String>,
+    /// The agent's current policy; applied when it differs.
+    #[serde(default)]
+    pub policy: Option<AgentPolicy>,
 }
 
 // ═══════════════════════════════════════════════════════════════
@@ -76,6 +181,66 @@ pub struct MetricsPayload {
     pub network: Vec<NetworkMetrics>,
     pub processes_count: u32,
     pub uptime_sec: u64,
+    // The fields below are absent from older agents and on platforms
+    // that do not expose them
+    /// 1, 5 and 15 minute load averages (not on Windows).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub load_average: Option<[f64; 3]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pressure: Option<PressureMetrics>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub temperatures: Vec<TemperatureMetrics>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_descriptors: Option<FileDescriptorMetrics>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_switches_sec: Option<u64>,
+    /// Summary of the fine-grained samples taken since the previous report.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggregates: Option<MetricsAggregates>,
+    /// Heaviest processes, on every `metrics_top_processes_every`th report.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_processes: Option<TopProcesses>,
+    /// Running containers (Linux, cgroup v2).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub containers: Vec<ContainerMetrics>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerMetrics {
+    pub id: String,
+    /// Runtime name (Kubernetes: namespace/pod/container), else the short ID.
+    pub name: String,
+    /// docker, podman, containerd, cri-o or unknown.
+    pub runtime: String,
+    /// Per core since the previous report: 200 is two cores busy.
+    pub cpu_percent: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_limit_cores: Option<f64>,
+    /// Without reclaimable page cache, as `docker stats` shows it.
+    pub memory_bytes: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_limit_bytes: Option<u64>,
+    pub io_read_bytes_sec: u64,
+    pub io_write_bytes_sec: u64,
+    pub pids: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopProcesses {
+    pub by_cpu: Vec<ProcessUsage>,
+    pub by_memory: Vec<ProcessUsage>,
+}
+
+/// `cpu_percent` is averaged since the previous report and counted
+/// per core, as in top: a process busy on two cores reports 200.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessUsage {
+    pub pid: u32,
+    pub name: String,
+    #[serde(default)]
+    pub user: Option<String>,
+    pub cpu_percent: f64,
+    pub memory_bytes: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -85,6 +250,78 @@ pub struct CpuMetrics {
     pub model: String,
     pub frequency_mhz: u64,
     pub per_core_usage: Vec<f64>,
+    /// Share of CPU time waiting on I/O since the previous sample.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iowait_percent: Option<f64>,
+    /// Share of CPU time taken by the hypervisor since the previous sample.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub steal_percent: Option<f64>,
+}
+
+/// Linux pressure stall information (/proc/pressure), per resource.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PressureMetrics {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<PressureStall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<PressureStall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub io: Option<PressureStall>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PressureStall {
+    /// Some tasks were stalled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub some: Option<PressureLine>,
+    /// All non-idle tasks were stalled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full: Option<PressureLine>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PressureLine {
+    /// Percent of time stalled over the last 10, 60 and 300 seconds.
+    pub avg10: f64,
+    pub avg60: f64,
+    pub avg300: f64,
+    /// Cumulative stall time in microseconds.
+    pub total_us: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemperatureMetrics {
+    pub label: String,
+    pub celsius: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub critical_celsius: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsAggregates {
+    pub samples: u32,
+    pub sample_interval_sec: u64,
+    pub cpu_percent: Aggregate,
+    pub memory_percent: Aggregate,
+    pub disk_read_bytes_sec: Aggregate,
+    pub disk_write_bytes_sec: Aggregate,
+    pub net_rx_bytes_sec: Aggregate,
+    pub net_tx_bytes_sec: Aggregate,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Aggregate {
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    pub p95: f64,
+}
+
+/// System-wide file handles.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileDescriptorMetrics {
+    pub open: u64,
+    pub max: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -138,6 +375,18 @@ pub struct InventoryPayload {
     pub services: Vec<ServiceInfo>,
     pub users: Vec<LocalUser>,
     pub network_config: Vec<NetworkConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub container_runtimes: Vec<ContainerRuntime>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerRuntime {
+    /// docker, podman, containerd or cri-o.
+    pub name: String,
+    pub version: Option<String>,
+    /// API socket, when the runtime has a system one.
+    pub socket: Option<String>,
+    pub running_containers: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -238,6 +487,70 @@ pub struct JobRequest {
     pub organization_id: String,
 }
 
+impl JobRequest {
+    /// Decode `payload` according to `type`. Payload structs reject
+    /// unknown fields, so a misspelt field is an error rather than a
+    /// silently applied default.
+    pub fn parse_payload(&self) -> Result<JobPayload, PayloadError> {
+        let payload = &self.payload;
+        Ok(match self.job_type.as_str() {
+            "run_script" => JobPayload::RunScript(self.decode(payload)?),
+            "remote_shell_start" => JobPayload::RemoteShellStart(self.decode(payload)?),
+            "remote_shell_stop" => JobPayload::RemoteShellStop(self.decode(payload)?),
+            "remote_desktop_start" => JobPayload::RemoteDesktopStart(self.decode(payload)?),
+            "remote_desktop_stop" => JobPayload::RemoteDesktopStop(self.decode(payload)?),
+            "remote_desktop_stream" => JobPayload::RemoteDesktopStream(self.decode(payload)?),
+            "remote_desktop_input" => JobPayload::RemoteDesktopInput(self.decode(payload)?),
+            "remote_desktop_privacy_mode_set" => JobPayload::RemoteDesktopPrivacyModeSet(self.decode(payload)?),
+            "remote_desktop_input_lock_set" => JobPayload::RemoteDesktopInputLockSet(self.decode(payload)?),
+            "wake_on_lan" => JobPayload::WakeOnLan(self.decode(payload)?),
+            "agent_update" => JobPayload::AgentUpdate(self.decode(payload)?),
+            "reboot" => JobPayload::Reboot(self.decode(payload)?),
+            "shutdown" => JobPayload::Shutdown(self.decode(payload)?),
+            "service_restart" => JobPayload::ServiceRestart(self.decode(payload)?),
+            "service_stop" => JobPayload::ServiceStop(self.decode(payload)?),
+            "service_start" => JobPayload::ServiceStart(self.decode(payload)?),
+            "process_kill" => JobPayload::ProcessKill(self.decode(payload)?),
+            "process_list" => JobPayload::ProcessList(self.decode(payload)?),
+            "metrics_history" => JobPayload::MetricsHistory(self.decode(payload)?),
+            "artifact_upload" => JobPayload::ArtifactUpload(self.decode(payload)?),
+            "artifact_download" => JobPayload::ArtifactDownload(self.decode(payload)?),
+            "webcam_capture" => JobPayload::WebcamCapture(self.decode(payload)?),
+            other => return Err(PayloadError::UnknownType(other.to_string())),
+        })
+    }
+
+    fn decode<T: serde::de::DeserializeOwned>(&self, payload: &serde_json::Value) -> Result<T, PayloadError> {
+        serde_path_to_error::deserialize(payload).map_err(|e| {
+            let field = e.path().to_string();
+            PayloadError::Invalid {
+                job_type: self.job_type.clone(),
+                field: (field != ".").then_some(field),
+                message: e.into_inner().to_string(),
+            }
+        })
+    }
+}
+
+/// Why a job's payload could not be used.
+#[derive(Debug, thiserror::Error)]
+pub enum PayloadError {
+    #[error("Unknown job type: {0}")]
+    UnknownType(String),
+    #[error("Invalid {job_type} payload{}: {message}", field.as_ref().map(|f| format!(" at `{}`", f)).unwrap_or_default())]
+    Invalid {
+        job_type: String,
+        field: Option<String>,
+        message: String,
+    },
+}
+
+/// Server → agent `job_cancel` frame.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobCancelPayload {
+    pub job_id: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JobResult {
     pub job_id: String,
@@ -248,9 +561,451 @@ pub struct JobResult {
     pub stderr: Option<String>,
     pub exit_code: Option<i32>,
     pub error_message: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<JobArtifact>,
     pub result_data: Option<serde_json::Value>,
 }
 
+/// A file collected from the endpoint and stored by the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobArtifact {
+    pub artifact_id: String,
+    pub filename: String,
+    pub mime_type: String,
+    pub size_bytes: u64,
+    pub sha256: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// Incremental script output, sent while the job runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobOutputChunk {
+    pub job_id: String,
+    pub seq: u64,
+    pub stream: String,
+    pub data: String,
+    pub timestamp: i64,
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Job Payloads (mirror JobPayloadMap in packages/shared/src/jobs.ts)
+// ═══════════════════════════════════════════════════════════════
+
+/// A job payload decoded for its `type`. Remote desktop and webcam
+/// payloads are decoded for validation only; this agent has no
+/// handler for them.
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub enum JobPayload {
+    RunScript(RunScriptPayload),
+    RemoteShellStart(RemoteShellStartPayload),
+    RemoteShellStop(RemoteShellStopPayload),
+    RemoteDesktopStart(RemoteDesktopStartPayload),
+    RemoteDesktopStop(RemoteDesktopStopPayload),
+    RemoteDesktopStream(RemoteDesktopStreamPayload),
+    RemoteDesktopInput(RemoteDesktopInputPayload),
+    RemoteDesktopPrivacyModeSet(PrivacyModePayload),
+    RemoteDesktopInputLockSet(InputLockPayload),
+    WakeOnLan(WakeOnLanPayload),
+    AgentUpdate(AgentUpdatePayload),
+    Reboot(PowerPayload),
+    Shutdown(PowerPayload),
+    ServiceRestart(ServiceActionPayload),
+    ServiceStop(ServiceActionPayload),
+    ServiceStart(ServiceActionPayload),
+    ProcessKill(ProcessKillPayload),
+    ProcessList(ProcessListPayload),
+    MetricsHistory(MetricsHistoryPayload),
+    ArtifactUpload(ArtifactUploadPayload),
+    ArtifactDownload(ArtifactDownloadPayload),
+    WebcamCapture(WebcamCapturePayload),
+}
+
+fn default_true() -> bool { true }
+fn default_cols() -> u16 { 120 }
+fn default_rows() -> u16 { 30 }
+fn default_update_channel() -> String { "stable".to_string() }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptLanguage {
+    Powershell,
+    Bash,
+    Python,
+    Cmd,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RunScriptPayload {
+    pub language: ScriptLanguage,
+    pub script: String,
+    #[serde(default)]
+    pub working_dir: Option<String>,
+    #[serde(default)]
+    pub env_vars: HashMap<String, String>,
+    #[serde(default)]
+    pub run_as: Option<String>,
+    #[serde(default = "default_true")]
+    pub capture_output: bool,
+    #[serde(default)]
+    pub limits: Option<ResourceLimits>,
+    /// Base64 Ed25519 signature by an organization script signing key.
+    #[serde(default)]
+    pub signature: Option<String>,
+    /// Unix seconds after which the signature is refused; signed.
+    #[serde(default)]
+    pub signature_expires_at: Option<i64>,
+}
+
+/// Resource caps for one run_script job. Also used for the local
+/// maximums in `AgentConfig::job_limits`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResourceLimits {
+    /// CPU seconds used by the whole job.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_time_sec: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_mb: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_processes: Option<u64>,
+    /// Per process.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_open_files: Option<u64>,
+    /// stdout and stderr together.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShellKind {
+    Powershell,
+    Bash,
+    Cmd,
+    Sh,
+}
+
+impl ShellKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ShellKind::Powershell => "powershell",
+            ShellKind::Bash => "bash",
+            ShellKind::Cmd => "cmd",
+            ShellKind::Sh => "sh",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RemoteShellStartPayload {
+    pub shell: ShellKind,
+    #[serde(default = "default_cols")]
+    pub cols: u16,
+    #[serde(default = "default_rows")]
+    pub rows: u16,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RemoteShellStopPayload {
+    pub session_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(dead_code)]
+pub struct RemoteDesktopStartPayload {
+    pub mode: String,
+    pub fps: u32,
+    pub quality: u8,
+    pub scale: f64,
+    #[serde(default)]
+    pub monitor_index: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(dead_code)]
+pub struct RemoteDesktopStopPayload {
+    pub session_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(dead_code)]
+pub struct RemoteDesktopStreamPayload {
+    pub session_id: String,
+    pub action: String,
+    #[serde(default)]
+    pub quality: Option<u8>,
+    #[serde(default)]
+    pub fps: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(dead_code)]
+pub struct RemoteDesktopInputPayload {
+    pub session_id: String,
+    /// Input events are passed through untouched.
+    pub events: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(dead_code)]
+pub struct PrivacyModePayload {
+    pub enabled: bool,
+    #[serde(default)]
+    pub auto_restore_on_end: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(dead_code)]
+pub struct InputLockPayload {
+    pub enabled: bool,
+    #[serde(default)]
+    pub lock: Vec<String>,
+    #[serde(default)]
+    pub auto_restore_on_end: bool,
+    #[serde(default)]
+    pub ttl_sec: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WakeOnLanPayload {
+    pub target_mac: String,
+    #[serde(default)]
+    pub target_agent_id: Option<String>,
+    #[serde(default)]
+    pub broadcast_address: Option<String>,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub secureon_password: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgentUpdatePayload {
+    pub version: String,
+    /// Release channel the build was published to; part of the signed message.
+    #[serde(default = "default_update_channel")]
+    pub channel: String,
+    pub url: String,
+    pub sha256: String,
+    pub signature: String,
+    #[serde(default)]
+    pub force: bool,
+    /// Required to install a version older than the running one.
+    #[serde(default)]
+    pub allow_downgrade: bool,
+    #[serde(default = "default_true")]
+    pub rollback_on_failure: bool,
+}
+
+/// `reboot` and `shutdown`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PowerPayload {
+    #[serde(default)]
+    pub delay_sec: u64,
+    #[serde(default)]
+    pub force: bool,
+    #[serde(default)]
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServiceActionPayload {
+    pub service_name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProcessKillPayload {
+    /// Exactly one of pid, name, exe_path and cmdline_pattern.
+    #[serde(default)]
+    pub pid: Option<u32>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub exe_path: Option<String>,
+    /// Regex over the space-joined command line.
+    #[serde(default)]
+    pub cmdline_pattern: Option<String>,
+    #[serde(default)]
+    pub signal: Option<String>,
+    #[serde(default)]
+    pub kill_children: bool,
+    /// Refuse, signalling nothing, when more processes match.
+    #[serde(default)]
+    pub max_matches: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessSortKey {
+    #[default]
+    Cpu,
+    Memory,
+    Pid,
+    Name,
+    StartTime,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProcessListPayload {
+    #[serde(default)]
+    pub sort_by: ProcessSortKey,
+    /// Defaults to descending for cpu, memory and start_time.
+    #[serde(default)]
+    pub descending: Option<bool>,
+    /// Case-insensitive substring of the process name.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Exact owner user name.
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsHistoryPayload {
+    /// How far back to return raw samples (default 5).
+    #[serde(default)]
+    pub minutes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArtifactUploadPayload {
+    pub source_path: String,
+    #[serde(default)]
+    pub filename: Option<String>,
+    #[serde(default)]
+    pub compress: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArtifactDownloadPayload {
+    pub url: String,
+    pub destination_path: String,
+    pub sha256: String,
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(dead_code)]
+pub struct WebcamCapturePayload {
+    pub device_index: u32,
+    #[serde(default)]
+    pub duration_sec: Option<u64>,
+    #[serde(default)]
+    pub resolution: Option<serde_json::Value>,
+}
+
+// ═══════════════════════════════════════════════════════════════
+// WebSocket Channel
+// ═══════════════════════════════════════════════════════════════
+
+/// First frame on the agent socket; answered with a signed `hello_ack`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WsHelloPayload {
+    pub agent_version: String,
+    pub capabilities: Vec<String>,
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Remote Shell
+// ═══════════════════════════════════════════════════════════════
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShellOutputPayload {
+    pub session_id: String,
+    pub seq: u64,
+    pub data: String,
+    pub closed: bool,
+    #[serde(default)]
+    pub close_reason: Option<String>,
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShellPollPayload {
+    pub session_ids: Vec<String>,
+}
+
+/// Operator-side event relayed by the backend for an open session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ShellEvent {
+    Input { session_id: String, data: String },
+    Resize { session_id: String, cols: u16, rows: u16 },
+    Stop { session_id: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShellPollResponse {
+    #[serde(default)]
+    pub events: Vec<ShellEvent>,
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Artifact Upload
+// ═══════════════════════════════════════════════════════════════
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactUploadInit {
+    pub job_id: String,
+    pub filename: String,
+    pub mime_type: String,
+    pub size_bytes: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactUploadSession {
+    pub artifact_id: String,
+    /// Bytes the server already holds; the upload resumes here.
+    pub received_bytes: u64,
+    pub chunk_size: usize,
+    #[serde(default)]
+    pub complete: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactChunk {
+    pub artifact_id: String,
+    pub offset: u64,
+    /// base64
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactChunkAck {
+    pub received_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactUploadComplete {
+    pub artifact_id: String,
+}
+
 // ═══════════════════════════════════════════════════════════════
 // API Response Wrapper
 // ═══════════════════════════════════════════════════════════════
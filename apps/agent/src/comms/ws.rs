@@ -0,0 +1,254 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r Agent - WebSocket Transport
+// ─────────────────────────────────────────────────────────────
+//
+// Optional long-lived channel to /agent-v2/ws carrying the same
+// HMAC-signed envelopes as the HTTP endpoints, in both directions.
+// While connected the server pushes jobs and shell events, and
+// AgentClient routes outgoing reports over the socket. When the
+// socket is down every call falls back to plain HTTP.
+// ─────────────────────────────────────────────────────────────
+
+use anyhow::{Context, Result, bail};
+use futures_util::{SinkExt, StreamExt};
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, RwLock};
+use tokio::time::{interval, sleep, timeout, Duration, Instant};
+use tokio_tungstenite::tungstenite::Message;
+
+use super::client::AgentClient;
+use super::protocol::{AgentEnvelope, WsHelloPayload};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+const READ_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Connections that stay up this long reset the reconnect backoff.
+const STABLE_CONNECTION: Duration = Duration::from_secs(60);
+
+/// Number of server nonces remembered for replay detection.
+const NONCE_MEMORY: usize = 1024;
+
+/// Shared handle to the current socket, if any. Outlives every
+/// connection, so server nonces seen before a reconnect stay refused.
+#[derive(Default)]
+pub struct WsLink {
+    outbound: Mutex<Option<mpsc::UnboundedSender<String>>>,
+    seen_nonces: Mutex<NonceWindow>,
+}
+
+impl WsLink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.outbound
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|tx| !tx.is_closed())
+    }
+
+    /// Queue a frame on the socket. Returns false when not connected,
+    /// in which case the caller should use HTTP instead.
+    pub fn send(&self, frame: String) -> bool {
+        match self.outbound.lock().unwrap().as_ref() {
+            Some(tx) => tx.send(frame).is_ok(),
+            None => false,
+        }
+    }
+
+    fn attach(&self, tx: mpsc::UnboundedSender<String>) {
+        *self.outbound.lock().unwrap() = Some(tx);
+    }
+
+    fn detach(&self) {
+        *self.outbound.lock().unwrap() = None;
+    }
+
+    /// False if a server frame with this nonce was already accepted.
+    fn first_seen(&self, nonce: &str) -> bool {
+        self.seen_nonces.lock().unwrap().insert(nonce)
+    }
+}
+
+/// Keep the socket connected for the life of the agent, forwarding
+/// verified server frames to `inbound`.
+pub async fn run(
+    client: Arc<RwLock<AgentClient>>,
+    hello: WsHelloPayload,
+    inbound: mpsc::Sender<AgentEnvelope>,
+) {
+    let mut backoff = Duration::from_secs(1);
+
+    loop {
+        let started = Instant::now();
+
+        match session(&client, &hello, &inbound).await {
+            Ok(()) => tracing::info!("WebSocket closed by server"),
+            Err(e) => tracing::warn!("WebSocket unavailable, using HTTP polling: {:#}", e),
+        }
+
+        if started.elapsed() >= STABLE_CONNECTION {
+            backoff = Duration::from_secs(1);
+        }
+
+        sleep(backoff + jitter(backoff)).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+async fn session(
+    client: &Arc<RwLock<AgentClient>>,
+    hello: &WsHelloPayload,
+    inbound: &mpsc::Sender<AgentEnvelope>,
+) -> Result<()> {
+    let c = client.read().await;
+    let url = c.ws_url();
+    let link = c.ws_link();
+    let hello_frame = serde_json::to_string(
+        &c.build_envelope("hello", serde_json::to_value(hello)?)?,
+    )?;
+    drop(c);
+
+    let (mut socket, _) = timeout(CONNECT_TIMEOUT, tokio_tungstenite::connect_async(url.as_str()))
+        .await
+        .context("WebSocket connect timed out")?
+        .context("WebSocket connect failed")?;
+
+    socket.send(Message::Text(hello_frame)).await?;
+
+    // The server answers a valid hello with a signed hello_ack
+    let ack = timeout(CONNECT_TIMEOUT, socket.next())
+        .await
+        .context("No hello_ack from server")?;
+    match ack {
+        Some(Ok(Message::Text(text))) => {
+            let envelope = verify_frame(client, &link, &text).await?;
+            if envelope.msg_type != "hello_ack" {
+                bail!("Unexpected first frame: {}", envelope.msg_type);
+            }
+        }
+        Some(Ok(Message::Close(frame))) => bail!("Server rejected hello: {:?}", frame),
+        other => bail!("Handshake failed: {:?}", other),
+    }
+
+    tracing::info!("WebSocket connected to {}", url);
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    link.attach(tx);
+
+    let result = async {
+        let mut ping = interval(PING_INTERVAL);
+        let mut last_read = Instant::now();
+
+        loop {
+            tokio::select! {
+                frame = rx.recv() => match frame {
+                    Some(text) => socket.send(Message::Text(text)).await?,
+                    None => return Ok(()),
+                },
+                msg = socket.next() => {
+                    last_read = Instant::now();
+                    match msg {
+                        Some(Ok(Message::Text(text))) => {
+                            match verify_frame(client, &link, &text).await {
+                                Ok(envelope) => {
+                                    if inbound.send(envelope).await.is_err() {
+                                        return Ok(());
+                                    }
+                                }
+                                Err(e) => tracing::warn!("Dropping server frame: {}", e),
+                            }
+                        }
+                        Some(Ok(Message::Close(_))) | None => return Ok(()),
+                        Some(Ok(_)) => {}
+                        Some(Err(e)) => return Err(e.into()),
+                    }
+                }
+                _ = ping.tick() => {
+                    if last_read.elapsed() > READ_IDLE_TIMEOUT {
+                        bail!("No frames from server for {}s", READ_IDLE_TIMEOUT.as_secs());
+                    }
+                    socket.send(Message::Ping(Vec::new())).await?;
+                }
+            }
+        }
+    }
+    .await;
+
+    link.detach();
+    let _ = socket.close(None).await;
+    result
+}
+
+async fn verify_frame(client: &Arc<RwLock<AgentClient>>, link: &WsLink, text: &str) -> Result<AgentEnvelope> {
+    let envelope = client.read().await.verify_frame(text)?;
+    if !link.first_seen(&envelope.nonce) {
+        bail!("Replayed nonce {}", envelope.nonce);
+    }
+    Ok(envelope)
+}
+
+/// Up to 50% extra delay so reconnecting agents do not stampede.
+fn jitter(base: Duration) -> Duration {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    base.mul_f64((nanos % 500) as f64 / 1000.0)
+}
+
+#[derive(Default)]
+struct NonceWindow {
+    order: VecDeque<String>,
+    seen: HashSet<String>,
+}
+
+impl NonceWindow {
+    fn insert(&mut self, nonce: &str) -> bool {
+        if !self.seen.insert(nonce.to_string()) {
+            return false;
+        }
+        self.order.push_back(nonce.to_string());
+        if self.order.len() > NONCE_MEMORY {
+            if let Some(old) = self.order.pop_front() {
+                self.seen.remove(&old);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nonces_stay_refused_across_reconnects() {
+        let link = WsLink::new();
+        assert!(link.first_seen("n-1"));
+
+        let (tx, _rx) = mpsc::unbounded_channel();
+        link.attach(tx);
+        link.detach();
+        let (tx, _rx) = mpsc::unbounded_channel();
+        link.attach(tx);
+
+        assert!(!link.first_seen("n-1"));
+        assert!(link.first_seen("n-2"));
+    }
+
+    #[test]
+    fn window_forgets_the_oldest_nonces() {
+        let mut window = NonceWindow::default();
+        for i in 0..=NONCE_MEMORY {
+            assert!(window.insert(&i.to_string()));
+        }
+        assert!(window.insert("0"));
+        assert!(!window.insert(&NONCE_MEMORY.to_string()));
+    }
+}
@@ -6,6 +6,8 @@ use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
+use crate::comms::protocol::ResourceLimits;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentConfig {
     pub server_url: String,
@@ -20,23 +22,118 @@ pub struct AgentConfig {
     #[serde(default = "default_metrics_interval")]
     pub metrics_interval_sec: u64,
 
+    /// Cadence of the local high-resolution samples behind report
+    /// aggregates and metrics_history. 0 disables sampling.
+    #[serde(default = "default_metrics_sample_interval")]
+    pub metrics_sample_interval_sec: u64,
+
+    /// How much sample history the agent keeps in memory.
+    #[serde(default = "default_metrics_history_minutes")]
+    pub metrics_history_minutes: u64,
+
+    /// Processes listed by CPU and by memory in metrics reports.
+    /// 0 leaves them out.
+    #[serde(default = "default_metrics_top_processes")]
+    pub metrics_top_processes: usize,
+
+    /// Include the top processes in one report out of this many.
+    #[serde(default = "default_metrics_top_processes_every")]
+    pub metrics_top_processes_every: u32,
+
     #[serde(default = "default_inventory_interval")]
     pub inventory_interval_sec: u64,
 
     #[serde(default = "default_job_poll_interval")]
     pub job_poll_interval_sec: u64,
 
+    /// Use the persistent WebSocket channel when the server offers it
+    /// (HTTP polling remains the fallback).
+    #[serde(default = "default_websocket_enabled")]
+    pub websocket_enabled: bool,
+
     #[serde(default)]
     pub capabilities: Vec<String>,
 
+    /// Jobs allowed to run at once unless the server policy sets it.
+    #[serde(default = "default_max_concurrent_jobs")]
+    pub max_concurrent_jobs: usize,
+
+    /// Job types this agent refuses whatever the server policy says.
+    /// A trailing `*` matches a prefix (`remote_desktop_*`).
+    #[serde(default)]
+    pub denied_job_types: Vec<String>,
+
+    /// Local accounts run_script may switch to with `run_as`. Empty
+    /// refuses every run_as request.
+    #[serde(default)]
+    pub run_as_allowed_users: Vec<String>,
+
+    /// Base64 Ed25519 public keys run_script signatures are checked
+    /// against.
+    #[serde(default)]
+    pub script_signing_keys: Vec<String>,
+
+    /// Refuse run_script jobs not signed by one of script_signing_keys.
+    #[serde(default)]
+    pub require_signed_scripts: bool,
+
+    /// Ceilings for run_script resource limits. A job asking for
+    /// more, or for nothing, gets these.
+    #[serde(default)]
+    pub job_limits: ResourceLimits,
+
+    /// Disk budget for reports queued while the server is unreachable.
+    #[serde(default = "default_outbox_max_bytes")]
+    pub outbox_max_bytes: u64,
+
+    /// Queued reports older than this are discarded instead of replayed.
+    #[serde(default = "default_outbox_max_age")]
+    pub outbox_max_age_sec: u64,
+
+    /// Base64 Ed25519 public key that agent_update binaries must be signed with.
+    #[serde(default)]
+    pub update_public_key: Option<String>,
+
+    /// A new version must complete a heartbeat within this window or it is rolled back.
+    #[serde(default = "default_update_health_deadline")]
+    pub update_health_deadline_sec: u64,
+
+    /// systemd unit the agent runs as (restarted after an update).
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+
+    /// Remote shell sessions without input or output for this long are closed.
+    #[serde(default = "default_shell_idle_timeout")]
+    pub shell_idle_timeout_sec: u64,
+
+    /// Address of the built-in OpenMetrics exporter, for example
+    /// "127.0.0.1:9275". Unset disables it.
+    #[serde(default)]
+    pub exporter_listen: Option<String>,
+
+    /// When set, scrapes must send `Authorization: Bearer <token>`.
+    #[serde(default)]
+    pub exporter_token: Option<String>,
+
     #[serde(default = "default_log_level")]
     pub log_level: String,
 }
 
 fn default_heartbeat_interval() -> u64 { 10 }
 fn default_metrics_interval() -> u64 { 15 }
+fn default_metrics_sample_interval() -> u64 { 1 }
+fn default_metrics_history_minutes() -> u64 { 30 }
+fn default_metrics_top_processes() -> usize { 5 }
+fn default_metrics_top_processes_every() -> u32 { 1 }
 fn default_inventory_interval() -> u64 { 300 }
 fn default_job_poll_interval() -> u64 { 3 }
+fn default_max_concurrent_jobs() -> usize { 5 }
+fn default_outbox_max_bytes() -> u64 { 64 * 1024 * 1024 }
+fn default_outbox_max_age() -> u64 { 7 * 24 * 3600 }
+fn default_websocket_enabled() -> bool { true }
+fn default_update_health_deadline() -> u64 { 120 }
+fn default_service_name() -> String { "massvision-agent".to_string() }
+fn default_shell_idle_timeout() -> u64 { 900 }
 fn default_log_level() -> String { "info".to_string() }
 
 impl AgentConfig {
@@ -59,8 +156,15 @@ impl AgentConfig {
                 organization_id: None,
                 heartbeat_interval_sec: default_heartbeat_interval(),
                 metrics_interval_sec: default_metrics_interval(),
+                metrics_sample_interval_sec: default_metrics_sample_interval(),
+                metrics_history_minutes: default_metrics_history_minutes(),
+                metrics_top_processes: default_metrics_top_processes(),
+                metrics_top_processes_every: default_metrics_top_processes_every(),
                 inventory_interval_sec: default_inventory_interval(),
                 job_poll_interval_sec: default_job_poll_interval(),
+                websocket_enabled: default_websocket_enabled(),
+                outbox_max_bytes: default_outbox_max_bytes(),
+                outbox_max_age_sec: default_outbox_max_age(),
                 capabilities: vec![
                     "run_script".to_string(),
                     "remote_shell".to_string(),
@@ -70,7 +174,22 @@ impl AgentConfig {
                     "process_management".to_string(),
                     "inventory".to_string(),
                     "metrics".to_string(),
+                    "agent_update".to_string(),
+                    "artifact_transfer".to_string(),
+                    "wake_on_lan".to_string(),
                 ],
+                max_concurrent_jobs: default_max_concurrent_jobs(),
+                denied_job_types: Vec::new(),
+                run_as_allowed_users: Vec::new(),
+                script_signing_keys: Vec::new(),
+                require_signed_scripts: false,
+                job_limits: ResourceLimits::default(),
+                update_public_key: None,
+                update_health_deadline_sec: default_update_health_deadline(),
+                service_name: default_service_name(),
+                shell_idle_timeout_sec: default_shell_idle_timeout(),
+                exporter_listen: None,
+                exporter_token: None,
                 log_level: default_log_level(),
             };
 
@@ -101,6 +220,19 @@ impl AgentConfig {
         self.agent_id.is_some() && self.agent_secret.is_some()
     }
 
+    /// Directory for agent state (outbox, staged files...).
+    pub fn data_dir() -> PathBuf {
+        #[cfg(target_os = "windows")]
+        {
+            PathBuf::from(r"C:\ProgramData\MASSVISION\Reap3r\data")
+        }
+
+        #[cfg(not(target_os = "windows"))]
+        {
+            PathBuf::from("/var/lib/massvision/reap3r")
+        }
+    }
+
     fn config_path() -> Result<PathBuf> {
         #[cfg(target_os = "windows")]
         {
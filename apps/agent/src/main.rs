@@ -7,7 +7,17 @@
 //   - Heartbeat task (every 10s)
 //   - Metrics task (every 15s)
 //   - Inventory task (every 5min)
-//   - Job poll task (every 3s)
+//   - Job poll task (every 3s; jobs run concurrently up to
+//     max_concurrent_jobs)
+//   - Outbox drain (replays reports queued while offline)
+//   - Remote shell input poll (while sessions are open)
+//   - WebSocket channel (optional; jobs and shell events are
+//     pushed and the two polls above pause while it is up)
+//   - Pending self-update confirmation (after the first heartbeat)
+//   - Server policy (enrollment, heartbeat responses and `policy`
+//     frames) checked before every job; local deny-list wins
+//   - OpenMetrics exporter (optional; serves the latest metrics
+//     and agent health for Prometheus)
 //
 // All communication uses Protocol V2 signed envelopes
 // (HMAC-SHA256 + nonce + timestamp anti-replay)
@@ -19,15 +29,23 @@ mod modules;
 
 use anyhow::{Context, Result};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{mpsc, RwLock};
 use tokio::time::{sleep, Duration};
 
 use config::AgentConfig;
 use comms::client::AgentClient;
+use comms::outbox::Outbox;
 use comms::protocol::*;
+use comms::ws;
+use modules::alerts::AlertEngine;
+use modules::exporter::{self, AgentHealth};
+use modules::history::MetricsHistory;
 use modules::metrics::MetricsCollector;
 use modules::inventory::InventoryCollector;
-use modules::runner::JobRunner;
+use modules::policy::PolicyStore;
+use modules::runner::{JobContext, JobExecutor};
+use modules::shell::ShellManager;
+use modules::updater;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -42,6 +60,13 @@ async fn main() -> Result<()> {
         .with_target(false)
         .init();
 
+    // Rollback watchdog started by a previous agent_update job
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(pos) = args.iter().position(|a| a == "--update-guard") {
+        let state_file = args.get(pos + 1).context("--update-guard needs a state file")?;
+        return updater::run_guard(std::path::Path::new(state_file));
+    }
+
     tracing::info!("╔══════════════════════════════════════════╗");
     tracing::info!("║  MASSVISION Reap3r Agent v{}          ║", VERSION);
     tracing::info!("╚══════════════════════════════════════════╝");
@@ -56,9 +81,10 @@ async fn main() -> Result<()> {
     let client = Arc::new(RwLock::new(AgentClient::new(&config.server_url)));
 
     // Enroll if needed
+    let mut enrollment_policy = None;
     if !config.is_enrolled() {
         tracing::info!("Agent not enrolled. Starting enrollment...");
-        enroll(&mut config, &client).await?;
+        enrollment_policy = enroll(&mut config, &client).await?;
     } else {
         tracing::info!("Agent already enrolled: {}", config.agent_id.as_ref().unwrap());
         let mut c = client.write().await;
@@ -69,28 +95,110 @@ async fn main() -> Result<()> {
     }
 
     let config = Arc::new(config);
+    let policy = Arc::new(PolicyStore::load(&config));
+    if let Some(p) = enrollment_policy {
+        policy.apply(p);
+    }
+    let shells = Arc::new(ShellManager::new(
+        Arc::clone(&client),
+        config.shell_idle_timeout_sec,
+    ));
+    let outbox = Arc::new(Outbox::open(
+        AgentConfig::data_dir().join("outbox"),
+        config.outbox_max_bytes,
+        config.outbox_max_age_sec,
+    )?);
+    let history = Arc::new(MetricsHistory::new(&config));
+    let health = Arc::new(AgentHealth::new());
+    let jobs = Arc::new(JobExecutor::new(
+        Arc::new(JobContext {
+            client: Arc::clone(&client),
+            config: Arc::clone(&config),
+            shells: Arc::clone(&shells),
+            policy: Arc::clone(&policy),
+            history: Arc::clone(&history),
+        }),
+    ));
+    tokio::spawn(Arc::clone(&jobs).track_policy());
+
+    // Confirm (or report the rollback of) an update started before the restart
+    {
+        let client = Arc::clone(&client);
+        let outbox = Arc::clone(&outbox);
+        let capabilities = config.capabilities.clone();
+        tokio::spawn(async move {
+            updater::finish_pending(&client, &outbox, capabilities).await;
+        });
+    }
 
     // Spawn background tasks
     let heartbeat_handle = tokio::spawn(heartbeat_loop(
         Arc::clone(&client),
         Arc::clone(&config),
+        Arc::clone(&jobs),
+        Arc::clone(&policy),
+        Arc::clone(&health),
+        Arc::clone(&outbox),
     ));
 
+    tokio::spawn(Arc::clone(&history).sample_loop());
     let metrics_handle = tokio::spawn(metrics_loop(
         Arc::clone(&client),
+        Arc::clone(&policy),
         Arc::clone(&config),
+        Arc::clone(&history),
+        Arc::clone(&health),
+        Arc::clone(&outbox),
     ));
 
     let inventory_handle = tokio::spawn(inventory_loop(
         Arc::clone(&client),
-        Arc::clone(&config),
+        Arc::clone(&policy),
+        Arc::clone(&outbox),
     ));
 
     let job_handle = tokio::spawn(job_poll_loop(
         Arc::clone(&client),
         Arc::clone(&config),
+        Arc::clone(&jobs),
+        Arc::clone(&outbox),
+    ));
+
+    let outbox_handle = tokio::spawn(outbox_loop(
+        Arc::clone(&client),
+        Arc::clone(&outbox),
     ));
 
+    let shell_handle = tokio::spawn(Arc::clone(&shells).input_loop());
+
+    // A port clash must not take the agent down with it
+    {
+        let (config, health, outbox) = (Arc::clone(&config), Arc::clone(&health), Arc::clone(&outbox));
+        tokio::spawn(async move {
+            if let Err(e) = exporter::serve(config, health, outbox).await {
+                tracing::error!("Metrics exporter disabled: {:#}", e);
+            }
+        });
+    }
+
+    if config.websocket_enabled {
+        let (push_tx, push_rx) = mpsc::channel(64);
+        let hello = WsHelloPayload {
+            agent_version: VERSION.to_string(),
+            capabilities: config.capabilities.clone(),
+        };
+        tokio::spawn(ws::run(Arc::clone(&client), hello, push_tx));
+        tokio::spawn(push_loop(
+            Arc::clone(&client),
+            Arc::clone(&jobs),
+            Arc::clone(&shells),
+            Arc::clone(&policy),
+            Arc::clone(&health),
+            Arc::clone(&outbox),
+            push_rx,
+        ));
+    }
+
     tracing::info!("All background tasks started. Agent is operational.");
 
     // Wait for Ctrl+C or task failure
@@ -110,8 +218,18 @@ async fn main() -> Result<()> {
         r = job_handle => {
             tracing::error!("Job poll task exited: {:?}", r);
         }
+        r = shell_handle => {
+            tracing::error!("Shell input task exited: {:?}", r);
+        }
+        r = outbox_handle => {
+            tracing::error!("Outbox task exited: {:?}", r);
+        }
     }
 
+    // Close remote shells so their process groups do not outlive the agent
+    shells.stop_all("agent shutting down").await;
+    sleep(Duration::from_millis(500)).await;
+
     tracing::info!("Agent shutting down gracefully");
     Ok(())
 }
@@ -120,7 +238,8 @@ async fn main() -> Result<()> {
 // Enrollment
 // ═══════════════════════════════════════════════════════════════
 
-async fn enroll(config: &mut AgentConfig, client: &Arc<RwLock<AgentClient>>) -> Result<()> {
+/// Returns the policy handed out with the credentials.
+async fn enroll(config: &mut AgentConfig, client: &Arc<RwLock<AgentClient>>) -> Result<Option<AgentPolicy>> {
     let enrollment_token = config.enrollment_token.as_ref()
         .context("No enrollment token configured")?
         .clone();
@@ -169,15 +288,15 @@ async fn enroll(config: &mut AgentConfig, client: &Arc<RwLock<AgentClient>>) ->
     c.set_credentials(resp.agent_id, resp.agent_secret);
 
     tracing::info!("Enrollment complete!");
-    Ok(())
+    Ok(resp.policy)
 }
 
 fn get_mac_addresses() -> Vec<String> {
     let networks = sysinfo::Networks::new_with_refreshed_list();
     networks
         .list()
-        .iter()
-        .map(|(_name, data)| data.mac_address().to_string())
+        .values()
+        .map(|data| data.mac_address().to_string())
         .filter(|mac| mac != "00:00:00:00:00:00")
         .collect()
 }
@@ -186,7 +305,14 @@ fn get_mac_addresses() -> Vec<String> {
 // Heartbeat Loop
 // ═══════════════════════════════════════════════════════════════
 
-async fn heartbeat_loop(client: Arc<RwLock<AgentClient>>, config: Arc<AgentConfig>) {
+async fn heartbeat_loop(
+    client: Arc<RwLock<AgentClient>>,
+    config: Arc<AgentConfig>,
+    jobs: Arc<JobExecutor>,
+    policy: Arc<PolicyStore>,
+    health: Arc<AgentHealth>,
+    outbox: Arc<Outbox>,
+) {
     let mut consecutive_failures = 0u32;
 
     loop {
@@ -194,31 +320,41 @@ async fn heartbeat_loop(client: Arc<RwLock<AgentClient>>, config: Arc<AgentConfi
             status: "online".to_string(),
             uptime_sec: sysinfo::System::uptime(),
             agent_version: VERSION.to_string(),
-            active_jobs: vec![],
+            active_jobs: jobs.active_jobs(),
             capabilities: config.capabilities.clone(),
         };
 
         let c = client.read().await;
         match c.heartbeat(payload).await {
-            Ok(resp) => {
+            // Queued on the socket; counted once the server acks it
+            Ok(None) => {
+                consecutive_failures = 0;
+                tracing::debug!("Heartbeat sent over WebSocket");
+            }
+            Ok(Some(resp)) => {
                 consecutive_failures = 0;
+                health.heartbeat_succeeded();
                 tracing::debug!("Heartbeat OK (ack={})", resp.ack);
 
+                if let Some(p) = resp.policy {
+                    policy.apply(p);
+                }
+
+                for job_id in &resp.cancel_jobs {
+                    if !jobs.cancel(job_id) {
+                        tracing::debug!("Cancel for unknown job {}", job_id);
+                    }
+                }
+
                 // If the server pushes a pending job, execute it
                 if let Some(job) = resp.pending_job {
                     tracing::info!("Server pushed job via heartbeat: {} (type={})", job.job_id, job.job_type);
-                    let job_client = Arc::clone(&client);
-                    tokio::spawn(async move {
-                        let result = JobRunner::execute(&job).await;
-                        let c = job_client.read().await;
-                        if let Err(e) = c.report_job_result(result).await {
-                            tracing::error!("Failed to report pushed job result: {}", e);
-                        }
-                    });
+                    spawn_job(Arc::clone(&client), Arc::clone(&jobs), Arc::clone(&outbox), job);
                 }
             }
             Err(e) => {
                 consecutive_failures += 1;
+                health.heartbeat_failed();
                 tracing::warn!("Heartbeat failed (attempt {}): {}", consecutive_failures, e);
             }
         }
@@ -231,18 +367,49 @@ async fn heartbeat_loop(client: Arc<RwLock<AgentClient>>, config: Arc<AgentConfi
 // Metrics Loop
 // ═══════════════════════════════════════════════════════════════
 
-async fn metrics_loop(client: Arc<RwLock<AgentClient>>, config: Arc<AgentConfig>) {
+async fn metrics_loop(
+    client: Arc<RwLock<AgentClient>>,
+    policy: Arc<PolicyStore>,
+    config: Arc<AgentConfig>,
+    history: Arc<MetricsHistory>,
+    health: Arc<AgentHealth>,
+    outbox: Arc<Outbox>,
+) {
     let mut collector = MetricsCollector::new();
+    let mut alerts = AlertEngine::default();
+    let mut last_report = chrono::Utc::now().timestamp_millis();
+    let mut reports = 0u32;
 
     // Wait a bit before first collection
     sleep(Duration::from_secs(5)).await;
 
     loop {
         match collector.collect() {
-            Ok(payload) => {
+            Ok(mut payload) => {
+                payload.aggregates = history.aggregate_since(last_report);
+                last_report = payload.timestamp;
+                if config.metrics_top_processes > 0 && reports.is_multiple_of(config.metrics_top_processes_every.max(1)) {
+                    payload.top_processes = Some(collector.top_processes(config.metrics_top_processes));
+                }
+                reports = reports.wrapping_add(1);
+                health.set_metrics(&payload);
                 let c = client.read().await;
-                if let Err(e) = c.report_metrics(payload).await {
-                    tracing::warn!("Metrics report failed: {}", e);
+
+                // Alerts go out first: they are what someone is waiting on
+                for alert in alerts.evaluate(&policy.alert_rules(), &payload) {
+                    if let Err(e) = c.report_alert(&alert).await {
+                        tracing::warn!("Alert report failed, queued for retry: {}", e);
+                        if let Err(e) = outbox.push("alert", &alert) {
+                            tracing::error!("Failed to queue alert: {}", e);
+                        }
+                    }
+                }
+
+                if let Err(e) = c.report_metrics(payload.clone()).await {
+                    tracing::warn!("Metrics report failed, queued for retry: {}", e);
+                    if let Err(e) = outbox.push("metrics", &payload) {
+                        tracing::error!("Failed to queue metrics: {}", e);
+                    }
                 } else {
                     tracing::debug!("Metrics reported");
                 }
@@ -252,7 +419,7 @@ async fn metrics_loop(client: Arc<RwLock<AgentClient>>, config: Arc<AgentConfig>
             }
         }
 
-        sleep(Duration::from_secs(config.metrics_interval_sec)).await;
+        policy.sleep(policy.metrics_interval()).await;
     }
 }
 
@@ -260,7 +427,11 @@ async fn metrics_loop(client: Arc<RwLock<AgentClient>>, config: Arc<AgentConfig>
 // Inventory Loop
 // ═══════════════════════════════════════════════════════════════
 
-async fn inventory_loop(client: Arc<RwLock<AgentClient>>, config: Arc<AgentConfig>) {
+async fn inventory_loop(
+    client: Arc<RwLock<AgentClient>>,
+    policy: Arc<PolicyStore>,
+    outbox: Arc<Outbox>,
+) {
     // Report inventory immediately on startup
     sleep(Duration::from_secs(10)).await;
 
@@ -268,8 +439,11 @@ async fn inventory_loop(client: Arc<RwLock<AgentClient>>, config: Arc<AgentConfi
         match InventoryCollector::collect() {
             Ok(payload) => {
                 let c = client.read().await;
-                if let Err(e) = c.report_inventory(payload).await {
-                    tracing::warn!("Inventory report failed: {}", e);
+                if let Err(e) = c.report_inventory(payload.clone()).await {
+                    tracing::warn!("Inventory report failed, queued for retry: {}", e);
+                    if let Err(e) = outbox.push("inventory", &payload) {
+                        tracing::error!("Failed to queue inventory: {}", e);
+                    }
                 } else {
                     tracing::info!("Inventory snapshot reported");
                 }
@@ -279,7 +453,7 @@ async fn inventory_loop(client: Arc<RwLock<AgentClient>>, config: Arc<AgentConfi
             }
         }
 
-        sleep(Duration::from_secs(config.inventory_interval_sec)).await;
+        policy.sleep(policy.inventory_interval()).await;
     }
 }
 
@@ -287,23 +461,35 @@ async fn inventory_loop(client: Arc<RwLock<AgentClient>>, config: Arc<AgentConfi
 // Job Poll Loop
 // ═══════════════════════════════════════════════════════════════
 
-async fn job_poll_loop(client: Arc<RwLock<AgentClient>>, config: Arc<AgentConfig>) {
+async fn job_poll_loop(
+    client: Arc<RwLock<AgentClient>>,
+    config: Arc<AgentConfig>,
+    jobs: Arc<JobExecutor>,
+    outbox: Arc<Outbox>,
+) {
     sleep(Duration::from_secs(3)).await;
 
     loop {
         let c = client.read().await;
+
+        // Jobs arrive over the WebSocket while it is connected, and
+        // there is no point pulling work while every slot is taken
+        if c.ws_connected() || !jobs.has_capacity() {
+            drop(c);
+            sleep(Duration::from_secs(config.job_poll_interval_sec)).await;
+            continue;
+        }
+
         match c.poll_jobs().await {
             Ok(Some(job)) => {
                 tracing::info!("Received job: {} (type={})", job.job_id, job.job_type);
                 drop(c); // Release read lock
 
-                // Execute job
-                let result = JobRunner::execute(&job).await;
+                spawn_job(Arc::clone(&client), Arc::clone(&jobs), Arc::clone(&outbox), job);
 
-                // Report result
-                let c = client.read().await;
-                if let Err(e) = c.report_job_result(result).await {
-                    tracing::error!("Failed to report job result: {}", e);
+                // Keep pulling while the server has work and we have slots
+                if jobs.has_capacity() {
+                    continue;
                 }
             }
             Ok(None) => {
@@ -317,3 +503,96 @@ async fn job_poll_loop(client: Arc<RwLock<AgentClient>>, config: Arc<AgentConfig
         sleep(Duration::from_secs(config.job_poll_interval_sec)).await;
     }
 }
+
+// ═══════════════════════════════════════════════════════════════
+// WebSocket Push Loop
+// ═══════════════════════════════════════════════════════════════
+
+async fn push_loop(
+    client: Arc<RwLock<AgentClient>>,
+    jobs: Arc<JobExecutor>,
+    shells: Arc<ShellManager>,
+    policy: Arc<PolicyStore>,
+    health: Arc<AgentHealth>,
+    outbox: Arc<Outbox>,
+    mut inbound: mpsc::Receiver<AgentEnvelope>,
+) {
+    while let Some(envelope) = inbound.recv().await {
+        match envelope.msg_type.as_str() {
+            "heartbeat_ack" => health.heartbeat_succeeded(),
+            "job" => match serde_json::from_value::<JobRequest>(envelope.payload) {
+                Ok(job) => {
+                    tracing::info!("Server pushed job via WebSocket: {} (type={})", job.job_id, job.job_type);
+                    spawn_job(Arc::clone(&client), Arc::clone(&jobs), Arc::clone(&outbox), job);
+                }
+                Err(e) => tracing::warn!("Invalid pushed job: {}", e),
+            },
+            "job_cancel" => match serde_json::from_value::<JobCancelPayload>(envelope.payload) {
+                Ok(cancel) => {
+                    if !jobs.cancel(&cancel.job_id) {
+                        tracing::debug!("Cancel for unknown job {}", cancel.job_id);
+                    }
+                }
+                Err(e) => tracing::warn!("Invalid job cancel: {}", e),
+            },
+            "shell_event" => match serde_json::from_value::<ShellEvent>(envelope.payload) {
+                Ok(event) => shells.dispatch(event).await,
+                Err(e) => tracing::warn!("Invalid shell event: {}", e),
+            },
+            "policy" => match serde_json::from_value::<AgentPolicy>(envelope.payload) {
+                Ok(p) => {
+                    policy.apply(p);
+                }
+                Err(e) => tracing::warn!("Invalid policy: {}", e),
+            },
+            other => tracing::debug!("Ignoring server frame type: {}", other),
+        }
+    }
+}
+
+/// Run a job in the background and report its result.
+fn spawn_job(
+    client: Arc<RwLock<AgentClient>>,
+    jobs: Arc<JobExecutor>,
+    outbox: Arc<Outbox>,
+    job: JobRequest,
+) {
+    if !jobs.accept(&job) {
+        return;
+    }
+    tokio::spawn(async move {
+        let result = jobs.run(&job).await;
+        report_job_result(&client, &outbox, result).await;
+    });
+}
+
+/// Report a job result, queueing it in the outbox if delivery fails.
+/// The job has already run, so its result must never be dropped.
+async fn report_job_result(client: &Arc<RwLock<AgentClient>>, outbox: &Outbox, result: JobResult) {
+    let c = client.read().await;
+    if let Err(e) = c.report_job_result(result.clone()).await {
+        tracing::error!("Failed to report job result {}, queued for retry: {}", result.job_id, e);
+        if let Err(e) = outbox.push("job_result", &result) {
+            tracing::error!("Failed to queue job result {}: {}", result.job_id, e);
+        }
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Outbox Drain Loop
+// ═══════════════════════════════════════════════════════════════
+
+async fn outbox_loop(client: Arc<RwLock<AgentClient>>, outbox: Arc<Outbox>) {
+    loop {
+        if !outbox.is_empty() {
+            let c = client.read().await;
+            match outbox.drain(&c).await {
+                Ok(n) if n > 0 => tracing::info!("Outbox drained: {} report(s) delivered", n),
+                Ok(_) => {}
+                Err(e) => tracing::debug!("Outbox drain paused: {}", e),
+            }
+        }
+
+        sleep(Duration::from_secs(15)).await;
+    }
+}
@@ -0,0 +1,338 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r Agent - Local Alert Rules
+// ─────────────────────────────────────────────────────────────
+//
+// The policy's `alert_rules` are checked against every metrics
+// sample, so a full disk or a pegged CPU is reported the moment the
+// rule fires instead of when the server next looks. A rule fires
+// once its condition has held for `for_samples` samples and
+// `for_sec` seconds, and resolves only after the value has been
+// back past `clear_threshold` for the same window; both keep an
+// alert from flapping around the threshold. Per-instance metrics
+// (disks, interfaces, sensors, containers) are tracked per instance.
+// A firing instance that stops being reported (an unmounted disk, a
+// failed sensor read) resolves only once it has been missing for the
+// same window, so a single bad sample does not clear it.
+// ─────────────────────────────────────────────────────────────
+
+use std::collections::{HashMap, HashSet};
+
+use crate::comms::protocol::{
+    AlertOperator, AlertPayload, AlertRule, AlertState, ContainerMetrics, DiskMetrics, MetricsPayload,
+    NetworkMetrics,
+};
+
+/// (rule id, instance)
+type Key = (String, Option<String>);
+
+#[derive(Default)]
+struct RuleState {
+    /// The rule as it was when the alert fired; None while not firing.
+    firing: Option<AlertRule>,
+    /// First sample and count of the current streak towards firing
+    /// (or, while firing, towards resolving).
+    streak: Option<(i64, u32)>,
+    /// Same, for samples in which a firing instance was missing.
+    absent: Option<(i64, u32)>,
+    started_at: i64,
+}
+
+#[derive(Default)]
+pub struct AlertEngine {
+    states: HashMap<Key, RuleState>,
+    /// Rules already reported as unusable, so the log is not flooded.
+    warned: HashSet<String>,
+}
+
+impl AlertEngine {
+    /// Evaluate `rules` against one sample; returns the alerts that
+    /// fired or resolved.
+    pub fn evaluate(&mut self, rules: &[AlertRule], metrics: &MetricsPayload) -> Vec<AlertPayload> {
+        let now = metrics.timestamp;
+        let mut alerts = Vec::new();
+        let mut seen: HashSet<Key> = HashSet::new();
+
+        for rule in rules {
+            let Some(values) = metric_values(&rule.metric, metrics) else {
+                if self.warned.insert(rule.id.clone()) {
+                    tracing::warn!("Alert rule {} uses unknown metric {:?}; ignored", rule.id, rule.metric);
+                }
+                continue;
+            };
+
+            for (instance, value) in values {
+                if rule.instance.as_ref().is_some_and(|wanted| instance.as_ref() != Some(wanted)) {
+                    continue;
+                }
+                let key = (rule.id.clone(), instance.clone());
+                let state = self.states.entry(key.clone()).or_default();
+                state.absent = None;
+                seen.insert(key);
+
+                if let Some(alert) = step(rule, state, instance, value, now) {
+                    alerts.push(alert);
+                }
+            }
+        }
+
+        // A rule removed from the policy resolves now; a disk or
+        // interface that went away resolves once it has been missing
+        // for the rule's window. Neither may leave an alert firing.
+        let unseen: Vec<Key> = self.states.keys().filter(|k| !seen.contains(*k)).cloned().collect();
+        for key in unseen {
+            let Some(state) = self.states.get_mut(&key) else { continue };
+            if state.firing.is_none() {
+                self.states.remove(&key);
+                continue;
+            }
+            if let Some(rule) = rules.iter().find(|r| r.id == key.0) {
+                let (since, count) = state.absent.get_or_insert((now, 0));
+                *count += 1;
+                if !held(rule, *since, *count, now) {
+                    continue;
+                }
+            }
+            let Some(state) = self.states.remove(&key) else { continue };
+            let Some(rule) = state.firing else { continue };
+            tracing::info!("Alert {} resolved: {:?} no longer reported", rule.id, key.1);
+            alerts.push(alert(&rule, AlertState::Resolved, key.1, None, state.started_at, now));
+        }
+        self.warned.retain(|id| rules.iter().any(|r| &r.id == id));
+
+        alerts
+    }
+}
+
+/// Advance one rule instance by one sample.
+fn step(rule: &AlertRule, state: &mut RuleState, instance: Option<String>, value: f64, now: i64) -> Option<AlertPayload> {
+    // Not firing: count samples that breach the threshold. Firing:
+    // count samples back past the clear threshold.
+    let counts = if state.firing.is_some() {
+        !compare(rule.operator, value, rule.clear_threshold.unwrap_or(rule.threshold))
+    } else {
+        compare(rule.operator, value, rule.threshold)
+    };
+    if !counts {
+        state.streak = None;
+        return None;
+    }
+
+    let (since, count) = state.streak.get_or_insert((now, 0));
+    *count += 1;
+    if !held(rule, *since, *count, now) {
+        return None;
+    }
+
+    let since = *since;
+    state.streak = None;
+    if state.firing.take().is_none() {
+        state.firing = Some(rule.clone());
+        state.started_at = since;
+        tracing::warn!("Alert {} firing: {} = {} ({:?})", rule.id, rule.metric, value, instance);
+        Some(alert(rule, AlertState::Firing, instance, Some(value), since, now))
+    } else {
+        tracing::info!("Alert {} resolved: {} = {} ({:?})", rule.id, rule.metric, value, instance);
+        Some(alert(rule, AlertState::Resolved, instance, Some(value), state.started_at, now))
+    }
+}
+
+/// Whether a streak of `count` samples starting at `since` (ms) has
+/// lasted the rule's `for_samples` and `for_sec`.
+fn held(rule: &AlertRule, since: i64, count: u32, now: i64) -> bool {
+    let for_ms = i64::try_from(rule.for_sec.unwrap_or(0).saturating_mul(1000)).unwrap_or(i64::MAX);
+    count >= rule.for_samples.unwrap_or(1).max(1) && now.saturating_sub(since) >= for_ms
+}
+
+fn compare(operator: AlertOperator, value: f64, threshold: f64) -> bool {
+    match operator {
+        AlertOperator::Above => value > threshold,
+        AlertOperator::AtLeast => value >= threshold,
+        AlertOperator::Below => value < threshold,
+        AlertOperator::AtMost => value <= threshold,
+    }
+}
+
+fn alert(
+    rule: &AlertRule,
+    state: AlertState,
+    instance: Option<String>,
+    value: Option<f64>,
+    started_at: i64,
+    now: i64,
+) -> AlertPayload {
+    AlertPayload {
+        rule_id: rule.id.clone(),
+        rule_name: rule.name.clone(),
+        state,
+        severity: rule.severity,
+        metric: rule.metric.clone(),
+        instance,
+        value,
+        operator: rule.operator,
+        threshold: rule.threshold,
+        started_at,
+        timestamp: now,
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Metric Catalog
+// ═══════════════════════════════════════════════════════════════
+
+/// Current values of `metric`, per instance. None for an unknown
+/// metric; empty when it is known but not reported on this host.
+fn metric_values(metric: &str, m: &MetricsPayload) -> Option<Vec<(Option<String>, f64)>> {
+    let single = |value: Option<f64>| Some(value.map(|v| (None, v)).into_iter().collect());
+    let percent = |part: u64, total: u64| (total > 0).then(|| part as f64 * 100.0 / total as f64);
+    let disks = |f: fn(&DiskMetrics) -> Option<f64>| {
+        Some(m.disks.iter().filter_map(|d| Some((Some(d.mount_point.clone()), f(d)?))).collect())
+    };
+    let interfaces = |f: fn(&NetworkMetrics) -> f64| {
+        Some(m.network.iter().map(|n| (Some(n.interface_name.clone()), f(n))).collect())
+    };
+    let containers = |f: fn(&ContainerMetrics) -> Option<f64>| {
+        Some(m.containers.iter().filter_map(|c| Some((Some(c.name.clone()), f(c)?))).collect())
+    };
+
+    match metric {
+        "cpu.usage_percent" => single(Some(m.cpu.usage_percent)),
+        "cpu.iowait_percent" => single(m.cpu.iowait_percent),
+        "cpu.steal_percent" => single(m.cpu.steal_percent),
+        "memory.used_percent" => single(percent(m.memory.used_bytes, m.memory.total_bytes)),
+        "memory.available_bytes" => single(Some(m.memory.available_bytes as f64)),
+        "swap.used_percent" => single(percent(m.memory.swap_used_bytes, m.memory.swap_total_bytes)),
+        "disk.used_percent" => disks(|d| (d.total_bytes > 0).then(|| d.used_bytes as f64 * 100.0 / d.total_bytes as f64)),
+        "disk.available_percent" => disks(|d| (d.total_bytes > 0).then(|| d.available_bytes as f64 * 100.0 / d.total_bytes as f64)),
+        "disk.available_bytes" => disks(|d| Some(d.available_bytes as f64)),
+        "disk.read_bytes_sec" => disks(|d| Some(d.read_bytes_sec as f64)),
+        "disk.write_bytes_sec" => disks(|d| Some(d.write_bytes_sec as f64)),
+        "network.rx_bytes_sec" => interfaces(|n| n.rx_bytes_sec as f64),
+        "network.tx_bytes_sec" => interfaces(|n| n.tx_bytes_sec as f64),
+        "network.rx_packets_sec" => interfaces(|n| n.rx_packets_sec as f64),
+        "network.tx_packets_sec" => interfaces(|n| n.tx_packets_sec as f64),
+        "load.1" => single(m.load_average.map(|l| l[0])),
+        "load.5" => single(m.load_average.map(|l| l[1])),
+        "load.15" => single(m.load_average.map(|l| l[2])),
+        "temperature.celsius" => Some(m.temperatures.iter().map(|t| (Some(t.label.clone()), t.celsius)).collect()),
+        "file_descriptors.used_percent" => single(m.file_descriptors.as_ref().and_then(|f| percent(f.open, f.max))),
+        "processes.count" => single(Some(m.processes_count as f64)),
+        "context_switches_sec" => single(m.context_switches_sec.map(|v| v as f64)),
+        "container.cpu_percent" => containers(|c| Some(c.cpu_percent)),
+        "container.memory_bytes" => containers(|c| Some(c.memory_bytes as f64)),
+        "container.memory_limit_percent" => containers(|c| {
+            c.memory_limit_bytes.filter(|l| *l > 0).map(|l| c.memory_bytes as f64 * 100.0 / l as f64)
+        }),
+        _ => pressure_value(metric, m),
+    }
+}
+
+/// `pressure.<cpu|memory|io>.<some|full>.<avg10|avg60|avg300>`
+fn pressure_value(metric: &str, m: &MetricsPayload) -> Option<Vec<(Option<String>, f64)>> {
+    let mut parts = metric.strip_prefix("pressure.")?.split('.');
+    let (resource, kind, window) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some()
+        || !matches!(resource, "cpu" | "memory" | "io")
+        || !matches!(kind, "some" | "full")
+        || !matches!(window, "avg10" | "avg60" | "avg300")
+    {
+        return None;
+    }
+
+    let stall = m.pressure.as_ref().and_then(|p| match resource {
+        "cpu" => p.cpu.as_ref(),
+        "memory" => p.memory.as_ref(),
+        _ => p.io.as_ref(),
+    });
+    let line = stall.and_then(|s| if kind == "some" { s.some.as_ref() } else { s.full.as_ref() });
+    let value = line.map(|l| match window {
+        "avg10" => l.avg10,
+        "avg60" => l.avg60,
+        _ => l.avg300,
+    });
+    Some(value.map(|v| (None, v)).into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(for_samples: u32) -> AlertRule {
+        serde_json::from_value(serde_json::json!({
+            "id": "hot",
+            "metric": "temperature.celsius",
+            "operator": ">",
+            "threshold": 80.0,
+            "clear_threshold": 70.0,
+            "for_samples": for_samples,
+        }))
+        .unwrap()
+    }
+
+    /// One sample at `second` with the given sensors.
+    fn sample(second: i64, sensors: &[(&str, f64)]) -> MetricsPayload {
+        serde_json::from_value(serde_json::json!({
+            "timestamp": second * 1000,
+            "cpu": { "usage_percent": 0.0, "cores": 1, "model": "", "frequency_mhz": 0, "per_core_usage": [] },
+            "memory": { "total_bytes": 0, "used_bytes": 0, "available_bytes": 0, "swap_total_bytes": 0, "swap_used_bytes": 0 },
+            "disks": [],
+            "network": [],
+            "processes_count": 0,
+            "uptime_sec": 0,
+            "temperatures": sensors.iter()
+                .map(|(label, celsius)| serde_json::json!({ "label": label, "celsius": celsius }))
+                .collect::<Vec<_>>(),
+        }))
+        .unwrap()
+    }
+
+    fn states(alerts: &[AlertPayload]) -> Vec<AlertState> {
+        alerts.iter().map(|a| a.state).collect()
+    }
+
+    #[test]
+    fn fires_and_resolves_only_after_the_window() {
+        let (mut engine, rules) = (AlertEngine::default(), vec![rule(2)]);
+        assert!(engine.evaluate(&rules, &sample(0, &[("cpu", 85.0)])).is_empty());
+        assert_eq!(states(&engine.evaluate(&rules, &sample(1, &[("cpu", 90.0)]))), [AlertState::Firing]);
+
+        // Below the threshold but not past the clear threshold
+        assert!(engine.evaluate(&rules, &sample(2, &[("cpu", 75.0)])).is_empty());
+        assert!(engine.evaluate(&rules, &sample(3, &[("cpu", 75.0)])).is_empty());
+
+        // A single sample past it is not enough
+        assert!(engine.evaluate(&rules, &sample(4, &[("cpu", 60.0)])).is_empty());
+        assert!(engine.evaluate(&rules, &sample(5, &[("cpu", 85.0)])).is_empty());
+        assert!(engine.evaluate(&rules, &sample(6, &[("cpu", 60.0)])).is_empty());
+        assert_eq!(states(&engine.evaluate(&rules, &sample(7, &[("cpu", 60.0)]))), [AlertState::Resolved]);
+    }
+
+    #[test]
+    fn missing_instance_resolves_only_after_the_window() {
+        let (mut engine, rules) = (AlertEngine::default(), vec![rule(2)]);
+        engine.evaluate(&rules, &sample(0, &[("cpu", 85.0)]));
+        assert_eq!(states(&engine.evaluate(&rules, &sample(1, &[("cpu", 85.0)]))), [AlertState::Firing]);
+
+        // One failed read, then the sensor is back: still firing
+        assert!(engine.evaluate(&rules, &sample(2, &[])).is_empty());
+        assert!(engine.evaluate(&rules, &sample(3, &[("cpu", 85.0)])).is_empty());
+
+        assert!(engine.evaluate(&rules, &sample(4, &[])).is_empty());
+        let resolved = engine.evaluate(&rules, &sample(5, &[]));
+        assert_eq!(states(&resolved), [AlertState::Resolved]);
+        assert_eq!(resolved[0].value, None);
+    }
+
+    #[test]
+    fn removed_rule_resolves_at_once() {
+        let mut engine = AlertEngine::default();
+        assert_eq!(states(&engine.evaluate(&[rule(1)], &sample(0, &[("cpu", 85.0)]))), [AlertState::Firing]);
+        assert_eq!(states(&engine.evaluate(&[], &sample(1, &[("cpu", 85.0)]))), [AlertState::Resolved]);
+    }
+
+    #[test]
+    fn huge_for_sec_never_overflows() {
+        let mut rule = rule(1);
+        rule.for_sec = Some(u64::MAX);
+        assert!(!held(&rule, 0, 1, 3_600_000));
+    }
+}
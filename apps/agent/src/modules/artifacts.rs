@@ -0,0 +1,535 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r Agent - Artifact Transfer
+// ─────────────────────────────────────────────────────────────
+//
+// artifact_upload: package the source (directories as tar, gzip
+// when `compress` is set), hash it, then upload it in resumable
+// chunks: init → chunk* → complete. Every call is a signed
+// envelope. After a failure the agent re-opens the session and
+// continues from the offset the server reports.
+//
+// artifact_download: stream a URL into a `.part` file beside the
+// destination, resuming it with HTTP range requests, and move it
+// into place only once its SHA-256 matches. A replaced file keeps
+// its owner and mode.
+// ─────────────────────────────────────────────────────────────
+
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::time::{sleep, Duration};
+
+use crate::comms::protocol::{
+    ArtifactChunk, ArtifactDownloadPayload, ArtifactUploadInit, ArtifactUploadPayload, JobArtifact,
+    JobRequest, JobResult,
+};
+use crate::config::AgentConfig;
+use crate::modules::runner::JobContext;
+
+/// Transfer attempts before the job fails; progress is kept between them.
+const MAX_ATTEMPTS: u32 = 6;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Never trust the server to pick an absurd chunk size.
+const MAX_CHUNK_BYTES: usize = 4 * 1024 * 1024;
+
+// ═══════════════════════════════════════════════════════════════
+// Job Handler
+// ═══════════════════════════════════════════════════════════════
+
+pub async fn artifact_upload(job: &JobRequest, payload: &ArtifactUploadPayload, ctx: &JobContext) -> Result<JobResult> {
+    let source_path = payload.source_path.as_str();
+    let compress = payload.compress;
+
+    let source = PathBuf::from(source_path);
+    let metadata = tokio::fs::metadata(&source).await
+        .with_context(|| format!("Cannot read {}", source_path))?;
+    let is_dir = metadata.is_dir();
+
+    let requested_name = payload.filename.as_deref()
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| {
+            source.file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| "artifact".to_string())
+        });
+    let filename = artifact_filename(&requested_name, is_dir, compress);
+
+    // ── Package + hash ──────────────────────────────────────
+    let staging = AgentConfig::data_dir().join("artifacts").join(format!("{}.upload", safe_name(&job.job_id)));
+    let needs_staging = is_dir || compress;
+    let package = {
+        let source = source.clone();
+        let staging = staging.clone();
+        tokio::task::spawn_blocking(move || {
+            if needs_staging {
+                build_package(&source, &staging, is_dir, compress)?;
+                hash_file(&staging)
+            } else {
+                hash_file(&source)
+            }
+        })
+        .await?
+    };
+    let package = match package {
+        Ok(package) => package,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&staging).await;
+            return Err(e);
+        }
+    };
+    let upload_path = if needs_staging { staging.clone() } else { source.clone() };
+
+    tracing::info!(
+        "Uploading artifact {} ({} bytes, sha256 {})",
+        filename, package.size_bytes, package.sha256,
+    );
+
+    let init = ArtifactUploadInit {
+        job_id: job.job_id.clone(),
+        filename: filename.clone(),
+        mime_type: mime_type(&filename).to_string(),
+        size_bytes: package.size_bytes,
+        sha256: package.sha256.clone(),
+    };
+    let uploaded = upload_with_retry(ctx, &init, &upload_path).await;
+
+    if needs_staging {
+        let _ = tokio::fs::remove_file(&staging).await;
+    }
+    let artifact = uploaded?;
+
+    let packaged_as = match (is_dir, compress) {
+        (true, true) => "tar.gz",
+        (true, false) => "tar",
+        (false, true) => "gzip",
+        (false, false) => "file",
+    };
+
+    Ok(JobResult {
+        job_id: job.job_id.clone(),
+        status: "success".to_string(),
+        started_at: 0,
+        completed_at: 0,
+        stdout: None,
+        stderr: None,
+        exit_code: None,
+        error_message: None,
+        result_data: Some(serde_json::json!({
+            "source_path": source_path,
+            "packaged_as": packaged_as,
+        })),
+        artifacts: vec![artifact],
+    })
+}
+
+pub async fn artifact_download(
+    job: &JobRequest,
+    payload: &ArtifactDownloadPayload,
+    _ctx: &JobContext,
+) -> Result<JobResult> {
+    let url = payload.url.as_str();
+    let destination_path = payload.destination_path.as_str();
+    let expected = payload.sha256.to_ascii_lowercase();
+    let overwrite = payload.overwrite;
+
+    if expected.len() != 64 || !expected.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("sha256 must be 64 hex characters");
+    }
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        bail!("Unsupported download URL: {}", url);
+    }
+
+    let destination = PathBuf::from(destination_path);
+    let file_name = destination.file_name()
+        .context("destination_path has no file name")?
+        .to_string_lossy()
+        .to_string();
+    let parent = destination.parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .to_path_buf();
+
+    // ── Existing destination ────────────────────────────────
+    let existing = match tokio::fs::metadata(&destination).await {
+        Ok(meta) => Some(meta),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e).with_context(|| format!("Cannot read {}", destination_path)),
+    };
+    if let Some(meta) = &existing {
+        if meta.is_dir() {
+            bail!("{} is a directory", destination_path);
+        }
+        // A re-delivered job whose file is already in place succeeds
+        let path = destination.clone();
+        let current = tokio::task::spawn_blocking(move || hash_file(&path)).await??;
+        if current.sha256 == expected {
+            return Ok(download_result(job, destination_path, &current, 0, false));
+        }
+        if !overwrite {
+            bail!("{} already exists and overwrite is not set", destination_path);
+        }
+    }
+
+    tokio::fs::create_dir_all(&parent).await
+        .with_context(|| format!("Failed to create {:?}", parent))?;
+
+    // Keyed by hash so a different file never resumes this one
+    let part = parent.join(format!(".{}.{}.part", file_name, &expected[..16]));
+    let resumed_from = tokio::fs::metadata(&part).await.map(|m| m.len()).unwrap_or(0);
+
+    tracing::info!("Downloading {} to {} (resuming at {} bytes)", url, destination_path, resumed_from);
+    let package = download_with_retry(url, &part, &expected).await?;
+
+    // ── Move into place ─────────────────────────────────────
+    if let Some(meta) = &existing {
+        preserve_attributes(meta, &part);
+    } else if tokio::fs::try_exists(&destination).await.unwrap_or(false) {
+        bail!("{} appeared during the download and overwrite is not set", destination_path);
+    }
+    tokio::fs::rename(&part, &destination).await
+        .with_context(|| format!("Failed to move download into {}", destination_path))?;
+    sync_dir(&parent);
+
+    Ok(download_result(job, destination_path, &package, resumed_from, true))
+}
+
+fn download_result(
+    job: &JobRequest,
+    destination_path: &str,
+    package: &PackageInfo,
+    resumed_from: u64,
+    changed: bool,
+) -> JobResult {
+    JobResult {
+        job_id: job.job_id.clone(),
+        status: "success".to_string(),
+        started_at: 0,
+        completed_at: 0,
+        stdout: None,
+        stderr: None,
+        exit_code: None,
+        error_message: None,
+        artifacts: Vec::new(),
+        result_data: Some(serde_json::json!({
+            "destination_path": destination_path,
+            "size_bytes": package.size_bytes,
+            "sha256": package.sha256,
+            "resumed_from": resumed_from,
+            "changed": changed,
+        })),
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Upload
+// ═══════════════════════════════════════════════════════════════
+
+async fn upload_with_retry(ctx: &JobContext, init: &ArtifactUploadInit, path: &Path) -> Result<JobArtifact> {
+    let mut attempt = 1;
+    loop {
+        match upload_once(ctx, init, path).await {
+            Ok(artifact) => return Ok(artifact),
+            Err(e) if attempt < MAX_ATTEMPTS => {
+                let delay = Duration::from_secs(2u64.pow(attempt).min(30));
+                tracing::warn!(
+                    "Artifact upload attempt {} failed, resuming in {}s: {:#}",
+                    attempt, delay.as_secs(), e,
+                );
+                sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e.context(format!("Artifact upload failed after {} attempts", attempt))),
+        }
+    }
+}
+
+async fn upload_once(ctx: &JobContext, init: &ArtifactUploadInit, path: &Path) -> Result<JobArtifact> {
+    let session = ctx.client.read().await.artifact_init(init).await?;
+    if session.complete {
+        return ctx.client.read().await.artifact_complete(&session.artifact_id).await;
+    }
+
+    let chunk_size = session.chunk_size.clamp(1, MAX_CHUNK_BYTES);
+    let mut offset = session.received_bytes;
+    if offset > init.size_bytes {
+        bail!("Server reports {} bytes received of {}", offset, init.size_bytes);
+    }
+
+    let mut file = tokio::fs::File::open(path).await
+        .with_context(|| format!("Cannot open {:?}", path))?;
+    file.seek(std::io::SeekFrom::Start(offset)).await?;
+    let mut buf = vec![0u8; chunk_size];
+
+    while offset < init.size_bytes {
+        let want = chunk_size.min((init.size_bytes - offset) as usize);
+        file.read_exact(&mut buf[..want]).await
+            .context("Source changed while uploading")?;
+
+        let chunk = ArtifactChunk {
+            artifact_id: session.artifact_id.clone(),
+            offset,
+            data: base64::engine::general_purpose::STANDARD.encode(&buf[..want]),
+        };
+        offset = ctx.client.read().await.artifact_chunk(&chunk).await?;
+    }
+
+    ctx.client.read().await.artifact_complete(&session.artifact_id).await
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Download
+// ═══════════════════════════════════════════════════════════════
+
+async fn download_with_retry(url: &str, part: &Path, expected: &str) -> Result<PackageInfo> {
+    let http = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(10))
+        .read_timeout(Duration::from_secs(60))
+        .user_agent(format!("MASSVISION-Agent/{}", VERSION))
+        .build()?;
+
+    let mut attempt = 1;
+    loop {
+        let outcome = match download_once(&http, url, part).await {
+            Ok(0) => match verify_part(part, expected).await {
+                // A complete fresh copy that does not match will not start matching
+                Err(e) => return Err(e),
+                ok => ok,
+            },
+            Ok(_) => verify_part(part, expected).await,
+            Err(e) => Err(e),
+        };
+        match outcome {
+            Ok(package) => return Ok(package),
+            Err(e) if attempt < MAX_ATTEMPTS => {
+                let delay = Duration::from_secs(2u64.pow(attempt).min(30));
+                tracing::warn!(
+                    "Artifact download attempt {} failed, resuming in {}s: {:#}",
+                    attempt, delay.as_secs(), e,
+                );
+                sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e.context(format!("Artifact download failed after {} attempts", attempt))),
+        }
+    }
+}
+
+/// Fetch whatever the part file is missing and return the offset the
+/// transfer started at. A server that ignores the range answers 200
+/// and the file starts over.
+async fn download_once(http: &reqwest::Client, url: &str, part: &Path) -> Result<u64> {
+    let offset = tokio::fs::metadata(part).await.map(|m| m.len()).unwrap_or(0);
+
+    let mut request = http.get(url);
+    if offset > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+    }
+    let mut response = request.send().await.context("Download request failed")?;
+
+    let status = response.status();
+    let append = match status {
+        reqwest::StatusCode::PARTIAL_CONTENT if offset > 0 => {
+            let start = response.headers()
+                .get(reqwest::header::CONTENT_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(content_range_start);
+            if start != Some(offset) {
+                bail!("Server resumed at {:?} instead of byte {}", start, offset);
+            }
+            true
+        }
+        // Nothing left to fetch; the hash check decides
+        reqwest::StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => return Ok(offset),
+        s if s.is_success() => false,
+        s => bail!("Download failed (HTTP {})", s),
+    };
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(part)
+        .await
+        .with_context(|| format!("Failed to open {:?}", part))?;
+    while let Some(chunk) = response.chunk().await.context("Download interrupted")? {
+        file.write_all(&chunk).await?;
+    }
+    file.sync_all().await?;
+    Ok(if append { offset } else { 0 })
+}
+
+async fn verify_part(part: &Path, expected: &str) -> Result<PackageInfo> {
+    let path = part.to_path_buf();
+    let package = tokio::task::spawn_blocking(move || hash_file(&path)).await??;
+    if package.sha256 != expected {
+        // Corrupt data must not be resumed; the next attempt starts over
+        let _ = tokio::fs::remove_file(part).await;
+        bail!("SHA-256 mismatch: expected {}, got {}", expected, package.sha256);
+    }
+    Ok(package)
+}
+
+/// `bytes 100-199/200` → 100
+fn content_range_start(value: &str) -> Option<u64> {
+    value.strip_prefix("bytes ")?.split('-').next()?.trim().parse().ok()
+}
+
+/// Give the replacement the owner and mode of the file it replaces.
+/// Ownership is set first: chown clears setuid/setgid bits.
+#[cfg(unix)]
+fn preserve_attributes(existing: &std::fs::Metadata, part: &Path) {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    if let Err(e) = std::os::unix::fs::chown(part, Some(existing.uid()), Some(existing.gid())) {
+        tracing::warn!("Cannot keep owner {}:{} on {:?}: {}", existing.uid(), existing.gid(), part, e);
+    }
+    let mode = std::fs::Permissions::from_mode(existing.mode() & 0o7777);
+    if let Err(e) = std::fs::set_permissions(part, mode) {
+        tracing::warn!("Cannot keep mode {:o} on {:?}: {}", existing.mode() & 0o7777, part, e);
+    }
+}
+
+#[cfg(not(unix))]
+fn preserve_attributes(existing: &std::fs::Metadata, part: &Path) {
+    if let Err(e) = std::fs::set_permissions(part, existing.permissions()) {
+        tracing::warn!("Cannot keep permissions on {:?}: {}", part, e);
+    }
+}
+
+/// Make the rename itself durable.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) {
+    if let Ok(dir) = std::fs::File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) {}
+
+// ═══════════════════════════════════════════════════════════════
+// Packaging
+// ═══════════════════════════════════════════════════════════════
+
+struct PackageInfo {
+    size_bytes: u64,
+    sha256: String,
+}
+
+fn build_package(source: &Path, staging: &Path, is_dir: bool, compress: bool) -> Result<()> {
+    if let Some(parent) = staging.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {:?}", parent))?;
+    }
+    let file = std::fs::File::create(staging)
+        .with_context(|| format!("Failed to create {:?}", staging))?;
+    let out = if compress {
+        PackageWriter::Gzip(flate2::write::GzEncoder::new(file, flate2::Compression::default()))
+    } else {
+        PackageWriter::Plain(file)
+    };
+
+    if is_dir {
+        let root = source.file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "artifact".to_string());
+        let mut archive = tar::Builder::new(out);
+        // Archive symlinks as links rather than following them out of the tree
+        archive.follow_symlinks(false);
+        archive.append_dir_all(&root, source)
+            .with_context(|| format!("Failed to archive {:?}", source))?;
+        archive.into_inner()?.finish()?;
+    } else {
+        let mut out = out;
+        let mut input = std::fs::File::open(source)
+            .with_context(|| format!("Failed to open {:?}", source))?;
+        std::io::copy(&mut input, &mut out)?;
+        out.finish()?;
+    }
+    Ok(())
+}
+
+enum PackageWriter {
+    Plain(std::fs::File),
+    Gzip(flate2::write::GzEncoder<std::fs::File>),
+}
+
+impl PackageWriter {
+    /// Write the gzip trailer (if any) and flush to disk.
+    fn finish(self) -> Result<()> {
+        let file = match self {
+            PackageWriter::Plain(file) => file,
+            PackageWriter::Gzip(encoder) => encoder.finish()?,
+        };
+        file.sync_all()?;
+        Ok(())
+    }
+}
+
+impl Write for PackageWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            PackageWriter::Plain(file) => file.write(buf),
+            PackageWriter::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            PackageWriter::Plain(file) => file.flush(),
+            PackageWriter::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+fn hash_file(path: &Path) -> Result<PackageInfo> {
+    let mut file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open {:?}", path))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut size_bytes = 0u64;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        size_bytes += n as u64;
+    }
+    Ok(PackageInfo { size_bytes, sha256: hex::encode(hasher.finalize()) })
+}
+
+fn artifact_filename(name: &str, is_dir: bool, compress: bool) -> String {
+    let lower = name.to_ascii_lowercase();
+    match (is_dir, compress) {
+        (true, true) if !lower.ends_with(".tar.gz") && !lower.ends_with(".tgz") => format!("{}.tar.gz", name),
+        (true, false) if !lower.ends_with(".tar") => format!("{}.tar", name),
+        (false, true) if !lower.ends_with(".gz") => format!("{}.gz", name),
+        _ => name.to_string(),
+    }
+}
+
+fn mime_type(filename: &str) -> &'static str {
+    let lower = filename.to_ascii_lowercase();
+    match lower.rsplit('.').next().unwrap_or("") {
+        "gz" | "tgz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "zip" => "application/zip",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "txt" | "log" | "csv" => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Job IDs come from the server; keep them from naming other paths.
+fn safe_name(id: &str) -> String {
+    id.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '-').collect()
+}
@@ -0,0 +1,402 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r Agent - Container Discovery (cgroup v2)
+// ─────────────────────────────────────────────────────────────
+//
+// Running containers are found through the cgroup of every
+// process (/proc/<pid>/cgroup): Docker, Podman, containerd
+// (nerdctl, Kubernetes CRI) and CRI-O each put a container in a
+// cgroup whose name carries its 64-hex ID, for example
+// system.slice/docker-<id>.scope or kubepods/burstable/pod<uid>/<id>.
+// CPU, memory, I/O and PID usage are read from that cgroup under
+// /sys/fs/cgroup; names come from the runtime's own metadata files.
+// Only the unified (v2) hierarchy is supported; on cgroup v1 hosts
+// and off Linux no containers are reported.
+// ─────────────────────────────────────────────────────────────
+
+use std::collections::HashMap;
+
+use crate::comms::protocol::ContainerRuntime;
+
+/// Raw readings of one container's cgroup. Counters are cumulative.
+#[derive(Debug, Clone)]
+pub struct ContainerSample {
+    pub id: String,
+    pub name: String,
+    pub runtime: String,
+    pub cpu_usage_usec: u64,
+    pub cpu_limit_cores: Option<f64>,
+    /// Usage without reclaimable page cache, as `docker stats` shows it.
+    pub memory_bytes: u64,
+    pub memory_limit_bytes: Option<u64>,
+    pub io_read_bytes: u64,
+    pub io_write_bytes: u64,
+    pub pids: u64,
+}
+
+/// Keeps container names between samples; they only need looking
+/// up once per container.
+#[derive(Default)]
+pub struct ContainerCollector {
+    /// id -> (runtime, name)
+    known: HashMap<String, (String, String)>,
+}
+
+impl ContainerCollector {
+    pub fn sample(&mut self) -> Vec<ContainerSample> {
+        let samples = imp::sample(&mut self.known);
+        self.known.retain(|id, _| samples.iter().any(|s| &s.id == id));
+        samples
+    }
+}
+
+/// Runtimes installed on the host, with how many containers each
+/// is running.
+pub fn runtimes(containers: &[ContainerSample]) -> Vec<ContainerRuntime> {
+    imp::runtimes(containers)
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use super::*;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+    /// Transient units the runtimes create with the systemd cgroup driver.
+    const SCOPE_PREFIXES: &[(&str, &str)] = &[
+        ("docker-", "docker"),
+        ("libpod-", "podman"),
+        ("cri-containerd-", "containerd"),
+        ("nerdctl-", "containerd"),
+        ("crio-", "cri-o"),
+    ];
+
+    pub fn sample(known: &mut HashMap<String, (String, String)>) -> Vec<ContainerSample> {
+        let root = Path::new(CGROUP_ROOT);
+        if !root.join("cgroup.controllers").exists() {
+            return Vec::new();
+        }
+
+        let mut found: HashMap<String, (Option<&'static str>, PathBuf)> = HashMap::new();
+        for (id, runtime, cgroup) in process_cgroups().iter().filter_map(|path| container_of(path)) {
+            found.entry(id).or_insert((runtime, cgroup));
+        }
+
+        let mut samples: Vec<ContainerSample> = found.into_iter().filter_map(|(id, (runtime, cgroup))| {
+            let (runtime, name) = match known.get(&id) {
+                Some(entry) => entry.clone(),
+                None => {
+                    let (runtime, name) = lookup(&id, runtime, &cgroup);
+                    // Cache only real names: metadata may not be written yet
+                    match name {
+                        Some(name) => {
+                            known.insert(id.clone(), (runtime.clone(), name.clone()));
+                            (runtime, name)
+                        }
+                        None => (runtime, id[..12].to_string()),
+                    }
+                }
+            };
+            read_cgroup(&root.join(&cgroup), id, name, runtime)
+        }).collect();
+        samples.sort_by(|a, b| a.name.cmp(&b.name));
+        samples
+    }
+
+    /// The unified-hierarchy cgroup of every process.
+    fn process_cgroups() -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir("/proc") else {
+            return Vec::new();
+        };
+        let mut paths: Vec<String> = entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().bytes().all(|b| b.is_ascii_digit()))
+            .filter_map(|e| std::fs::read_to_string(e.path().join("cgroup")).ok())
+            .filter_map(|content| {
+                // v2 has a single line: 0::/system.slice/docker-<id>.scope
+                content.lines().find_map(|line| line.strip_prefix("0::").map(str::to_string))
+            })
+            .collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    /// Container ID, runtime (when the cgroup name tells) and the
+    /// container's own cgroup, relative to the root. The outermost
+    /// match wins so processes in sub-cgroups count for the container.
+    pub(super) fn container_of(path: &str) -> Option<(String, Option<&'static str>, PathBuf)> {
+        let mut cgroup = PathBuf::new();
+        let mut parent = "";
+        for component in path.trim_start_matches('/').split('/') {
+            cgroup.push(component);
+            let scope = component.strip_suffix(".scope").and_then(|unit| {
+                SCOPE_PREFIXES.iter().find_map(|(prefix, runtime)| {
+                    unit.strip_prefix(prefix).filter(|id| is_container_id(id)).map(|id| (id, Some(*runtime)))
+                })
+            });
+            // cgroupfs driver: the bare ID under docker/, kubepods/...
+            let bare = is_container_id(component).then(|| {
+                (component, (parent == "docker").then_some("docker"))
+            });
+            if let Some((id, runtime)) = scope.or(bare) {
+                return Some((id.to_string(), runtime, cgroup));
+            }
+            parent = component;
+        }
+        None
+    }
+
+    fn is_container_id(s: &str) -> bool {
+        s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit() && !b.is_ascii_uppercase())
+    }
+
+    fn read_cgroup(dir: &Path, id: String, name: String, runtime: String) -> Option<ContainerSample> {
+        let read = |file: &str| std::fs::read_to_string(dir.join(file)).ok();
+        let number = |file: &str| read(file).and_then(|v| v.trim().parse::<u64>().ok());
+        let keyed = |file: &str| -> HashMap<String, u64> {
+            read(file).unwrap_or_default().lines()
+                .filter_map(|line| line.split_once(' '))
+                .filter_map(|(key, value)| Some((key.to_string(), value.trim().parse().ok()?)))
+                .collect()
+        };
+
+        // The container exited between discovery and now
+        let cpu = keyed("cpu.stat");
+        let cpu_usage_usec = *cpu.get("usage_usec")?;
+
+        // "max 100000" or "<quota> <period>"
+        let cpu_limit_cores = read("cpu.max").and_then(|v| {
+            let mut fields = v.split_whitespace();
+            let quota = fields.next()?.parse::<f64>().ok()?;
+            let period = fields.next()?.parse::<f64>().ok()?;
+            (period > 0.0).then(|| (quota / period * 100.0).round() / 100.0)
+        });
+
+        let inactive_file = keyed("memory.stat").get("inactive_file").copied().unwrap_or(0);
+        let memory_bytes = number("memory.current").unwrap_or(0).saturating_sub(inactive_file);
+        // "max" does not parse, which is what no limit should give
+        let memory_limit_bytes = number("memory.max");
+
+        // 8:0 rbytes=1459200 wbytes=314773504 rios=192 wios=353 dbytes=0 dios=0
+        let (mut io_read_bytes, mut io_write_bytes) = (0, 0);
+        for line in read("io.stat").unwrap_or_default().lines() {
+            for field in line.split_whitespace().skip(1) {
+                match field.split_once('=') {
+                    Some(("rbytes", v)) => io_read_bytes += v.parse::<u64>().unwrap_or(0),
+                    Some(("wbytes", v)) => io_write_bytes += v.parse::<u64>().unwrap_or(0),
+                    _ => {}
+                }
+            }
+        }
+
+        Some(ContainerSample {
+            id,
+            name,
+            runtime,
+            cpu_usage_usec,
+            cpu_limit_cores,
+            memory_bytes,
+            memory_limit_bytes,
+            io_read_bytes,
+            io_write_bytes,
+            pids: number("pids.current").unwrap_or(0),
+        })
+    }
+
+    // ═══════════════════════════════════════════════════════════
+    // Names
+    // ═══════════════════════════════════════════════════════════
+
+    /// Runtime and name from the runtime's metadata, trying each
+    /// runtime in turn when the cgroup name did not tell.
+    fn lookup(id: &str, runtime: Option<&'static str>, cgroup: &Path) -> (String, Option<String>) {
+        type Lookup = fn(&str, &Path) -> Option<String>;
+        let lookups: [(&str, Lookup); 4] = [
+            ("docker", docker_name),
+            ("podman", podman_name),
+            ("containerd", containerd_name),
+            ("cri-o", crio_name),
+        ];
+        for (candidate, lookup) in lookups {
+            if runtime.is_some_and(|r| r != candidate) {
+                continue;
+            }
+            if let Some(name) = lookup(id, cgroup) {
+                return (candidate.to_string(), Some(name));
+            }
+        }
+        (runtime.unwrap_or("unknown").to_string(), None)
+    }
+
+    fn read_json(path: impl AsRef<Path>) -> Option<serde_json::Value> {
+        serde_json::from_slice(&std::fs::read(path).ok()?).ok()
+    }
+
+    fn docker_name(id: &str, _cgroup: &Path) -> Option<String> {
+        let config = read_json(format!("/var/lib/docker/containers/{}/config.v2.json", id))?;
+        Some(config["Name"].as_str()?.trim_start_matches('/').to_string())
+    }
+
+    /// containers.json of root's storage or, for rootless containers
+    /// (cgroup under user@<uid>.service), of the owner's.
+    fn podman_name(id: &str, cgroup: &Path) -> Option<String> {
+        let mut stores = vec![PathBuf::from("/var/lib/containers/storage")];
+        let uid = cgroup.iter()
+            .filter_map(|c| c.to_str()?.strip_prefix("user@")?.strip_suffix(".service")?.parse::<u32>().ok())
+            .next();
+        if let Some(home) = uid.and_then(home_of) {
+            stores.push(home.join(".local/share/containers/storage"));
+        }
+        stores.iter().find_map(|store| {
+            let containers = read_json(store.join("overlay-containers/containers.json"))?;
+            let container = containers.as_array()?.iter().find(|c| c["id"] == id)?;
+            Some(container["names"].get(0)?.as_str()?.to_string())
+        })
+    }
+
+    /// From the OCI bundle: Kubernetes pods show as
+    /// `<namespace>/<pod>/<container>`, nerdctl by its name.
+    fn containerd_name(id: &str, _cgroup: &Path) -> Option<String> {
+        let namespaces = std::fs::read_dir("/run/containerd/io.containerd.runtime.v2.task").ok()?;
+        namespaces.filter_map(|ns| ns.ok()).find_map(|ns| {
+            let config = read_json(ns.path().join(id).join("config.json"))?;
+            let annotations = &config["annotations"];
+            let get = |key: &str| annotations[key].as_str();
+            match (get("io.kubernetes.cri.sandbox-namespace"), get("io.kubernetes.cri.sandbox-name"), get("io.kubernetes.cri.container-name")) {
+                (Some(namespace), Some(pod), Some(container)) => Some(format!("{}/{}/{}", namespace, pod, container)),
+                // The pause container of a pod
+                (Some(namespace), Some(pod), None) => Some(format!("{}/{}", namespace, pod)),
+                _ => get("nerdctl/name").map(str::to_string),
+            }
+        })
+    }
+
+    fn crio_name(id: &str, _cgroup: &Path) -> Option<String> {
+        let config = read_json(format!("/run/containers/storage/overlay-containers/{}/userdata/config.json", id))?;
+        let annotations = &config["annotations"];
+        let get = |key: &str| annotations[key].as_str();
+        let (namespace, pod) = (get("io.kubernetes.pod.namespace")?, get("io.kubernetes.pod.name")?);
+        Some(match get("io.kubernetes.container.name") {
+            Some(container) => format!("{}/{}/{}", namespace, pod, container),
+            None => format!("{}/{}", namespace, pod),
+        })
+    }
+
+    fn home_of(uid: u32) -> Option<PathBuf> {
+        let passwd = std::fs::read_to_string("/etc/passwd").ok()?;
+        passwd.lines().find_map(|line| {
+            // name:x:uid:gid:gecos:home:shell
+            let fields: Vec<&str> = line.split(':').collect();
+            match fields.as_slice() {
+                [_, _, id, _, _, home, ..] if id.parse() == Ok(uid) => Some(PathBuf::from(home)),
+                _ => None,
+            }
+        })
+    }
+
+    // ═══════════════════════════════════════════════════════════
+    // Runtimes
+    // ═══════════════════════════════════════════════════════════
+
+    /// (runtime, API socket, binary)
+    const RUNTIMES: &[(&str, &str, &str)] = &[
+        ("docker", "/run/docker.sock", "docker"),
+        ("podman", "/run/podman/podman.sock", "podman"),
+        ("containerd", "/run/containerd/containerd.sock", "containerd"),
+        ("cri-o", "/run/crio/crio.sock", "crio"),
+    ];
+
+    /// A runtime is listed when its socket exists or it runs one of
+    /// the discovered containers (rootless Podman has no system socket).
+    pub fn runtimes(containers: &[ContainerSample]) -> Vec<ContainerRuntime> {
+        RUNTIMES.iter().filter_map(|(name, socket, binary)| {
+            let running = containers.iter().filter(|c| c.runtime == *name).count();
+            let socket_present = Path::new(socket).exists();
+            if running == 0 && !socket_present {
+                return None;
+            }
+            Some(ContainerRuntime {
+                name: name.to_string(),
+                version: version_of(binary),
+                socket: socket_present.then(|| socket.to_string()),
+                running_containers: running,
+            })
+        }).collect()
+    }
+
+    /// First version-looking word of `<binary> --version`:
+    /// "Docker version 24.0.7, build afdd53b", "podman version 4.9.3",
+    /// "containerd github.com/containerd/containerd v1.7.13 7c3aca7"...
+    fn version_of(binary: &str) -> Option<String> {
+        let output = Command::new(binary).arg("--version").output().ok()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        stdout.split_whitespace()
+            .map(|word| word.trim_end_matches(',').trim_start_matches('v'))
+            .find(|word| word.starts_with(|c: char| c.is_ascii_digit()) && word.contains('.'))
+            .map(str::to_string)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use super::*;
+
+    pub fn sample(_known: &mut HashMap<String, (String, String)>) -> Vec<ContainerSample> {
+        Vec::new()
+    }
+
+    pub fn runtimes(_containers: &[ContainerSample]) -> Vec<ContainerRuntime> {
+        Vec::new()
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::imp::container_of;
+    use std::path::PathBuf;
+
+    const ID: &str = "4f9b1c0e8a7d6c5b4a39281706f5e4d3c2b1a0f9e8d7c6b5a4938271605f4e3d";
+
+    #[test]
+    fn docker_scope_and_cgroupfs_paths() {
+        let (id, runtime, cgroup) = container_of(&format!("/system.slice/docker-{}.scope", ID)).unwrap();
+        assert_eq!((id.as_str(), runtime), (ID, Some("docker")));
+        assert_eq!(cgroup, PathBuf::from(format!("system.slice/docker-{}.scope", ID)));
+
+        let (id, runtime, cgroup) = container_of(&format!("/docker/{}", ID)).unwrap();
+        assert_eq!((id.as_str(), runtime), (ID, Some("docker")));
+        assert_eq!(cgroup, PathBuf::from(format!("docker/{}", ID)));
+    }
+
+    #[test]
+    fn podman_scope_counts_its_sub_cgroups() {
+        let path = format!("/user.slice/user-1000.slice/user@1000.service/user.slice/libpod-{}.scope/container", ID);
+        let (id, runtime, cgroup) = container_of(&path).unwrap();
+        assert_eq!((id.as_str(), runtime), (ID, Some("podman")));
+        assert!(cgroup.ends_with(format!("libpod-{}.scope", ID)));
+    }
+
+    #[test]
+    fn kubepods_with_systemd_and_cgroupfs_drivers() {
+        let systemd = format!(
+            "/kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod1234.slice/cri-containerd-{}.scope",
+            ID,
+        );
+        let (id, runtime, _) = container_of(&systemd).unwrap();
+        assert_eq!((id.as_str(), runtime), (ID, Some("containerd")));
+
+        // A cgroupfs name does not tell the runtime; lookup() tries each
+        let (id, runtime, _) = container_of(&format!("/kubepods/burstable/pod1234/{}", ID)).unwrap();
+        assert_eq!((id.as_str(), runtime), (ID, None));
+    }
+
+    #[test]
+    fn ignores_non_container_cgroups() {
+        assert!(container_of("/system.slice/sshd.service").is_none());
+        assert!(container_of("/user.slice/user-1000.slice/session-3.scope").is_none());
+        assert!(container_of(&format!("/docker/{}", ID.to_uppercase())).is_none());
+        assert!(container_of("/docker/abc123").is_none());
+    }
+}
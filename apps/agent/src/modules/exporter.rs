@@ -0,0 +1,423 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r Agent - OpenMetrics Exporter
+// ─────────────────────────────────────────────────────────────
+//
+// With `exporter_listen` set, the agent serves its latest metrics
+// collection and its own health (heartbeats, failed reports,
+// outbox backlog) at GET /metrics in OpenMetrics text format, so
+// Prometheus can scrape hosts directly. Values are those of the
+// last metrics_loop collection; scraping never triggers one.
+//
+// The server is a deliberately small HTTP/1.1 responder: one
+// request per connection, no keep-alive, GET/HEAD only.
+// ─────────────────────────────────────────────────────────────
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use subtle::ConstantTimeEq;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{timeout, Duration};
+
+use crate::comms::outbox::Outbox;
+use crate::comms::protocol::{ContainerMetrics, DiskMetrics, MetricsPayload, NetworkMetrics, PressureStall};
+use crate::config::AgentConfig;
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+const MAX_REQUEST_BYTES: usize = 8192;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the agent knows about itself, updated by the main loops.
+pub struct AgentHealth {
+    started: Instant,
+    /// Unix milliseconds; 0 until the first successful heartbeat.
+    last_heartbeat: AtomicI64,
+    heartbeat_failures: AtomicU64,
+    consecutive_heartbeat_failures: AtomicU64,
+    latest_metrics: Mutex<Option<MetricsPayload>>,
+}
+
+impl AgentHealth {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            last_heartbeat: AtomicI64::new(0),
+            heartbeat_failures: AtomicU64::new(0),
+            consecutive_heartbeat_failures: AtomicU64::new(0),
+            latest_metrics: Mutex::new(None),
+        }
+    }
+
+    pub fn heartbeat_succeeded(&self) {
+        self.last_heartbeat.store(chrono::Utc::now().timestamp_millis(), Ordering::Relaxed);
+        self.consecutive_heartbeat_failures.store(0, Ordering::Relaxed);
+    }
+
+    pub fn heartbeat_failed(&self) {
+        self.heartbeat_failures.fetch_add(1, Ordering::Relaxed);
+        self.consecutive_heartbeat_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_metrics(&self, metrics: &MetricsPayload) {
+        *self.latest_metrics.lock().unwrap() = Some(metrics.clone());
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+// HTTP Server
+// ═══════════════════════════════════════════════════════════════
+
+struct Exporter {
+    token: Option<String>,
+    agent_id: String,
+    health: Arc<AgentHealth>,
+    outbox: Arc<Outbox>,
+}
+
+/// Bind `exporter_listen` and serve scrapes forever. Returns at once
+/// when the exporter is not configured.
+pub async fn serve(config: Arc<AgentConfig>, health: Arc<AgentHealth>, outbox: Arc<Outbox>) -> Result<()> {
+    let Some(listen) = config.exporter_listen.as_deref() else {
+        return Ok(());
+    };
+    let listener = TcpListener::bind(listen).await
+        .with_context(|| format!("Failed to bind metrics exporter to {}", listen))?;
+    let addr = listener.local_addr()?;
+    if !addr.ip().is_loopback() && config.exporter_token.is_none() {
+        tracing::warn!("Metrics exporter on {} is reachable from the network without exporter_token", addr);
+    }
+    tracing::info!("Metrics exporter listening on http://{}/metrics", addr);
+
+    let exporter = Arc::new(Exporter {
+        token: config.exporter_token.clone(),
+        agent_id: config.agent_id.clone().unwrap_or_default(),
+        health,
+        outbox,
+    });
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                tracing::warn!("Metrics exporter accept failed: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let exporter = Arc::clone(&exporter);
+        tokio::spawn(async move {
+            if let Err(e) = exporter.handle(stream).await {
+                tracing::debug!("Metrics exporter request from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+impl Exporter {
+    async fn handle(&self, mut stream: TcpStream) -> Result<()> {
+        let head = timeout(REQUEST_TIMEOUT, read_head(&mut stream)).await
+            .context("Request timed out")??;
+
+        let mut lines = head.lines();
+        let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+        let (method, target) = (request_line.next().unwrap_or_default(), request_line.next().unwrap_or_default());
+        let path = target.split('?').next().unwrap_or_default();
+        let authorization = lines
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("authorization"))
+            .map(|(_, value)| value.trim());
+
+        let (status, content_type, body) = if method != "GET" && method != "HEAD" {
+            ("405 Method Not Allowed", "text/plain", "Method not allowed\n".to_string())
+        } else if path != "/metrics" {
+            ("404 Not Found", "text/plain", "Not found; metrics are at /metrics\n".to_string())
+        } else if self.token.as_deref().is_some_and(|token| !bearer_matches(authorization, token)) {
+            ("401 Unauthorized", "text/plain", "Unauthorized\n".to_string())
+        } else {
+            ("200 OK", CONTENT_TYPE, self.render())
+        };
+
+        let mut response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            status, content_type, body.len(),
+        );
+        if status.starts_with("401") {
+            response.push_str("WWW-Authenticate: Bearer\r\n");
+        }
+        response.push_str("\r\n");
+        if method != "HEAD" {
+            response.push_str(&body);
+        }
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await?;
+        Ok(())
+    }
+
+    fn render(&self) -> String {
+        let mut out = OpenMetrics::default();
+        self.render_health(&mut out);
+        if let Some(metrics) = self.health.latest_metrics.lock().unwrap().as_ref() {
+            render_host(&mut out, metrics);
+        }
+        out.finish()
+    }
+
+    fn render_health(&self, out: &mut OpenMetrics) {
+        let health = &self.health;
+
+        out.family("reap3r_agent", "info", "Agent build and identity");
+        out.sample("reap3r_agent_info", &[("version", env!("CARGO_PKG_VERSION")), ("agent_id", &self.agent_id)], 1.0);
+
+        out.gauge("reap3r_agent_uptime_seconds", "Time since the agent process started", health.started.elapsed().as_secs_f64());
+
+        let last_heartbeat = health.last_heartbeat.load(Ordering::Relaxed);
+        out.family("reap3r_agent_last_heartbeat_timestamp_seconds", "gauge", "Time of the last successful heartbeat");
+        if last_heartbeat > 0 {
+            out.sample("reap3r_agent_last_heartbeat_timestamp_seconds", &[], last_heartbeat as f64 / 1000.0);
+        }
+
+        out.counter("reap3r_agent_heartbeat_failures", "Heartbeats that failed since start", health.heartbeat_failures.load(Ordering::Relaxed) as f64);
+        out.gauge(
+            "reap3r_agent_heartbeat_consecutive_failures",
+            "Heartbeats failed since the last successful one",
+            health.consecutive_heartbeat_failures.load(Ordering::Relaxed) as f64,
+        );
+
+        out.family("reap3r_agent_reports_failed", "counter", "Reports that could not be delivered and were queued for retry");
+        for (msg_type, count) in self.outbox.queued_counts() {
+            out.sample("reap3r_agent_reports_failed_total", &[("type", &msg_type)], count as f64);
+        }
+
+        out.gauge("reap3r_agent_outbox_entries", "Reports waiting in the outbox", self.outbox.len() as f64);
+        out.gauge("reap3r_agent_outbox_bytes", "Disk space taken by the outbox", self.outbox.size_bytes() as f64);
+    }
+}
+
+/// Compared in constant time over digests, so response timing tells
+/// a LAN scraper neither how much of a guess was right nor its length.
+fn bearer_matches(authorization: Option<&str>, token: &str) -> bool {
+    let Some(presented) = authorization.and_then(|value| value.strip_prefix("Bearer ")) else {
+        return false;
+    };
+    Sha256::digest(presented.as_bytes()).ct_eq(&Sha256::digest(token.as_bytes())).into()
+}
+
+/// Read up to the blank line ending the request headers.
+async fn read_head(stream: &mut TcpStream) -> Result<String> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 1024];
+    loop {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if buf.windows(4).any(|w| w == b"\r\n\r\n") {
+            break;
+        }
+        anyhow::ensure!(buf.len() <= MAX_REQUEST_BYTES, "Request headers too large");
+    }
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Host Metrics
+// ═══════════════════════════════════════════════════════════════
+
+/// Name, help and value of a per-instance gauge.
+type Family<T, V = u64> = (&'static str, &'static str, fn(&T) -> V);
+
+fn render_host(out: &mut OpenMetrics, m: &MetricsPayload) {
+    out.gauge("reap3r_metrics_timestamp_seconds", "Time of the collection the values below come from", m.timestamp as f64 / 1000.0);
+    out.gauge("reap3r_host_uptime_seconds", "Host uptime", m.uptime_sec as f64);
+    out.gauge("reap3r_processes", "Running processes", m.processes_count as f64);
+
+    // CPU
+    out.gauge("reap3r_cpu_usage_percent", "CPU usage over all cores", m.cpu.usage_percent);
+    out.family("reap3r_cpu_core_usage_percent", "gauge", "CPU usage per core");
+    for (core, usage) in m.cpu.per_core_usage.iter().enumerate() {
+        out.sample("reap3r_cpu_core_usage_percent", &[("core", &core.to_string())], *usage);
+    }
+    out.gauge("reap3r_cpu_cores", "Logical CPU cores", m.cpu.cores as f64);
+    if let Some(iowait) = m.cpu.iowait_percent {
+        out.gauge("reap3r_cpu_iowait_percent", "Share of CPU time waiting on I/O", iowait);
+    }
+    if let Some(steal) = m.cpu.steal_percent {
+        out.gauge("reap3r_cpu_steal_percent", "Share of CPU time taken by the hypervisor", steal);
+    }
+    if let Some([load1, load5, load15]) = m.load_average {
+        out.gauge("reap3r_load1", "1 minute load average", load1);
+        out.gauge("reap3r_load5", "5 minute load average", load5);
+        out.gauge("reap3r_load15", "15 minute load average", load15);
+    }
+    if let Some(switches) = m.context_switches_sec {
+        out.gauge("reap3r_context_switches_per_second", "Context switches per second", switches as f64);
+    }
+
+    // Memory
+    out.gauge("reap3r_memory_total_bytes", "Physical memory", m.memory.total_bytes as f64);
+    out.gauge("reap3r_memory_used_bytes", "Physical memory in use", m.memory.used_bytes as f64);
+    out.gauge("reap3r_memory_available_bytes", "Physical memory available to new processes", m.memory.available_bytes as f64);
+    out.gauge("reap3r_swap_total_bytes", "Swap space", m.memory.swap_total_bytes as f64);
+    out.gauge("reap3r_swap_used_bytes", "Swap space in use", m.memory.swap_used_bytes as f64);
+
+    // Disks
+    let disk_families: [Family<DiskMetrics>; 5] = [
+        ("reap3r_disk_total_bytes", "Filesystem size", |d| d.total_bytes),
+        ("reap3r_disk_used_bytes", "Filesystem space in use", |d| d.used_bytes),
+        ("reap3r_disk_available_bytes", "Filesystem space available", |d| d.available_bytes),
+        ("reap3r_disk_read_bytes_per_second", "Bytes read per second from the underlying device", |d| d.read_bytes_sec),
+        ("reap3r_disk_written_bytes_per_second", "Bytes written per second to the underlying device", |d| d.write_bytes_sec),
+    ];
+    for (name, help, value) in disk_families {
+        out.family(name, "gauge", help);
+        for disk in &m.disks {
+            let labels = [("mount_point", disk.mount_point.as_str()), ("device", &disk.device), ("fs_type", &disk.fs_type)];
+            out.sample(name, &labels, value(disk) as f64);
+        }
+    }
+
+    // Network
+    let network_families: [Family<NetworkMetrics>; 4] = [
+        ("reap3r_network_receive_bytes_per_second", "Bytes received per second", |n| n.rx_bytes_sec),
+        ("reap3r_network_transmit_bytes_per_second", "Bytes sent per second", |n| n.tx_bytes_sec),
+        ("reap3r_network_receive_packets_per_second", "Packets received per second", |n| n.rx_packets_sec),
+        ("reap3r_network_transmit_packets_per_second", "Packets sent per second", |n| n.tx_packets_sec),
+    ];
+    for (name, help, value) in network_families {
+        out.family(name, "gauge", help);
+        for interface in &m.network {
+            out.sample(name, &[("interface", &interface.interface_name)], value(interface) as f64);
+        }
+    }
+
+    // Pressure stall information
+    if let Some(pressure) = &m.pressure {
+        let resources = [("cpu", &pressure.cpu), ("memory", &pressure.memory), ("io", &pressure.io)];
+        let lines = |stall: &PressureStall| [("some", stall.some.clone()), ("full", stall.full.clone())];
+
+        out.family("reap3r_pressure_stalled_percent", "gauge", "Share of time tasks were stalled on the resource");
+        for (resource, stall) in resources {
+            for (kind, line) in stall.iter().flat_map(lines) {
+                let Some(line) = line else { continue };
+                for (window, value) in [("10", line.avg10), ("60", line.avg60), ("300", line.avg300)] {
+                    out.sample("reap3r_pressure_stalled_percent", &[("resource", resource), ("kind", kind), ("window_seconds", window)], value);
+                }
+            }
+        }
+        out.family("reap3r_pressure_stalled_seconds", "counter", "Total time tasks were stalled on the resource");
+        for (resource, stall) in resources {
+            for (kind, line) in stall.iter().flat_map(lines) {
+                let Some(line) = line else { continue };
+                out.sample("reap3r_pressure_stalled_seconds_total", &[("resource", resource), ("kind", kind)], line.total_us as f64 / 1e6);
+            }
+        }
+    }
+
+    // Temperatures
+    if !m.temperatures.is_empty() {
+        out.family("reap3r_temperature_celsius", "gauge", "Sensor temperature");
+        for sensor in &m.temperatures {
+            out.sample("reap3r_temperature_celsius", &[("sensor", &sensor.label)], sensor.celsius);
+        }
+        out.family("reap3r_temperature_critical_celsius", "gauge", "Sensor critical temperature");
+        for sensor in &m.temperatures {
+            if let Some(critical) = sensor.critical_celsius {
+                out.sample("reap3r_temperature_critical_celsius", &[("sensor", &sensor.label)], critical);
+            }
+        }
+    }
+
+    if let Some(fds) = &m.file_descriptors {
+        out.gauge("reap3r_file_descriptors_open", "Open file handles", fds.open as f64);
+        out.gauge("reap3r_file_descriptors_max", "File handle limit", fds.max as f64);
+    }
+
+    // Containers
+    if !m.containers.is_empty() {
+        let container_families: [Family<ContainerMetrics, Option<f64>>; 7] = [
+            ("reap3r_container_cpu_usage_percent", "Container CPU usage, per core", |c| Some(c.cpu_percent)),
+            ("reap3r_container_cpu_limit_cores", "Container CPU quota in cores", |c| c.cpu_limit_cores),
+            ("reap3r_container_memory_bytes", "Container memory in use, without reclaimable cache", |c| Some(c.memory_bytes as f64)),
+            ("reap3r_container_memory_limit_bytes", "Container memory limit", |c| c.memory_limit_bytes.map(|b| b as f64)),
+            ("reap3r_container_read_bytes_per_second", "Bytes read per second by the container", |c| Some(c.io_read_bytes_sec as f64)),
+            ("reap3r_container_written_bytes_per_second", "Bytes written per second by the container", |c| Some(c.io_write_bytes_sec as f64)),
+            ("reap3r_container_pids", "Processes and threads in the container", |c| Some(c.pids as f64)),
+        ];
+        for (name, help, value) in container_families {
+            out.family(name, "gauge", help);
+            for container in &m.containers {
+                if let Some(value) = value(container) {
+                    let labels = [("id", container.id.as_str()), ("name", &container.name), ("runtime", &container.runtime)];
+                    out.sample(name, &labels, value);
+                }
+            }
+        }
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+// OpenMetrics Text Format
+// ═══════════════════════════════════════════════════════════════
+
+#[derive(Default)]
+struct OpenMetrics {
+    text: String,
+}
+
+impl OpenMetrics {
+    /// Start a metric family. Counter samples are named `<family>_total`,
+    /// info samples `<family>_info`.
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind);
+        let _ = writeln!(self.text, "# HELP {} {}", name, help);
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.text.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels.iter()
+                .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+                .collect();
+            let _ = write!(self.text, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.text, " {}", value);
+    }
+
+    fn gauge(&mut self, name: &str, help: &str, value: f64) {
+        self.family(name, "gauge", help);
+        self.sample(name, &[], value);
+    }
+
+    fn counter(&mut self, name: &str, help: &str, value: f64) {
+        self.family(name, "counter", help);
+        self.sample(&format!("{}_total", name), &[], value);
+    }
+
+    fn finish(mut self) -> String {
+        self.text.push_str("# EOF\n");
+        self.text
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', r"\\").replace('"', "\\\"").replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bearer_token_must_match_exactly() {
+        assert!(bearer_matches(Some("Bearer s3cret"), "s3cret"));
+        assert!(!bearer_matches(Some("Bearer s3cre"), "s3cret"));
+        assert!(!bearer_matches(Some("Bearer s3cret2"), "s3cret"));
+        assert!(!bearer_matches(Some("Basic s3cret"), "s3cret"));
+        assert!(!bearer_matches(None, "s3cret"));
+    }
+}
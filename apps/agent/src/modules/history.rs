@@ -0,0 +1,283 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r Agent - Metrics History
+// ─────────────────────────────────────────────────────────────
+//
+// Between two metrics reports the agent samples CPU, memory, disk
+// and network throughput every `metrics_sample_interval_sec` into
+// an in-memory ring buffer holding `metrics_history_minutes`. Each
+// report carries min/max/avg/p95 of the samples taken since the
+// previous one, so short spikes are not lost, and the metrics_history
+// job returns the raw samples of the last N minutes.
+// ─────────────────────────────────────────────────────────────
+
+use anyhow::{Result, bail};
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use sysinfo::{Networks, System};
+use tokio::time::{interval, Duration, MissedTickBehavior};
+
+use crate::comms::protocol::{Aggregate, JobRequest, JobResult, MetricsAggregates, MetricsHistoryPayload};
+use crate::config::AgentConfig;
+use crate::modules::metrics::RateTracker;
+use crate::modules::procfs;
+
+const DEFAULT_HISTORY_MINUTES: u64 = 5;
+
+/// One high-resolution sample. Throughput is summed over physical
+/// disks and non-loopback interfaces.
+#[derive(Debug, Clone, Serialize)]
+pub struct Sample {
+    /// Unix milliseconds.
+    pub timestamp: i64,
+    pub cpu_percent: f64,
+    pub memory_percent: f64,
+    pub disk_read_bytes_sec: u64,
+    pub disk_write_bytes_sec: u64,
+    pub net_rx_bytes_sec: u64,
+    pub net_tx_bytes_sec: u64,
+}
+
+pub struct MetricsHistory {
+    samples: Mutex<VecDeque<Sample>>,
+    interval_sec: u64,
+    capacity: usize,
+}
+
+impl MetricsHistory {
+    pub fn new(config: &AgentConfig) -> Self {
+        let interval_sec = config.metrics_sample_interval_sec;
+        let capacity = match interval_sec {
+            0 => 0,
+            sec => (config.metrics_history_minutes * 60 / sec).max(1) as usize,
+        };
+        Self {
+            samples: Mutex::new(VecDeque::with_capacity(capacity)),
+            interval_sec,
+            capacity,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Sample forever at the configured cadence. Returns at once when
+    /// sampling is disabled.
+    pub async fn sample_loop(self: Arc<Self>) {
+        if !self.enabled() {
+            tracing::info!("High-resolution metrics sampling disabled");
+            return;
+        }
+
+        let mut sampler = Sampler::new();
+        let mut ticker = interval(Duration::from_secs(self.interval_sec));
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // The first tick fires at once; the sampler's baseline needs a gap
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let sample = sampler.sample();
+            self.record(sample);
+        }
+    }
+
+    fn record(&self, sample: Sample) {
+        let mut samples = self.samples.lock().unwrap();
+        if samples.len() >= self.capacity {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+
+    /// Samples taken after `since` (Unix milliseconds), oldest first.
+    pub fn since(&self, since: i64) -> Vec<Sample> {
+        self.samples.lock().unwrap()
+            .iter()
+            .filter(|s| s.timestamp > since)
+            .cloned()
+            .collect()
+    }
+
+    /// Summary of the samples taken after `since`; None if there are none.
+    pub fn aggregate_since(&self, since: i64) -> Option<MetricsAggregates> {
+        let samples = self.since(since);
+        if samples.is_empty() {
+            return None;
+        }
+        let field = |f: fn(&Sample) -> f64| aggregate(samples.iter().map(f).collect());
+        Some(MetricsAggregates {
+            samples: samples.len() as u32,
+            sample_interval_sec: self.interval_sec,
+            cpu_percent: field(|s| s.cpu_percent),
+            memory_percent: field(|s| s.memory_percent),
+            disk_read_bytes_sec: field(|s| s.disk_read_bytes_sec as f64),
+            disk_write_bytes_sec: field(|s| s.disk_write_bytes_sec as f64),
+            net_rx_bytes_sec: field(|s| s.net_rx_bytes_sec as f64),
+            net_tx_bytes_sec: field(|s| s.net_tx_bytes_sec as f64),
+        })
+    }
+}
+
+/// min/max/avg and nearest-rank p95 of a non-empty series.
+fn aggregate(mut values: Vec<f64>) -> Aggregate {
+    values.sort_by(f64::total_cmp);
+    let n = values.len();
+    let round = |v: f64| (v * 100.0).round() / 100.0;
+    let p95_rank = ((n as f64) * 0.95).ceil() as usize;
+    Aggregate {
+        min: round(values[0]),
+        max: round(values[n - 1]),
+        avg: round(values.iter().sum::<f64>() / n as f64),
+        p95: round(values[p95_rank.clamp(1, n) - 1]),
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Sampler
+// ═══════════════════════════════════════════════════════════════
+
+const DISK_READ: &str = "disk:read_bytes";
+const DISK_WRITE: &str = "disk:write_bytes";
+const NET_RX: &str = "net:rx_bytes";
+const NET_TX: &str = "net:tx_bytes";
+
+/// Cheap per-tick readings: no process table, no disk space.
+struct Sampler {
+    sys: System,
+    networks: Networks,
+    rates: RateTracker,
+}
+
+impl Sampler {
+    fn new() -> Self {
+        let mut sampler = Self {
+            sys: System::new(),
+            networks: Networks::new_with_refreshed_list(),
+            rates: RateTracker::default(),
+        };
+        sampler.sample();
+        sampler
+    }
+
+    fn sample(&mut self) -> Sample {
+        self.sys.refresh_cpu_usage();
+        self.sys.refresh_memory();
+        self.networks.refresh_list();
+        let now = Instant::now();
+
+        let disk = procfs::disk_totals();
+        let (rx, tx) = self.networks.list().iter()
+            .filter(|(name, _)| !is_loopback(name))
+            .fold((0u64, 0u64), |(rx, tx), (_, data)| {
+                (rx + data.total_received(), tx + data.total_transmitted())
+            });
+
+        let mut rate = |key: &str, value: u64| self.rates.rate(key, value, now);
+        let (disk_read, disk_write) = match disk {
+            Some(disk) => (rate(DISK_READ, disk.read_bytes), rate(DISK_WRITE, disk.write_bytes)),
+            None => (0, 0),
+        };
+        let (net_rx, net_tx) = (rate(NET_RX, rx), rate(NET_TX, tx));
+
+        let total = self.sys.total_memory();
+        Sample {
+            timestamp: Utc::now().timestamp_millis(),
+            cpu_percent: (self.sys.global_cpu_info().cpu_usage() as f64 * 10.0).round() / 10.0,
+            memory_percent: match total {
+                0 => 0.0,
+                total => (self.sys.used_memory() as f64 * 1000.0 / total as f64).round() / 10.0,
+            },
+            disk_read_bytes_sec: disk_read,
+            disk_write_bytes_sec: disk_write,
+            net_rx_bytes_sec: net_rx,
+            net_tx_bytes_sec: net_tx,
+        }
+    }
+}
+
+fn is_loopback(name: &str) -> bool {
+    name == "lo" || name.starts_with("Loopback")
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Job Handler
+// ═══════════════════════════════════════════════════════════════
+
+pub async fn metrics_history(
+    job: &JobRequest,
+    payload: &MetricsHistoryPayload,
+    history: &MetricsHistory,
+    config: &AgentConfig,
+) -> Result<JobResult> {
+    if !history.enabled() {
+        bail!("Metrics sampling is disabled on this agent (metrics_sample_interval_sec = 0)");
+    }
+    let minutes = payload.minutes.unwrap_or(DEFAULT_HISTORY_MINUTES);
+    if minutes == 0 || minutes > config.metrics_history_minutes {
+        bail!("minutes must be between 1 and {}", config.metrics_history_minutes);
+    }
+
+    let since = Utc::now().timestamp_millis() - (minutes * 60_000) as i64;
+    let samples = history.since(since);
+
+    Ok(JobResult {
+        job_id: job.job_id.clone(),
+        status: "success".to_string(),
+        started_at: 0,
+        completed_at: 0,
+        stdout: None,
+        stderr: None,
+        exit_code: None,
+        error_message: None,
+        artifacts: Vec::new(),
+        result_data: Some(serde_json::json!({
+            "minutes": minutes,
+            "sample_interval_sec": history.interval_sec,
+            "count": samples.len(),
+            "aggregates": history.aggregate_since(since),
+            "samples": samples,
+        })),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn p95_is_the_nearest_rank() {
+        let hundred = aggregate((1..=100).rev().map(f64::from).collect());
+        assert_eq!((hundred.min, hundred.max, hundred.avg, hundred.p95), (1.0, 100.0, 50.5, 95.0));
+
+        // ceil(20 * 0.95) = 19th smallest
+        assert_eq!(aggregate((1..=20).map(f64::from).collect()).p95, 19.0);
+        // With few samples p95 is the maximum
+        assert_eq!(aggregate(vec![3.0, 1.0, 2.0]).p95, 3.0);
+        assert_eq!(aggregate(vec![42.125]).p95, 42.13);
+    }
+
+    #[test]
+    fn ring_keeps_the_newest_samples() {
+        let history = MetricsHistory { samples: Mutex::new(VecDeque::new()), interval_sec: 1, capacity: 3 };
+        for second in 1..=5 {
+            history.record(Sample {
+                timestamp: second * 1000,
+                cpu_percent: second as f64 * 10.0,
+                memory_percent: 0.0,
+                disk_read_bytes_sec: 0,
+                disk_write_bytes_sec: 0,
+                net_rx_bytes_sec: 0,
+                net_tx_bytes_sec: 0,
+            });
+        }
+
+        let timestamps: Vec<i64> = history.since(0).iter().map(|s| s.timestamp).collect();
+        assert_eq!(timestamps, [3000, 4000, 5000]);
+        let recent = history.aggregate_since(3000).unwrap();
+        assert_eq!((recent.samples, recent.cpu_percent.min, recent.cpu_percent.max), (2, 40.0, 50.0));
+        assert!(history.aggregate_since(5000).is_none());
+    }
+}
@@ -6,6 +6,7 @@ use anyhow::Result;
 use chrono::Utc;
 use sysinfo::{System, Networks};
 use crate::comms::protocol::*;
+use crate::modules::containers::{self, ContainerCollector};
 
 pub struct InventoryCollector;
 
@@ -20,6 +21,7 @@ impl InventoryCollector {
         let services = Self::collect_services();
         let users = Self::collect_users();
         let software = Self::collect_software();
+        let container_runtimes = containers::runtimes(&ContainerCollector::default().sample());
 
         Ok(InventoryPayload {
             timestamp: Utc::now().timestamp_millis(),
@@ -29,6 +31,7 @@ impl InventoryCollector {
             services,
             users,
             network_config,
+            container_runtimes,
         })
     }
 
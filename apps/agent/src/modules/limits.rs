@@ -0,0 +1,507 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r Agent - Script Resource Limits
+// ─────────────────────────────────────────────────────────────
+//
+// run_script jobs may carry `limits`; each one is lowered to the
+// local `job_limits` maximum, which also applies when the job asks
+// for nothing. On Linux with cgroup v2 the script runs in its own
+// transient cgroup under `jobs/` in the agent's cgroup, which the
+// service manager must delegate (systemd `Delegate=yes`): memory.max
+// and pids.max cover the whole process tree, CPU time is summed
+// from cpu.stat, and the cgroup is emptied and removed when the job
+// ends. Without a delegated cgroup v2 the same limits fall back to
+// per-process rlimits (RLIMIT_CPU, RLIMIT_AS, RLIMIT_NPROC); limits
+// that cannot be enforced that way are reported with the result.
+// Open files always use RLIMIT_NOFILE; output size is enforced by
+// OutputCapture.
+// ─────────────────────────────────────────────────────────────
+
+use anyhow::{Result, bail};
+use tokio::process::Command;
+
+use crate::comms::protocol::ResourceLimits;
+
+/// A limit a job ran into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exceeded {
+    CpuTime(u64),
+    Memory(u64),
+    Processes(u64),
+    Output(u64),
+}
+
+impl Exceeded {
+    fn resource(self) -> &'static str {
+        match self {
+            Exceeded::CpuTime(_) => "cpu_time_sec",
+            Exceeded::Memory(_) => "memory_mb",
+            Exceeded::Processes(_) => "max_processes",
+            Exceeded::Output(_) => "max_output_bytes",
+        }
+    }
+
+    fn limit(self) -> u64 {
+        match self {
+            Exceeded::CpuTime(v) | Exceeded::Memory(v) | Exceeded::Processes(v) | Exceeded::Output(v) => v,
+        }
+    }
+
+    pub fn message(self) -> String {
+        match self {
+            Exceeded::CpuTime(sec) => format!("Job killed after exceeding its CPU time limit of {}s", sec),
+            Exceeded::Memory(mb) => format!("Job killed after exceeding its memory limit of {} MiB", mb),
+            Exceeded::Processes(n) => format!("Job failed after reaching its limit of {} processes", n),
+            Exceeded::Output(bytes) => format!("Job killed after producing more than {} bytes of output", bytes),
+        }
+    }
+
+    pub fn to_json(self) -> serde_json::Value {
+        serde_json::json!({ "resource": self.resource(), "limit": self.limit() })
+    }
+}
+
+/// The limits a job asked for, capped by the local maximums.
+pub fn effective(requested: Option<&ResourceLimits>, max: &ResourceLimits) -> Result<ResourceLimits> {
+    let requested = requested.cloned().unwrap_or_default();
+    let cap = |name: &str, asked: Option<u64>, ceiling: Option<u64>| -> Result<Option<u64>> {
+        if asked == Some(0) {
+            bail!("limits.{} must be greater than 0", name);
+        }
+        Ok(match (asked, ceiling) {
+            (Some(asked), Some(ceiling)) => Some(asked.min(ceiling)),
+            (asked, ceiling) => asked.or(ceiling),
+        })
+    };
+    Ok(ResourceLimits {
+        cpu_time_sec: cap("cpu_time_sec", requested.cpu_time_sec, max.cpu_time_sec)?,
+        memory_mb: cap("memory_mb", requested.memory_mb, max.memory_mb)?,
+        max_processes: cap("max_processes", requested.max_processes, max.max_processes)?,
+        max_open_files: cap("max_open_files", requested.max_open_files, max.max_open_files)?,
+        max_output_bytes: cap("max_output_bytes", requested.max_output_bytes, max.max_output_bytes)?,
+    })
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Sandbox
+// ═══════════════════════════════════════════════════════════════
+
+/// Limits prepared for one job. Dropping it kills whatever is left
+/// in the job's cgroup and removes the cgroup.
+pub struct Sandbox {
+    limits: ResourceLimits,
+    /// Limits that are set but not enforced on this host.
+    warnings: Vec<String>,
+    #[cfg(target_os = "linux")]
+    cgroup: Option<imp::JobCgroup>,
+}
+
+impl Sandbox {
+    /// `uid` is the account the script will run as. Touches cgroupfs;
+    /// call from a blocking context.
+    pub fn prepare(job_id: &str, limits: ResourceLimits, uid: u32) -> Result<Self> {
+        imp::prepare(job_id, limits, uid)
+    }
+
+    pub fn limits(&self) -> &ResourceLimits {
+        &self.limits
+    }
+
+    /// Make the child enter the cgroup and set its rlimits.
+    pub fn apply(&self, cmd: &mut Command) {
+        imp::apply(self, cmd);
+    }
+
+    /// Resolves once the job's tree has used up its CPU time; never
+    /// resolves where the kernel enforces it instead.
+    pub async fn cpu_exceeded(&self) -> Exceeded {
+        imp::cpu_exceeded(self).await
+    }
+
+    /// Which limit, if any, explains how the job ended.
+    pub fn exit_reason(&self, status: &std::process::ExitStatus) -> Option<Exceeded> {
+        imp::exit_reason(self, status)
+    }
+
+    /// `result_data.limits`, or None when the job has no limits.
+    pub fn to_json(&self) -> Option<serde_json::Value> {
+        if self.limits == ResourceLimits::default() {
+            return None;
+        }
+        let mut value = serde_json::to_value(&self.limits).unwrap_or_default();
+        value["enforcement"] = serde_json::json!(self.enforcement());
+        if !self.warnings.is_empty() {
+            value["warnings"] = serde_json::json!(self.warnings);
+        }
+        Some(value)
+    }
+
+    fn enforcement(&self) -> &'static str {
+        #[cfg(target_os = "linux")]
+        if self.cgroup.is_some() {
+            return "cgroup";
+        }
+        if cfg!(target_os = "linux") { "rlimit" } else { "agent" }
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Linux
+// ═══════════════════════════════════════════════════════════════
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use super::*;
+    use nix::sys::resource::{setrlimit, Resource};
+    use nix::sys::signal::{kill, Signal};
+    use nix::unistd::Pid;
+    use std::os::fd::{AsRawFd, BorrowedFd};
+    use std::os::unix::process::ExitStatusExt;
+    use std::path::{Path, PathBuf};
+    use tokio::time::{sleep, Duration};
+
+    const CGROUP_FS: &str = "/sys/fs/cgroup";
+    const JOBS_CGROUP: &str = "jobs";
+    const AGENT_CGROUP: &str = "agent";
+    const CPU_POLL: Duration = Duration::from_millis(500);
+
+    /// The agent's delegated cgroup, once the agent has moved itself
+    /// out of it; None when it has none.
+    static DELEGATED: std::sync::OnceLock<Option<PathBuf>> = std::sync::OnceLock::new();
+
+    pub struct JobCgroup {
+        path: PathBuf,
+        /// Opened before the fork; the child writes its own pid here.
+        procs: std::fs::File,
+    }
+
+    impl JobCgroup {
+        fn create(job_id: &str, limits: &ResourceLimits) -> std::io::Result<Self> {
+            let root = DELEGATED.get_or_init(delegated_cgroup).as_deref().ok_or_else(|| {
+                std::io::Error::other("agent has no delegated cgroup (systemd Delegate=yes)")
+            })?;
+            let mut controllers = Vec::new();
+            if limits.memory_mb.is_some() {
+                controllers.push("memory");
+            }
+            if limits.max_processes.is_some() {
+                controllers.push("pids");
+            }
+
+            let available = std::fs::read_to_string(root.join("cgroup.controllers"))?;
+            if let Some(missing) = controllers.iter().find(|c| !available.split_whitespace().any(|a| a == **c)) {
+                return Err(std::io::Error::other(format!("{} controller not available", missing)));
+            }
+
+            let parent = root.join(JOBS_CGROUP);
+            std::fs::create_dir_all(&parent)?;
+            enable_controllers(root, &controllers)?;
+            enable_controllers(&parent, &controllers)?;
+
+            // Job IDs come from the server; keep them from naming other paths
+            let safe_id: String = job_id
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+                .collect();
+            let path = parent.join(format!("job-{}", safe_id));
+            if path.exists() {
+                // Left behind by an agent that died mid-job
+                remove_cgroup(&path);
+            }
+            std::fs::create_dir(&path)?;
+
+            let cgroup = Self {
+                procs: std::fs::OpenOptions::new().write(true).open(path.join("cgroup.procs"))?,
+                path,
+            };
+            if let Some(mb) = limits.memory_mb {
+                std::fs::write(cgroup.path.join("memory.max"), mb.saturating_mul(1024 * 1024).to_string())?;
+                // Without swap accounting the limit still holds for RAM
+                let _ = std::fs::write(cgroup.path.join("memory.swap.max"), "0");
+                // Kill the whole job on OOM rather than one random process
+                let _ = std::fs::write(cgroup.path.join("memory.oom.group"), "1");
+            }
+            if let Some(n) = limits.max_processes {
+                std::fs::write(cgroup.path.join("pids.max"), n.to_string())?;
+            }
+            Ok(cgroup)
+        }
+
+        fn cpu_usage_usec(&self) -> Option<u64> {
+            read_key(&self.path.join("cpu.stat"), "usage_usec")
+        }
+
+        fn event(&self, file: &str, key: &str) -> u64 {
+            read_key(&self.path.join(file), key).unwrap_or(0)
+        }
+    }
+
+    impl Drop for JobCgroup {
+        fn drop(&mut self) {
+            // Removal waits for killed members to leave; keep that off
+            // the async workers
+            let path = std::mem::take(&mut self.path);
+            match tokio::runtime::Handle::try_current() {
+                Ok(runtime) => drop(runtime.spawn_blocking(move || remove_cgroup(&path))),
+                Err(_) => remove_cgroup(&path),
+            }
+        }
+    }
+
+    /// The cgroup systemd delegated to the agent's service. cgroup v2
+    /// only lets a cgroup without member processes hand controllers
+    /// to its children, so the agent moves itself into an `agent`
+    /// leaf first and job cgroups go under a `jobs` sibling.
+    fn delegated_cgroup() -> Option<PathBuf> {
+        let own = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+        let own = own_cgroup(&own)?;
+        let mut base = Path::new(CGROUP_FS).join(own.trim_start_matches('/'));
+        if own == "/" || !base.join("cgroup.subtree_control").exists() {
+            tracing::info!("Agent is not in a cgroup v2 of its own; job limits use rlimits");
+            return None;
+        }
+        // Already moved by an earlier call in this process
+        if base.file_name().is_some_and(|n| n == AGENT_CGROUP) {
+            base.pop();
+        }
+
+        let result = (|| -> std::io::Result<()> {
+            let leaf = base.join(AGENT_CGROUP);
+            std::fs::create_dir_all(&leaf)?;
+            let members = std::fs::read_to_string(base.join("cgroup.procs"))?;
+            for pid in members.lines().filter(|l| !l.trim().is_empty()) {
+                std::fs::write(leaf.join("cgroup.procs"), pid.trim())?;
+            }
+            Ok(())
+        })();
+        match result {
+            Ok(()) => Some(base),
+            Err(e) => {
+                // EACCES/EPERM: the service manager did not delegate it
+                tracing::info!("Cannot manage cgroup {:?} ({}); job limits use rlimits", base, e);
+                None
+            }
+        }
+    }
+
+    /// The cgroup v2 path in /proc/<pid>/cgroup content.
+    pub(super) fn own_cgroup(content: &str) -> Option<&str> {
+        content.lines().find_map(|line| line.strip_prefix("0::")).map(str::trim)
+    }
+
+    fn enable_controllers(cgroup: &Path, controllers: &[&str]) -> std::io::Result<()> {
+        let path = cgroup.join("cgroup.subtree_control");
+        let enabled = std::fs::read_to_string(&path)?;
+        for controller in controllers {
+            if !enabled.split_whitespace().any(|c| c == *controller) {
+                std::fs::write(&path, format!("+{}", controller))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_key(path: &Path, key: &str) -> Option<u64> {
+        std::fs::read_to_string(path).ok()?.lines().find_map(|line| {
+            let (k, v) = line.split_once(' ')?;
+            if k == key { v.trim().parse().ok() } else { None }
+        })
+    }
+
+    /// Kill every process still in the cgroup, then remove it.
+    fn remove_cgroup(path: &Path) {
+        // cgroup.kill needs Linux 5.14; signal the members otherwise
+        if std::fs::write(path.join("cgroup.kill"), "1").is_err() {
+            if let Ok(procs) = std::fs::read_to_string(path.join("cgroup.procs")) {
+                for pid in procs.lines().filter_map(|l| l.trim().parse::<i32>().ok()) {
+                    let _ = kill(Pid::from_raw(pid), Signal::SIGKILL);
+                }
+            }
+        }
+        // Killed members leave the cgroup asynchronously
+        for _ in 0..20 {
+            match std::fs::remove_dir(path) {
+                Ok(()) => return,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+                Err(_) => std::thread::sleep(std::time::Duration::from_millis(10)),
+            }
+        }
+        tracing::warn!("Could not remove job cgroup {:?}", path);
+    }
+
+    pub fn prepare(job_id: &str, limits: ResourceLimits, uid: u32) -> Result<Sandbox> {
+        let wants_cgroup = limits.memory_mb.is_some()
+            || limits.max_processes.is_some()
+            || limits.cpu_time_sec.is_some();
+        let cgroup = if wants_cgroup {
+            match JobCgroup::create(job_id, &limits) {
+                Ok(cgroup) => Some(cgroup),
+                Err(e) => {
+                    tracing::debug!("No cgroup v2 for job {} ({}), using rlimits", job_id, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let mut warnings = Vec::new();
+        if cgroup.is_none() {
+            if let Some(n) = limits.max_processes {
+                if uid == 0 {
+                    warnings.push(format!(
+                        "max_processes ({}) is not enforced: no delegated cgroup, and RLIMIT_NPROC does not apply to root",
+                        n,
+                    ));
+                } else {
+                    warnings.push(format!(
+                        "max_processes ({}) is enforced with RLIMIT_NPROC, which counts every process of uid {}",
+                        n, uid,
+                    ));
+                }
+            }
+            if limits.memory_mb.is_some() {
+                warnings.push("memory_mb is enforced per process with RLIMIT_AS, not for the whole job".to_string());
+            }
+        }
+        for warning in &warnings {
+            tracing::warn!("Job {}: {}", job_id, warning);
+        }
+        Ok(Sandbox { limits, warnings, cgroup })
+    }
+
+    pub fn apply(sandbox: &Sandbox, cmd: &mut Command) {
+        let limits = &sandbox.limits;
+        let mut rlimits = Vec::new();
+        if let Some(n) = limits.max_open_files {
+            rlimits.push((Resource::RLIMIT_NOFILE, n, n));
+        }
+        if sandbox.cgroup.is_none() {
+            if let Some(sec) = limits.cpu_time_sec {
+                // SIGXCPU at the soft limit, SIGKILL if it is ignored
+                rlimits.push((Resource::RLIMIT_CPU, sec, sec.saturating_add(2)));
+            }
+            if let Some(mb) = limits.memory_mb {
+                let bytes = mb.saturating_mul(1024 * 1024);
+                rlimits.push((Resource::RLIMIT_AS, bytes, bytes));
+            }
+            if let Some(n) = limits.max_processes {
+                rlimits.push((Resource::RLIMIT_NPROC, n, n));
+            }
+        }
+
+        let procs_fd = sandbox.cgroup.as_ref().map(|c| c.procs.as_raw_fd());
+        if procs_fd.is_none() && rlimits.is_empty() {
+            return;
+        }
+
+        // Runs in the child between fork and exec, before run_as
+        // drops privileges.
+        unsafe {
+            cmd.pre_exec(move || {
+                if let Some(fd) = procs_fd {
+                    nix::unistd::write(BorrowedFd::borrow_raw(fd), b"0")?;
+                }
+                for (resource, soft, hard) in &rlimits {
+                    setrlimit(*resource, *soft, *hard)?;
+                }
+                Ok(())
+            });
+        }
+    }
+
+    pub async fn cpu_exceeded(sandbox: &Sandbox) -> Exceeded {
+        let (Some(cgroup), Some(sec)) = (&sandbox.cgroup, sandbox.limits.cpu_time_sec) else {
+            return std::future::pending().await;
+        };
+        loop {
+            sleep(CPU_POLL).await;
+            if cgroup.cpu_usage_usec().is_some_and(|usec| usec >= sec.saturating_mul(1_000_000)) {
+                return Exceeded::CpuTime(sec);
+            }
+        }
+    }
+
+    pub fn exit_reason(sandbox: &Sandbox, status: &std::process::ExitStatus) -> Option<Exceeded> {
+        let limits = &sandbox.limits;
+        if let Some(cgroup) = &sandbox.cgroup {
+            if let Some(mb) = limits.memory_mb {
+                if cgroup.event("memory.events", "oom_kill") > 0 {
+                    return Some(Exceeded::Memory(mb));
+                }
+            }
+            if let Some(n) = limits.max_processes {
+                if !status.success() && cgroup.event("pids.events", "max") > 0 {
+                    return Some(Exceeded::Processes(n));
+                }
+            }
+        }
+        match (limits.cpu_time_sec, status.signal()) {
+            (Some(sec), Some(signal)) if signal == Signal::SIGXCPU as i32 => Some(Exceeded::CpuTime(sec)),
+            _ => None,
+        }
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Other Platforms
+// ═══════════════════════════════════════════════════════════════
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use super::*;
+
+    pub fn prepare(_job_id: &str, limits: ResourceLimits, _uid: u32) -> Result<Sandbox> {
+        if limits.cpu_time_sec.is_some()
+            || limits.memory_mb.is_some()
+            || limits.max_processes.is_some()
+            || limits.max_open_files.is_some()
+        {
+            bail!("Only max_output_bytes is supported on this platform");
+        }
+        Ok(Sandbox { limits, warnings: Vec::new() })
+    }
+
+    pub fn apply(_sandbox: &Sandbox, _cmd: &mut Command) {}
+
+    pub async fn cpu_exceeded(_sandbox: &Sandbox) -> Exceeded {
+        std::future::pending().await
+    }
+
+    pub fn exit_reason(_sandbox: &Sandbox, _status: &std::process::ExitStatus) -> Option<Exceeded> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requested_limits_are_capped_by_local_maximums() {
+        let max = ResourceLimits { memory_mb: Some(512), max_processes: Some(64), ..Default::default() };
+        let requested = ResourceLimits { memory_mb: Some(2048), cpu_time_sec: Some(30), ..Default::default() };
+        let limits = effective(Some(&requested), &max).unwrap();
+        assert_eq!(limits.memory_mb, Some(512));
+        assert_eq!(limits.cpu_time_sec, Some(30));
+        assert_eq!(limits.max_processes, Some(64));
+        assert_eq!(limits.max_open_files, None);
+    }
+
+    #[test]
+    fn lower_requests_win_and_zero_is_refused() {
+        let max = ResourceLimits { memory_mb: Some(512), ..Default::default() };
+        let requested = ResourceLimits { memory_mb: Some(128), ..Default::default() };
+        assert_eq!(effective(Some(&requested), &max).unwrap().memory_mb, Some(128));
+        assert_eq!(effective(None, &max).unwrap().memory_mb, Some(512));
+
+        let zero = ResourceLimits { max_processes: Some(0), ..Default::default() };
+        assert!(effective(Some(&zero), &max).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn finds_the_unified_cgroup_line() {
+        let content = "12:pids:/system.slice/x.service\n0::/system.slice/massvision-agent.service/agent\n";
+        assert_eq!(imp::own_cgroup(content), Some("/system.slice/massvision-agent.service/agent"));
+        assert_eq!(imp::own_cgroup("1:name=systemd:/init.scope\n"), None);
+    }
+}
@@ -1,17 +1,41 @@
 // ─────────────────────────────────────────────────────────────
 // MASSVISION Reap3r Agent - System Metrics Collector
 // ─────────────────────────────────────────────────────────────
+//
+// Network and disk throughput are per-second rates computed from
+// cumulative kernel counters: each sample is compared with the
+// previous one and divided by the real (monotonic) time between
+// them. The first sample of a counter, and one that went backwards
+// (interface re-created, driver reset), reports 0 and becomes the
+// new baseline. Disk I/O, pressure, file descriptors, context
+// switches and the iowait/steal split come from /proc (see procfs);
+// per-container usage from cgroup v2 (see containers).
+// ─────────────────────────────────────────────────────────────
 
 use anyhow::Result;
 use chrono::Utc;
-use sysinfo::{System, Disks, Networks};
-use crate::comms::protocol::{MetricsPayload, CpuMetrics, MemoryMetrics, DiskMetrics, NetworkMetrics};
+use std::collections::HashMap;
+use std::time::Instant;
+use sysinfo::{System, Components, Disks, Networks, Process, Users};
+use crate::comms::protocol::{
+    MetricsPayload, CpuMetrics, MemoryMetrics, DiskMetrics, NetworkMetrics,
+    PressureMetrics, TemperatureMetrics, FileDescriptorMetrics, TopProcesses, ProcessUsage,
+    ContainerMetrics,
+};
+use crate::modules::containers::{ContainerCollector, ContainerSample};
+use crate::modules::procfs::{self, CpuTimes, KernelStat};
+
+const CONTEXT_SWITCHES: &str = "kernel:context_switches";
 
 pub struct MetricsCollector {
     sys: System,
     disks: Disks,
     networks: Networks,
-    prev_net: Vec<(String, u64, u64)>,
+    components: Components,
+    users: Users,
+    containers: ContainerCollector,
+    rates: RateTracker,
+    prev_cpu: Option<CpuTimes>,
 }
 
 impl MetricsCollector {
@@ -20,26 +44,104 @@ impl MetricsCollector {
         sys.refresh_all();
         let disks = Disks::new_with_refreshed_list();
         let networks = Networks::new_with_refreshed_list();
+        let components = Components::new_with_refreshed_list();
 
-        // Initialise previous network counters
-        let prev_net: Vec<(String, u64, u64)> = networks
-            .list()
-            .iter()
-            .map(|(name, data)| (name.to_string(), data.total_received(), data.total_transmitted()))
-            .collect();
-
-        Self {
+        let mut collector = Self {
             sys,
             disks,
             networks,
-            prev_net,
+            components,
+            users: Users::new(),
+            containers: ContainerCollector::default(),
+            rates: RateTracker::default(),
+            prev_cpu: None,
+        };
+        // Baseline the counters so the first report has real rates
+        let stat = procfs::kernel_stat();
+        let containers = collector.containers.sample();
+        collector.sample_counters(stat.as_ref(), &containers);
+        collector.cpu_split(stat.as_ref());
+        collector
+    }
+
+    /// Feed every cumulative counter to the rate tracker. Counters
+    /// that are not seen (interface or disk gone) are forgotten.
+    fn sample_counters(&mut self, stat: Option<&KernelStat>, containers: &[ContainerSample]) -> HashMap<String, u64> {
+        let now = Instant::now();
+        let mut rates = HashMap::new();
+
+        for (name, data) in self.networks.list() {
+            for (key, value) in [
+                (format!("net:{}:rx_bytes", name), data.total_received()),
+                (format!("net:{}:tx_bytes", name), data.total_transmitted()),
+                (format!("net:{}:rx_packets", name), data.total_packets_received()),
+                (format!("net:{}:tx_packets", name), data.total_packets_transmitted()),
+            ] {
+                let rate = self.rates.rate(&key, value, now);
+                rates.insert(key, rate);
+            }
+        }
+
+        for (device, stats) in procfs::diskstats() {
+            for (key, value) in [
+                (format!("disk:{}:read_bytes", device), stats.read_bytes),
+                (format!("disk:{}:write_bytes", device), stats.write_bytes),
+            ] {
+                let rate = self.rates.rate(&key, value, now);
+                rates.insert(key, rate);
+            }
+        }
+
+        if let Some(stat) = stat {
+            let rate = self.rates.rate(CONTEXT_SWITCHES, stat.context_switches, now);
+            rates.insert(CONTEXT_SWITCHES.to_string(), rate);
         }
+
+        for container in containers {
+            for (key, value) in [
+                (format!("container:{}:cpu_usec", container.id), container.cpu_usage_usec),
+                (format!("container:{}:read_bytes", container.id), container.io_read_bytes),
+                (format!("container:{}:write_bytes", container.id), container.io_write_bytes),
+            ] {
+                let rate = self.rates.rate(&key, value, now);
+                rates.insert(key, rate);
+            }
+        }
+
+        self.rates.forget_unseen(now);
+        rates
+    }
+
+    /// Percent of CPU time in iowait and steal since the previous
+    /// sample; None on the first sample and off Linux.
+    fn cpu_split(&mut self, stat: Option<&KernelStat>) -> (Option<f64>, Option<f64>) {
+        let current = stat.map(|s| s.cpu);
+        let (Some(now), Some(prev)) = (current, std::mem::replace(&mut self.prev_cpu, current)) else {
+            return (None, None);
+        };
+        let total = now.total.saturating_sub(prev.total);
+        if total == 0 {
+            return (None, None);
+        }
+        let percent = |now: u64, prev: u64| {
+            (now.saturating_sub(prev) as f64 * 1000.0 / total as f64).round() / 10.0
+        };
+        (Some(percent(now.iowait, prev.iowait)), Some(percent(now.steal, prev.steal)))
     }
 
     pub fn collect(&mut self) -> Result<MetricsPayload> {
         self.sys.refresh_all();
-        self.disks.refresh();
-        self.networks.refresh();
+        // refresh_list (not refresh) so mounts and interfaces that
+        // appeared or went away since the last sample are picked up
+        self.disks.refresh_list();
+        self.networks.refresh_list();
+        self.components.refresh();
+        let stat = procfs::kernel_stat();
+        let container_samples = self.containers.sample();
+        let rates = self.sample_counters(stat.as_ref(), &container_samples);
+        let (iowait_percent, steal_percent) = self.cpu_split(stat.as_ref());
+        let rate = |key: String| rates.get(&key).copied().unwrap_or(0);
+        let addresses = interface_addresses();
 
         // ── CPU ─────────────────────────────────────────────
         let cpu_usage = self.sys.global_cpu_info().cpu_usage() as f64;
@@ -64,6 +166,8 @@ impl MetricsCollector {
             model: cpu_model,
             frequency_mhz,
             per_core_usage,
+            iowait_percent,
+            steal_percent,
         };
 
         // ── Memory ──────────────────────────────────────────
@@ -83,6 +187,7 @@ impl MetricsCollector {
             .map(|d| {
                 let total = d.total_space();
                 let available = d.available_space();
+                let stats_name = procfs::device_name(d.name());
                 DiskMetrics {
                     mount_point: d.mount_point().to_string_lossy().to_string(),
                     device: d.name().to_string_lossy().to_string(),
@@ -90,56 +195,83 @@ impl MetricsCollector {
                     total_bytes: total,
                     used_bytes: total.saturating_sub(available),
                     available_bytes: available,
-                    read_bytes_sec: 0,
-                    write_bytes_sec: 0,
+                    read_bytes_sec: stats_name.as_ref()
+                        .map_or(0, |dev| rate(format!("disk:{}:read_bytes", dev))),
+                    write_bytes_sec: stats_name.as_ref()
+                        .map_or(0, |dev| rate(format!("disk:{}:write_bytes", dev))),
                 }
             })
             .collect();
 
         // ── Network ─────────────────────────────────────────
-        let mut new_prev: Vec<(String, u64, u64)> = Vec::new();
-        let network: Vec<NetworkMetrics> = self
+        let mut network: Vec<NetworkMetrics> = self
             .networks
             .list()
             .iter()
-            .map(|(name, data)| {
-                let total_rx = data.total_received();
-                let total_tx = data.total_transmitted();
-
-                // Delta from previous snapshot
-                let (rx_sec, tx_sec) = self
-                    .prev_net
-                    .iter()
-                    .find(|(n, _, _)| n == name)
-                    .map(|(_, prev_rx, prev_tx)| {
-                        (
-                            total_rx.saturating_sub(*prev_rx),
-                            total_tx.saturating_sub(*prev_tx),
-                        )
-                    })
-                    .unwrap_or((0, 0));
-
-                new_prev.push((name.to_string(), total_rx, total_tx));
-
-                let mac = data.mac_address().to_string();
-
-                NetworkMetrics {
-                    interface_name: name.to_string(),
-                    ip_address: String::new(),
-                    mac_address: mac,
-                    rx_bytes_sec: rx_sec,
-                    tx_bytes_sec: tx_sec,
-                    rx_packets_sec: 0,
-                    tx_packets_sec: 0,
-                }
+            .map(|(name, data)| NetworkMetrics {
+                interface_name: name.to_string(),
+                ip_address: addresses.get(name.as_str()).cloned().unwrap_or_default(),
+                mac_address: data.mac_address().to_string(),
+                rx_bytes_sec: rate(format!("net:{}:rx_bytes", name)),
+                tx_bytes_sec: rate(format!("net:{}:tx_bytes", name)),
+                rx_packets_sec: rate(format!("net:{}:rx_packets", name)),
+                tx_packets_sec: rate(format!("net:{}:tx_packets", name)),
             })
             .collect();
-        self.prev_net = new_prev;
+        network.sort_by(|a, b| a.interface_name.cmp(&b.interface_name));
 
         // ── Aggregate ───────────────────────────────────────
         let processes_count = self.sys.processes().len() as u32;
         let uptime_sec = System::uptime();
 
+        // ── Host ────────────────────────────────────────────
+        let load_average = (!cfg!(target_os = "windows")).then(|| {
+            let load = System::load_average();
+            [load.one, load.five, load.fifteen]
+        });
+
+        let pressure = PressureMetrics {
+            cpu: procfs::pressure("cpu"),
+            memory: procfs::pressure("memory"),
+            io: procfs::pressure("io"),
+        };
+        let pressure = (pressure.cpu.is_some() || pressure.memory.is_some() || pressure.io.is_some())
+            .then_some(pressure);
+
+        // Sensors that cannot be read report NaN
+        let temperatures: Vec<TemperatureMetrics> = self
+            .components
+            .list()
+            .iter()
+            .filter(|c| c.temperature().is_finite())
+            .map(|c| TemperatureMetrics {
+                label: c.label().to_string(),
+                celsius: c.temperature() as f64,
+                critical_celsius: c.critical().filter(|t| t.is_finite()).map(f64::from),
+            })
+            .collect();
+
+        let file_descriptors = procfs::file_descriptors()
+            .map(|(open, max)| FileDescriptorMetrics { open, max });
+
+        // ── Containers ──────────────────────────────────────
+        let containers: Vec<ContainerMetrics> = container_samples
+            .into_iter()
+            .map(|c| ContainerMetrics {
+                // CPU microseconds per second / 10_000 = percent of a core
+                cpu_percent: (rate(format!("container:{}:cpu_usec", c.id)) as f64 / 1000.0).round() / 10.0,
+                io_read_bytes_sec: rate(format!("container:{}:read_bytes", c.id)),
+                io_write_bytes_sec: rate(format!("container:{}:write_bytes", c.id)),
+                id: c.id,
+                name: c.name,
+                runtime: c.runtime,
+                cpu_limit_cores: c.cpu_limit_cores,
+                memory_bytes: c.memory_bytes,
+                memory_limit_bytes: c.memory_limit_bytes,
+                pids: c.pids,
+            })
+            .collect();
+
         Ok(MetricsPayload {
             timestamp: Utc::now().timestamp_millis(),
             cpu,
@@ -148,6 +280,152 @@ impl MetricsCollector {
             network,
             processes_count,
             uptime_sec,
+            load_average,
+            pressure,
+            temperatures,
+            file_descriptors,
+            context_switches_sec: rates.get(CONTEXT_SWITCHES).copied(),
+            // Filled in by the metrics loop from the sample history
+            aggregates: None,
+            // Filled in by the metrics loop every Nth report
+            top_processes: None,
+            containers,
         })
     }
+
+    /// The `n` processes using the most CPU and the most resident
+    /// memory as of the last `collect`. Threads are left out.
+    pub fn top_processes(&mut self, n: usize) -> TopProcesses {
+        self.users.refresh_list();
+        let processes: Vec<&Process> = self.sys.processes().values()
+            .filter(|p| p.thread_kind().is_none())
+            .collect();
+
+        let top = |key: &dyn Fn(&Process, &Process) -> std::cmp::Ordering| {
+            let mut sorted = processes.clone();
+            // Ties in PID order so consecutive reports stay comparable
+            sorted.sort_by(|a, b| key(b, a).then_with(|| a.pid().cmp(&b.pid())));
+            sorted.iter().take(n).map(|p| ProcessUsage {
+                pid: p.pid().as_u32(),
+                name: p.name().to_string(),
+                user: p.user_id()
+                    .and_then(|uid| self.users.get_user_by_id(uid))
+                    .map(|user| user.name().to_string()),
+                cpu_percent: (p.cpu_usage() as f64 * 10.0).round() / 10.0,
+                memory_bytes: p.memory(),
+            }).collect()
+        };
+
+        TopProcesses {
+            by_cpu: top(&|a, b| a.cpu_usage().total_cmp(&b.cpu_usage())),
+            by_memory: top(&|a, b| a.memory().cmp(&b.memory())),
+        }
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Rates
+// ═══════════════════════════════════════════════════════════════
+
+struct Counter {
+    value: u64,
+    at: Instant,
+}
+
+/// Turns cumulative counters into per-second rates.
+#[derive(Default)]
+pub struct RateTracker {
+    counters: HashMap<String, Counter>,
+}
+
+impl RateTracker {
+    /// Per-second rate of `key` since its previous sample; 0 for a
+    /// new counter or one that went backwards.
+    pub fn rate(&mut self, key: &str, value: u64, now: Instant) -> u64 {
+        let rate = match self.counters.get(key) {
+            Some(prev) if value >= prev.value => {
+                let elapsed = now.duration_since(prev.at).as_secs_f64();
+                if elapsed > 0.0 {
+                    ((value - prev.value) as f64 / elapsed).round() as u64
+                } else {
+                    0
+                }
+            }
+            Some(_) => {
+                tracing::debug!("Counter {} went backwards, resetting its baseline", key);
+                0
+            }
+            None => 0,
+        };
+        self.counters.insert(key.to_string(), Counter { value, at: now });
+        rate
+    }
+
+    /// Drop counters not sampled at `now`, so a name that comes back
+    /// later starts from a fresh baseline.
+    pub fn forget_unseen(&mut self, now: Instant) {
+        self.counters.retain(|_, counter| counter.at == now);
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Interface Addresses
+// ═══════════════════════════════════════════════════════════════
+
+/// First IPv4 address of every interface, else its first IPv6 one
+/// (link-local addresses last).
+fn interface_addresses() -> HashMap<String, String> {
+    let mut best: HashMap<String, (u8, String)> = HashMap::new();
+    for iface in if_addrs::get_if_addrs().unwrap_or_default() {
+        let rank = match &iface.addr {
+            if_addrs::IfAddr::V4(_) => 0,
+            if_addrs::IfAddr::V6(v6) if v6.ip.segments()[0] & 0xffc0 == 0xfe80 => 2,
+            if_addrs::IfAddr::V6(_) => 1,
+        };
+        let ip = iface.ip().to_string();
+        match best.get(&iface.name) {
+            Some((existing, _)) if *existing <= rank => {}
+            _ => {
+                best.insert(iface.name, (rank, ip));
+            }
+        }
+    }
+    best.into_iter().map(|(name, (_, ip))| (name, ip)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn rates_are_per_second_from_the_second_sample() {
+        let (mut rates, start) = (RateTracker::default(), Instant::now());
+        assert_eq!(rates.rate("rx", 1_000, start), 0);
+        assert_eq!(rates.rate("rx", 3_000, start + Duration::from_secs(2)), 1_000);
+        assert_eq!(rates.rate("rx", 3_000, start + Duration::from_secs(2)), 0);
+    }
+
+    #[test]
+    fn wrapped_or_reset_counter_rebases_instead_of_spiking() {
+        let (mut rates, start) = (RateTracker::default(), Instant::now());
+        rates.rate("rx", u64::MAX - 10, start);
+        // Wrapped past zero, or the interface was recreated
+        assert_eq!(rates.rate("rx", 5, start + Duration::from_secs(1)), 0);
+        assert_eq!(rates.rate("rx", 505, start + Duration::from_secs(2)), 500);
+    }
+
+    #[test]
+    fn forgotten_counter_starts_over() {
+        let (mut rates, start) = (RateTracker::default(), Instant::now());
+        rates.rate("eth0", 100, start);
+        rates.rate("eth1", 100, start);
+        let later = start + Duration::from_secs(1);
+        rates.rate("eth0", 200, later);
+        rates.forget_unseen(later);
+
+        let again = later + Duration::from_secs(1);
+        assert_eq!(rates.rate("eth1", 1_000_000, again), 0);
+        assert_eq!(rates.rate("eth0", 300, again), 100);
+    }
 }
@@ -1,3 +1,18 @@
 pub mod metrics;
 pub mod inventory;
 pub mod runner;
+pub mod shell;
+pub mod updater;
+pub mod output;
+pub mod artifacts;
+pub mod wol;
+pub mod policy;
+pub mod run_as;
+pub mod limits;
+pub mod signing;
+pub mod process;
+pub mod procfs;
+pub mod containers;
+pub mod history;
+pub mod alerts;
+pub mod exporter;
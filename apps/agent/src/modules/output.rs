@@ -0,0 +1,601 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r Agent - Job Output Streaming
+// ─────────────────────────────────────────────────────────────
+//
+// Script stdout/stderr is spooled to disk as it is read, so the
+// final JobResult can carry the last 1 MiB of each stream without
+// the agent holding the whole output in memory. A separate sender
+// task reads the spool behind the pipe readers and forwards it as
+// sequenced job_output chunks, every 250ms or as soon as 32 KiB is
+// pending; a slow server makes the stream lag, never the script.
+// With an output limit, bytes past it are dropped and the runner
+// is told so it can stop the job.
+// ─────────────────────────────────────────────────────────────
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot, watch, Notify, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, Duration, MissedTickBehavior};
+
+use crate::comms::client::AgentClient;
+use crate::comms::protocol::JobOutputChunk;
+use crate::config::AgentConfig;
+use crate::modules::shell::Utf8Carry;
+
+const FLUSH_INTERVAL: Duration = Duration::from_millis(250);
+const MAX_CHUNK: usize = 32 * 1024;
+
+/// Bytes of each stream kept in the final JobResult.
+const RESULT_TAIL_BYTES: u64 = 1_048_576;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    fn name(self) -> &'static str {
+        match self {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        }
+    }
+}
+
+/// What a finished capture hands back to the job result.
+#[derive(Debug, Default)]
+pub struct OutputSummary {
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    pub stdout_bytes: u64,
+    pub stderr_bytes: u64,
+    pub chunks: u64,
+    /// Every chunk was handed to the transport.
+    pub complete: bool,
+    /// Output stopped at the job's max_output_bytes.
+    pub truncated: bool,
+}
+
+impl OutputSummary {
+    /// `result_data` pointing at the streamed output.
+    pub fn result_data(&self) -> serde_json::Value {
+        serde_json::json!({
+            "output": {
+                "chunks": self.chunks,
+                "stdout_bytes": self.stdout_bytes,
+                "stderr_bytes": self.stderr_bytes,
+                "complete": self.complete,
+                "truncated": self.truncated,
+            }
+        })
+    }
+}
+
+/// Streams a child's pipes until they close or `finish` gives up.
+/// Dropping the capture stops it and removes the spool files.
+pub struct OutputCapture {
+    stop: Option<oneshot::Sender<()>>,
+    task: JoinHandle<OutputSummary>,
+    limit: Option<u64>,
+    overflow: watch::Receiver<bool>,
+}
+
+impl OutputCapture {
+    pub fn start<O, E>(
+        job_id: &str,
+        client: Arc<RwLock<AgentClient>>,
+        stdout: Option<O>,
+        stderr: Option<E>,
+        limit: Option<u64>,
+    ) -> Self
+    where
+        O: AsyncRead + Unpin + Send + 'static,
+        E: AsyncRead + Unpin + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel(64);
+        let readers = [
+            spawn_reader(stdout, Stream::Stdout, tx.clone()),
+            spawn_reader(stderr, Stream::Stderr, tx),
+        ];
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let (overflow_tx, overflow) = watch::channel(false);
+        let budget = Budget { limit, used: 0, overflow: overflow_tx };
+        let task = tokio::spawn(pump(job_id.to_string(), client, rx, stop_rx, readers, budget));
+        Self { stop: Some(stop_tx), task, limit, overflow }
+    }
+
+    /// Resolves with the limit once the output has exceeded it;
+    /// never resolves without a limit.
+    pub async fn limit_reached(&self) -> u64 {
+        let mut overflow = self.overflow.clone();
+        match self.limit {
+            Some(limit) if overflow.wait_for(|hit| *hit).await.is_ok() => limit,
+            _ => std::future::pending().await,
+        }
+    }
+
+    /// Wait for the pipes to close. After `grace` the capture stops
+    /// anyway: grandchildren that escaped the process group may
+    /// hold the pipes open indefinitely.
+    pub async fn finish(mut self, grace: Duration) -> OutputSummary {
+        tokio::select! {
+            summary = &mut self.task => return summary.unwrap_or_default(),
+            _ = tokio::time::sleep(grace) => {}
+        }
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        (&mut self.task).await.unwrap_or_default()
+    }
+}
+
+fn spawn_reader<R>(
+    pipe: Option<R>,
+    stream: Stream,
+    tx: mpsc::Sender<(Stream, Vec<u8>)>,
+) -> Option<JoinHandle<()>>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    let mut pipe = pipe?;
+    Some(tokio::spawn(async move {
+        let mut buf = vec![0u8; 8192];
+        loop {
+            match pipe.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if tx.send((stream, buf[..n].to_vec())).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }))
+}
+
+async fn pump(
+    job_id: String,
+    client: Arc<RwLock<AgentClient>>,
+    mut rx: mpsc::Receiver<(Stream, Vec<u8>)>,
+    mut stop: oneshot::Receiver<()>,
+    readers: [Option<JoinHandle<()>>; 2],
+    mut budget: Budget,
+) -> OutputSummary {
+    let mut stdout = StreamState::open(&job_id, Stream::Stdout).await;
+    let mut stderr = StreamState::open(&job_id, Stream::Stderr).await;
+
+    let wake = Arc::new(Notify::new());
+    let (done_tx, done_rx) = watch::channel(false);
+    let sender = ChunkSender {
+        job_id,
+        client,
+        seq: 0,
+        healthy: true,
+        streams: [stdout.reader(), stderr.reader()],
+    };
+    let sender = tokio::spawn(sender.run(Arc::clone(&wake), done_rx));
+
+    loop {
+        tokio::select! {
+            msg = rx.recv() => match msg {
+                Some((stream, mut bytes)) => {
+                    if !budget.admit(&mut bytes) {
+                        continue;
+                    }
+                    let state = if stream == Stream::Stdout { &mut stdout } else { &mut stderr };
+                    if state.append(&bytes).await {
+                        wake.notify_one();
+                    }
+                }
+                None => break,
+            },
+            _ = &mut stop => break,
+        }
+    }
+
+    for reader in readers.into_iter().flatten() {
+        reader.abort();
+    }
+    while let Ok((stream, mut bytes)) = rx.try_recv() {
+        if !budget.admit(&mut bytes) {
+            continue;
+        }
+        let state = if stream == Stream::Stdout { &mut stdout } else { &mut stderr };
+        state.append(&bytes).await;
+    }
+
+    // The sender drains what is left of the spools before they go
+    let _ = done_tx.send(true);
+    let (chunks, complete) = sender.await.unwrap_or((0, false));
+
+    let stdout_bytes = stdout.bytes;
+    let stderr_bytes = stderr.bytes;
+    OutputSummary {
+        stdout: stdout.finish().await,
+        stderr: stderr.finish().await,
+        stdout_bytes,
+        stderr_bytes,
+        chunks,
+        complete,
+        truncated: *budget.overflow.borrow(),
+    }
+}
+
+/// Enforces max_output_bytes across both streams.
+struct Budget {
+    limit: Option<u64>,
+    used: u64,
+    overflow: watch::Sender<bool>,
+}
+
+impl Budget {
+    /// Trim `bytes` to what is left of the limit. Returns false when
+    /// nothing is left to keep.
+    fn admit(&mut self, bytes: &mut Vec<u8>) -> bool {
+        if let Some(limit) = self.limit {
+            let room = limit.saturating_sub(self.used);
+            if bytes.len() as u64 > room {
+                bytes.truncate(room as usize);
+                self.overflow.send_replace(true);
+            }
+        }
+        self.used += bytes.len() as u64;
+        !bytes.is_empty()
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Chunk Delivery
+// ═══════════════════════════════════════════════════════════════
+
+struct ChunkSender {
+    job_id: String,
+    client: Arc<RwLock<AgentClient>>,
+    seq: u64,
+    /// Cleared on the first failed send. Streaming stops there; the
+    /// final result still carries the tail from the spool.
+    healthy: bool,
+    streams: [SpoolReader; 2],
+}
+
+impl ChunkSender {
+    /// Send spooled output until `done`, then whatever is left.
+    /// Returns the number of chunks and whether all output was sent.
+    async fn run(mut self, wake: Arc<Notify>, mut done: watch::Receiver<bool>) -> (u64, bool) {
+        let mut flush = interval(FLUSH_INTERVAL);
+        flush.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            let finishing = tokio::select! {
+                _ = flush.tick() => false,
+                _ = wake.notified() => false,
+                _ = done.wait_for(|done| *done) => true,
+            };
+            for i in 0..self.streams.len() {
+                self.send_pending(i).await;
+            }
+            if finishing {
+                break;
+            }
+        }
+
+        let complete = self.healthy
+            && self.streams.iter().all(|s| !s.broken && !s.progress.lost.load(Ordering::Relaxed));
+        (self.seq, complete)
+    }
+
+    async fn send_pending(&mut self, stream: usize) {
+        while self.healthy {
+            let Some(data) = self.streams[stream].read_chunk().await else { return };
+            let chunk = JobOutputChunk {
+                job_id: self.job_id.clone(),
+                seq: self.seq,
+                stream: self.streams[stream].stream.name().to_string(),
+                data,
+                timestamp: chrono::Utc::now().timestamp_millis(),
+            };
+            self.seq += 1;
+
+            if let Err(e) = self.client.read().await.send_job_output(chunk).await {
+                tracing::warn!("Job {} output streaming stopped: {}", self.job_id, e);
+                self.healthy = false;
+            }
+        }
+    }
+}
+
+/// How much of a stream is in its spool file, shared between the
+/// pipe side and the sender.
+#[derive(Default)]
+struct SpoolProgress {
+    written: AtomicU64,
+    /// The spool could not be created or written; streaming stops at
+    /// `written`.
+    lost: AtomicBool,
+}
+
+/// The sender's own read handle on a spool file.
+struct SpoolReader {
+    stream: Stream,
+    path: Option<PathBuf>,
+    file: Option<tokio::fs::File>,
+    offset: u64,
+    decoder: Utf8Carry,
+    progress: Arc<SpoolProgress>,
+    /// Reading the spool failed; nothing more is sent.
+    broken: bool,
+}
+
+impl SpoolReader {
+    /// Up to MAX_CHUNK bytes of not yet sent output, decoded; None
+    /// when there is nothing to send.
+    async fn read_chunk(&mut self) -> Option<String> {
+        loop {
+            let available = self.progress.written.load(Ordering::Acquire).saturating_sub(self.offset);
+            if available == 0 || self.broken {
+                return None;
+            }
+
+            let mut buf = vec![0u8; available.min(MAX_CHUNK as u64) as usize];
+            if let Err(e) = self.read_at(&mut buf).await {
+                tracing::warn!("Cannot read {} spool: {}", self.stream.name(), e);
+                self.broken = true;
+                return None;
+            }
+            self.offset += buf.len() as u64;
+
+            // Only the start of a multibyte character decodes to
+            // nothing; the next read completes it
+            let text = self.decoder.decode(&buf);
+            if !text.is_empty() {
+                return Some(text);
+            }
+        }
+    }
+
+    async fn read_at(&mut self, buf: &mut [u8]) -> std::io::Result<()> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => {
+                let path = self.path.as_ref().ok_or(std::io::ErrorKind::NotFound)?;
+                self.file.insert(tokio::fs::File::open(path).await?)
+            }
+        };
+        file.seek(std::io::SeekFrom::Start(self.offset)).await?;
+        file.read_exact(buf).await?;
+        Ok(())
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Spool
+// ═══════════════════════════════════════════════════════════════
+
+struct StreamState {
+    stream: Stream,
+    bytes: u64,
+    spool: Option<(PathBuf, tokio::fs::File)>,
+    progress: Arc<SpoolProgress>,
+    /// Bytes spooled when the sender was last woken.
+    notified_at: u64,
+    /// Used only when the spool file could not be created.
+    memory_tail: Vec<u8>,
+}
+
+impl StreamState {
+    async fn open(job_id: &str, stream: Stream) -> Self {
+        let progress = Arc::new(SpoolProgress::default());
+        let spool = match open_spool(job_id, stream).await {
+            Ok(spool) => Some(spool),
+            Err(e) => {
+                tracing::warn!("Cannot spool job {} {}, keeping tail in memory: {}", job_id, stream.name(), e);
+                progress.lost.store(true, Ordering::Relaxed);
+                None
+            }
+        };
+        Self {
+            stream,
+            bytes: 0,
+            spool,
+            progress,
+            notified_at: 0,
+            memory_tail: Vec::new(),
+        }
+    }
+
+    fn reader(&self) -> SpoolReader {
+        SpoolReader {
+            stream: self.stream,
+            path: self.spool.as_ref().map(|(path, _)| path.clone()),
+            file: None,
+            offset: 0,
+            decoder: Utf8Carry::default(),
+            progress: Arc::clone(&self.progress),
+            broken: false,
+        }
+    }
+
+    /// Returns true once MAX_CHUNK bytes are waiting for the sender.
+    async fn append(&mut self, bytes: &[u8]) -> bool {
+        self.bytes += bytes.len() as u64;
+
+        if let Some((path, file)) = &mut self.spool {
+            // Flushed so the sender's handle sees the bytes
+            if file.write_all(bytes).await.is_ok() && file.flush().await.is_ok() {
+                self.progress.written.store(self.bytes, Ordering::Release);
+                if self.bytes - self.notified_at >= MAX_CHUNK as u64 {
+                    self.notified_at = self.bytes;
+                    return true;
+                }
+                return false;
+            }
+            // Disk full or gone: keep what follows in memory instead
+            tracing::warn!("Spool write failed for {:?}, keeping tail in memory", path);
+            let _ = tokio::fs::remove_file(&path).await;
+            self.spool = None;
+            self.progress.lost.store(true, Ordering::Relaxed);
+        }
+
+        self.memory_tail.extend_from_slice(bytes);
+        let cap = RESULT_TAIL_BYTES as usize;
+        if self.memory_tail.len() > 2 * cap {
+            self.memory_tail.drain(..self.memory_tail.len() - cap);
+        }
+        false
+    }
+
+    /// The last RESULT_TAIL_BYTES of the stream; removes the spool file.
+    async fn finish(self) -> Option<String> {
+        let tail = match self.spool {
+            Some((path, file)) => {
+                let tail = read_tail(file, self.bytes).await.unwrap_or_default();
+                let _ = tokio::fs::remove_file(&path).await;
+                tail
+            }
+            None => {
+                let start = self.memory_tail.len().saturating_sub(RESULT_TAIL_BYTES as usize);
+                self.memory_tail[start..].to_vec()
+            }
+        };
+        if tail.is_empty() {
+            return None;
+        }
+
+        let omitted = self.bytes.saturating_sub(tail.len() as u64);
+        if omitted == 0 {
+            return Some(String::from_utf8_lossy(&tail).to_string());
+        }
+        // Do not start in the middle of a multibyte character
+        let start = tail.iter().position(|b| (b & 0xC0) != 0x80).unwrap_or(0);
+        Some(format!(
+            "[... {} bytes omitted ...]\n{}",
+            omitted + start as u64,
+            String::from_utf8_lossy(&tail[start..]),
+        ))
+    }
+}
+
+/// Spooled output often comes from root scripts and can hold secrets;
+/// the directory and files are readable by the agent's user only.
+async fn open_spool(job_id: &str, stream: Stream) -> std::io::Result<(PathBuf, tokio::fs::File)> {
+    let dir = AgentConfig::data_dir().join("spool");
+    let mut builder = tokio::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    builder.mode(0o700);
+    builder.create(&dir).await?;
+    #[cfg(unix)]
+    {
+        // Created world-readable by earlier versions
+        use std::os::unix::fs::PermissionsExt;
+        tokio::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700)).await?;
+    }
+
+    // Job IDs come from the server; keep them from naming other paths
+    let safe_id: String = job_id
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect();
+    let path = dir.join(format!("{}.{}", safe_id, stream.name()));
+
+    // A leftover file would keep its old mode; start from a new one
+    match tokio::fs::remove_file(&path).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut options = tokio::fs::OpenOptions::new();
+    options.create_new(true).read(true).write(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let file = options.open(&path).await?;
+    Ok((path, file))
+}
+
+async fn read_tail(mut file: tokio::fs::File, len: u64) -> std::io::Result<Vec<u8>> {
+    file.flush().await?;
+    let start = len.saturating_sub(RESULT_TAIL_BYTES);
+    file.seek(std::io::SeekFrom::Start(start)).await?;
+    let mut tail = Vec::with_capacity((len - start) as usize);
+    file.read_to_end(&mut tail).await?;
+    Ok(tail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn spool_reader(content: &[u8]) -> (SpoolReader, PathBuf) {
+        let path = std::env::temp_dir().join(format!("reap3r-spool-{}", uuid::Uuid::new_v4()));
+        tokio::fs::write(&path, content).await.unwrap();
+        let progress = Arc::new(SpoolProgress::default());
+        let reader = SpoolReader {
+            stream: Stream::Stdout,
+            path: Some(path.clone()),
+            file: None,
+            offset: 0,
+            decoder: Utf8Carry::default(),
+            progress,
+            broken: false,
+        };
+        (reader, path)
+    }
+
+    #[tokio::test]
+    async fn reads_only_what_was_written() {
+        let (mut reader, path) = spool_reader(b"hello world").await;
+        assert_eq!(reader.read_chunk().await, None);
+
+        reader.progress.written.store(5, Ordering::Release);
+        assert_eq!(reader.read_chunk().await.as_deref(), Some("hello"));
+        assert_eq!(reader.read_chunk().await, None);
+
+        reader.progress.written.store(11, Ordering::Release);
+        assert_eq!(reader.read_chunk().await.as_deref(), Some(" world"));
+        let _ = tokio::fs::remove_file(path).await;
+    }
+
+    #[tokio::test]
+    async fn splits_large_output_into_chunks() {
+        let content = vec![b'x'; MAX_CHUNK * 2 + 10];
+        let (mut reader, path) = spool_reader(&content).await;
+        reader.progress.written.store(content.len() as u64, Ordering::Release);
+
+        let mut sizes = Vec::new();
+        while let Some(chunk) = reader.read_chunk().await {
+            sizes.push(chunk.len());
+        }
+        assert_eq!(sizes, [MAX_CHUNK, MAX_CHUNK, 10]);
+        let _ = tokio::fs::remove_file(path).await;
+    }
+
+    #[tokio::test]
+    async fn keeps_multibyte_characters_whole() {
+        let (mut reader, path) = spool_reader("é€".as_bytes()).await;
+        // Written up to the middle of "é"
+        reader.progress.written.store(1, Ordering::Release);
+        assert_eq!(reader.read_chunk().await, None);
+
+        reader.progress.written.store(5, Ordering::Release);
+        assert_eq!(reader.read_chunk().await.as_deref(), Some("é€"));
+        let _ = tokio::fs::remove_file(path).await;
+    }
+
+    #[test]
+    fn budget_trims_past_the_limit() {
+        let (overflow, _rx) = watch::channel(false);
+        let mut budget = Budget { limit: Some(10), used: 0, overflow };
+        let mut first = vec![b'a'; 6];
+        let mut second = vec![b'b'; 6];
+        let mut third = vec![b'c'; 1];
+
+        assert!(budget.admit(&mut first));
+        assert!(budget.admit(&mut second));
+        assert_eq!(second.len(), 4);
+        assert!(*budget.overflow.borrow());
+        assert!(!budget.admit(&mut third));
+    }
+}
@@ -0,0 +1,225 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r Agent - Agent Policy
+// ─────────────────────────────────────────────────────────────
+//
+// The server hands out an AgentPolicy at enrollment and may change
+// it later (heartbeat response or a `policy` WebSocket frame). It
+// is persisted to <data_dir>/policy.json, apart from agent.toml,
+// so the server never rewrites local configuration. The local
+// `denied_job_types` list is checked first and always wins.
+// ─────────────────────────────────────────────────────────────
+
+use std::path::PathBuf;
+use std::sync::RwLock;
+use tokio::sync::watch;
+use tokio::time::{sleep, Duration};
+
+use crate::comms::protocol::{AgentPolicy, AlertRule};
+use crate::config::AgentConfig;
+
+/// Floor for server-supplied intervals, whatever the policy says.
+const MIN_INTERVAL_SEC: u64 = 5;
+
+pub struct PolicyStore {
+    path: PathBuf,
+    current: RwLock<AgentPolicy>,
+    denied: Vec<String>,
+    local_metrics_interval_sec: u64,
+    local_inventory_interval_sec: u64,
+    local_max_concurrent_jobs: usize,
+    changed: watch::Sender<()>,
+}
+
+/// Why a job type was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Denial {
+    /// Listed in the local `denied_job_types`.
+    Local,
+    /// Missing from the server's `allowed_job_types`.
+    Server,
+}
+
+impl Denial {
+    pub fn source(self) -> &'static str {
+        match self {
+            Denial::Local => "local_deny_list",
+            Denial::Server => "server_policy",
+        }
+    }
+
+    pub fn message(self, job_type: &str) -> String {
+        match self {
+            Denial::Local => format!("Job type {} is denied by this agent's local configuration", job_type),
+            Denial::Server => format!("Job type {} is not allowed by the agent policy", job_type),
+        }
+    }
+}
+
+impl PolicyStore {
+    /// Load the last policy received from the server, if any.
+    pub fn load(config: &AgentConfig) -> Self {
+        let path = AgentConfig::data_dir().join("policy.json");
+        let current = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring unreadable policy {:?}: {}", path, e);
+                AgentPolicy::default()
+            }),
+            Err(_) => AgentPolicy::default(),
+        };
+
+        if !config.denied_job_types.is_empty() {
+            tracing::info!("Locally denied job types: {}", config.denied_job_types.join(", "));
+        }
+
+        Self {
+            path,
+            current: RwLock::new(current),
+            denied: config.denied_job_types.clone(),
+            local_metrics_interval_sec: config.metrics_interval_sec,
+            local_inventory_interval_sec: config.inventory_interval_sec,
+            local_max_concurrent_jobs: config.max_concurrent_jobs,
+            changed: watch::channel(()).0,
+        }
+    }
+
+    /// Adopt a policy from the server. Returns false if nothing changed.
+    pub fn apply(&self, policy: AgentPolicy) -> bool {
+        {
+            let mut current = self.current.write().unwrap();
+            if *current == policy {
+                return false;
+            }
+            *current = policy.clone();
+        }
+
+        tracing::info!(
+            "Agent policy updated: allowed_job_types={:?} max_concurrent_jobs={:?} \
+             metrics_interval_sec={:?} inventory_interval_sec={:?} update_channel={:?} alert_rules={}",
+            policy.allowed_job_types, policy.max_concurrent_jobs,
+            policy.metrics_interval_sec, policy.inventory_interval_sec, policy.update_channel,
+            policy.alert_rules.as_ref().map_or(0, Vec::len),
+        );
+        if let Err(e) = self.persist(&policy) {
+            tracing::warn!("Failed to persist policy to {:?}: {}", self.path, e);
+        }
+        self.changed.send_replace(());
+        true
+    }
+
+    fn persist(&self, policy: &AgentPolicy) -> std::io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(policy)?)?;
+        std::fs::rename(&tmp, &self.path)
+    }
+
+    /// Whether a job of this type may run.
+    pub fn check(&self, job_type: &str) -> Result<(), Denial> {
+        if self.denied.iter().any(|pattern| matches_pattern(pattern, job_type)) {
+            return Err(Denial::Local);
+        }
+        match &self.current.read().unwrap().allowed_job_types {
+            Some(allowed) if !allowed.iter().any(|t| t == job_type) => Err(Denial::Server),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn metrics_interval(&self) -> Duration {
+        let sec = self.current.read().unwrap().metrics_interval_sec.unwrap_or(self.local_metrics_interval_sec);
+        Duration::from_secs(sec.max(MIN_INTERVAL_SEC))
+    }
+
+    pub fn inventory_interval(&self) -> Duration {
+        let sec = self.current.read().unwrap().inventory_interval_sec.unwrap_or(self.local_inventory_interval_sec);
+        Duration::from_secs(sec.max(MIN_INTERVAL_SEC))
+    }
+
+    pub fn max_concurrent_jobs(&self) -> usize {
+        self.current.read().unwrap().max_concurrent_jobs.unwrap_or(self.local_max_concurrent_jobs).max(1)
+    }
+
+    /// Release channel agent_update jobs must come from; None when
+    /// the policy does not pin one.
+    pub fn update_channel(&self) -> Option<String> {
+        self.current.read().unwrap().update_channel.clone()
+    }
+
+    pub fn alert_rules(&self) -> Vec<AlertRule> {
+        self.current.read().unwrap().alert_rules.clone().unwrap_or_default()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<()> {
+        self.changed.subscribe()
+    }
+
+    /// Sleep for `interval`, cut short if the policy changes meanwhile
+    /// so a new interval takes effect without waiting out the old one.
+    pub async fn sleep(&self, interval: Duration) {
+        let mut changes = self.subscribe();
+        tokio::select! {
+            _ = sleep(interval) => {}
+            _ = changes.changed() => {}
+        }
+    }
+}
+
+fn matches_pattern(pattern: &str, job_type: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => job_type.starts_with(prefix),
+        None => pattern == job_type,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(denied: &[&str], allowed: Option<&[&str]>) -> PolicyStore {
+        PolicyStore {
+            path: std::env::temp_dir().join("reap3r-policy-test.json"),
+            current: RwLock::new(AgentPolicy {
+                allowed_job_types: allowed.map(|a| a.iter().map(|t| t.to_string()).collect()),
+                ..AgentPolicy::default()
+            }),
+            denied: denied.iter().map(|t| t.to_string()).collect(),
+            local_metrics_interval_sec: 60,
+            local_inventory_interval_sec: 3600,
+            local_max_concurrent_jobs: 5,
+            changed: watch::channel(()).0,
+        }
+    }
+
+    #[test]
+    fn matches_exact_and_prefix_patterns() {
+        assert!(matches_pattern("reboot", "reboot"));
+        assert!(!matches_pattern("reboot", "reboot_now"));
+        assert!(matches_pattern("remote_desktop_*", "remote_desktop_start"));
+        assert!(!matches_pattern("remote_desktop_*", "remote_shell_start"));
+        assert!(matches_pattern("*", "run_script"));
+    }
+
+    #[test]
+    fn local_deny_list_wins_over_server_policy() {
+        let policy = store(&["run_script"], Some(&["run_script", "reboot"]));
+        assert_eq!(policy.check("run_script"), Err(Denial::Local));
+        assert_eq!(policy.check("reboot"), Ok(()));
+        assert_eq!(policy.check("shutdown"), Err(Denial::Server));
+    }
+
+    #[test]
+    fn without_allowed_list_everything_not_denied_runs() {
+        let policy = store(&["remote_*"], None);
+        assert_eq!(policy.check("remote_shell_start"), Err(Denial::Local));
+        assert_eq!(policy.check("process_list"), Ok(()));
+    }
+
+    #[test]
+    fn intervals_have_a_floor() {
+        let policy = store(&[], None);
+        policy.current.write().unwrap().metrics_interval_sec = Some(1);
+        assert_eq!(policy.metrics_interval(), Duration::from_secs(MIN_INTERVAL_SEC));
+        assert_eq!(policy.inventory_interval(), Duration::from_secs(3600));
+    }
+}
@@ -0,0 +1,450 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r Agent - Process Control
+// ─────────────────────────────────────────────────────────────
+//
+// process_kill: select processes by PID, name, executable path or
+// a command-line regex, refuse when more match than `max_matches`
+// allows, signal them (and optionally their descendants) natively,
+// then wait briefly to report which ones exited. The agent itself
+// and PID 1 are never signalled.
+//
+// process_list: a task-manager snapshot with CPU %, memory, owner
+// and command line, filtered, sorted and cut to the top N.
+// ─────────────────────────────────────────────────────────────
+
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use sysinfo::{Pid, Process, ProcessRefreshKind, ProcessStatus, System, UpdateKind, Users};
+use tokio::time::{sleep, Duration, Instant};
+
+use crate::comms::protocol::{JobRequest, JobResult, ProcessKillPayload, ProcessListPayload, ProcessSortKey};
+
+const DEFAULT_MAX_MATCHES: usize = 10;
+
+/// Upper bound for `max_matches`, whatever the job asks for.
+const MAX_MATCHES_CEILING: usize = 100;
+
+/// How long to watch signalled processes for exit.
+const EXIT_WAIT: Duration = Duration::from_secs(3);
+const EXIT_POLL: Duration = Duration::from_millis(100);
+
+const DEFAULT_LIST_LIMIT: usize = 50;
+const MAX_LIST_LIMIT: usize = 1000;
+
+/// Gap between the two refreshes CPU usage is computed from.
+const CPU_SAMPLE: Duration = Duration::from_millis(500);
+
+// ═══════════════════════════════════════════════════════════════
+// Job Handler
+// ═══════════════════════════════════════════════════════════════
+
+pub async fn process_kill(job: &JobRequest, payload: &ProcessKillPayload) -> Result<JobResult> {
+    let signal = platform::parse_signal(payload.signal.as_deref())?;
+    let selector = Selector::from_payload(payload)?;
+    let max_matches = payload.max_matches.unwrap_or(DEFAULT_MAX_MATCHES);
+    if max_matches == 0 || max_matches > MAX_MATCHES_CEILING {
+        bail!("max_matches must be between 1 and {}", MAX_MATCHES_CEILING);
+    }
+
+    let mut sys = System::new();
+    sys.refresh_processes_specifics(
+        ProcessRefreshKind::new()
+            .with_exe(UpdateKind::OnlyIfNotSet)
+            .with_cmd(UpdateKind::OnlyIfNotSet),
+    );
+
+    let own_pid = Pid::from_u32(std::process::id());
+    let protected = |pid: Pid| pid == own_pid || pid.as_u32() <= 1;
+
+    let mut matched: Vec<Pid> = sys.processes().values()
+        .filter(|p| p.thread_kind().is_none() && selector.matches(p))
+        .map(Process::pid)
+        .collect();
+    matched.sort();
+
+    if let Selector::Pid(pid) = selector {
+        if protected(Pid::from_u32(pid)) {
+            bail!("Refusing to signal PID {}", pid);
+        }
+    }
+    matched.retain(|pid| !protected(*pid));
+
+    if matched.is_empty() {
+        bail!("No process matches {}", selector.describe());
+    }
+    if matched.len() > max_matches {
+        bail!(
+            "{} processes match {}, more than max_matches ({}); nothing was signalled",
+            matched.len(), selector.describe(), max_matches,
+        );
+    }
+
+    // Parents before children so a supervisor cannot respawn them
+    let mut targets: Vec<(Pid, &'static str)> = matched.iter().map(|pid| (*pid, "match")).collect();
+    if payload.kill_children {
+        let seen: HashSet<Pid> = matched.iter().copied().collect();
+        for pid in descendants(&sys, &matched, &seen) {
+            if !protected(pid) {
+                targets.push((pid, "child"));
+            }
+        }
+    }
+
+    tracing::info!(
+        "Sending {} to {} process(es) matching {}",
+        platform::signal_name(signal), targets.len(), selector.describe(),
+    );
+
+    let mut outcomes: Vec<serde_json::Value> = Vec::new();
+    let mut delivered = Vec::new();
+    for (pid, role) in &targets {
+        let process = sys.process(*pid);
+        let error = platform::send(process, *pid, signal).err();
+        if error.is_none() {
+            delivered.push(*pid);
+        }
+        outcomes.push(serde_json::json!({
+            "pid": pid.as_u32(),
+            "name": process.map(|p| p.name()).unwrap_or_default(),
+            "role": role,
+            "signalled": error.is_none(),
+            "error": error,
+            "exited": null,
+        }));
+    }
+
+    if platform::terminates(signal) && !delivered.is_empty() {
+        let exited = wait_for_exit(&mut sys, &delivered).await;
+        for outcome in outcomes.iter_mut().filter(|o| o["signalled"] == true) {
+            let pid = Pid::from_u32(outcome["pid"].as_u64().unwrap_or_default() as u32);
+            outcome["exited"] = serde_json::json!(exited.contains(&pid));
+        }
+    }
+
+    let failed = targets.len() - delivered.len();
+    Ok(JobResult {
+        job_id: job.job_id.clone(),
+        status: if failed == 0 { "success" } else { "failed" }.to_string(),
+        started_at: 0,
+        completed_at: 0,
+        stdout: None,
+        stderr: None,
+        exit_code: None,
+        error_message: (failed > 0).then(|| format!("Failed to signal {} of {} processes", failed, targets.len())),
+        artifacts: Vec::new(),
+        result_data: Some(serde_json::json!({
+            "signal": platform::signal_name(signal),
+            "selector": selector.to_json(),
+            "matched": matched.len(),
+            "processes": outcomes,
+        })),
+    })
+}
+
+pub async fn process_list(job: &JobRequest, payload: &ProcessListPayload) -> Result<JobResult> {
+    let limit = payload.limit.unwrap_or(DEFAULT_LIST_LIMIT);
+    if limit == 0 || limit > MAX_LIST_LIMIT {
+        bail!("limit must be between 1 and {}", MAX_LIST_LIMIT);
+    }
+    let descending = payload.descending.unwrap_or(matches!(
+        payload.sort_by,
+        ProcessSortKey::Cpu | ProcessSortKey::Memory | ProcessSortKey::StartTime,
+    ));
+
+    let refresh = ProcessRefreshKind::new()
+        .with_cpu()
+        .with_memory()
+        .with_exe(UpdateKind::OnlyIfNotSet)
+        .with_cmd(UpdateKind::OnlyIfNotSet)
+        .with_user(UpdateKind::OnlyIfNotSet);
+    let mut sys = System::new();
+    sys.refresh_processes_specifics(refresh);
+    sleep(CPU_SAMPLE.max(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL)).await;
+    sys.refresh_processes_specifics(refresh);
+    sys.refresh_cpu_usage();
+
+    let users = Users::new_with_refreshed_list();
+    let user_of = |process: &Process| {
+        process.user_id()
+            .and_then(|uid| users.get_user_by_id(uid))
+            .map(|user| user.name().to_string())
+    };
+
+    let name_filter = payload.name.as_deref().map(str::to_lowercase).filter(|n| !n.is_empty());
+    let user_filter = payload.user.as_deref().filter(|u| !u.is_empty());
+
+    let processes: Vec<&Process> = sys.processes().values()
+        .filter(|p| p.thread_kind().is_none())
+        .collect();
+    let total = processes.len();
+
+    let mut rows: Vec<(&Process, Option<String>)> = processes.into_iter()
+        .filter(|p| name_filter.as_ref().is_none_or(|n| p.name().to_lowercase().contains(n)))
+        .map(|p| (p, user_of(p)))
+        .filter(|(_, user)| user_filter.is_none_or(|u| user.as_deref() == Some(u)))
+        .collect();
+    let matched = rows.len();
+
+    rows.sort_by(|(a, _), (b, _)| {
+        let order = match payload.sort_by {
+            ProcessSortKey::Cpu => a.cpu_usage().total_cmp(&b.cpu_usage()),
+            ProcessSortKey::Memory => a.memory().cmp(&b.memory()),
+            ProcessSortKey::Pid => a.pid().cmp(&b.pid()),
+            ProcessSortKey::Name => a.name().to_lowercase().cmp(&b.name().to_lowercase()),
+            ProcessSortKey::StartTime => a.start_time().cmp(&b.start_time()),
+        };
+        // Ties in PID order so repeated listings stay stable
+        let order = if descending { order.reverse() } else { order };
+        order.then_with(|| a.pid().cmp(&b.pid()))
+    });
+    rows.truncate(limit);
+
+    let entries: Vec<serde_json::Value> = rows.iter()
+        .map(|(p, user)| process_json(p, user.as_deref()))
+        .collect();
+
+    Ok(JobResult {
+        job_id: job.job_id.clone(),
+        status: "success".to_string(),
+        started_at: 0,
+        completed_at: 0,
+        stdout: None,
+        stderr: None,
+        exit_code: None,
+        error_message: None,
+        artifacts: Vec::new(),
+        result_data: Some(serde_json::json!({
+            "total": total,
+            "matched": matched,
+            "returned": entries.len(),
+            "sort_by": payload.sort_by,
+            "descending": descending,
+            "cpu_count": sys.cpus().len(),
+            "processes": entries,
+        })),
+    })
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Listing
+// ═══════════════════════════════════════════════════════════════
+
+/// One task-manager row. `cpu_percent` is per core, as in top: a
+/// process busy on two cores reports 200.
+fn process_json(process: &Process, user: Option<&str>) -> serde_json::Value {
+    let cmd = process.cmd();
+    serde_json::json!({
+        "pid": process.pid().as_u32(),
+        "ppid": process.parent().map(|p| p.as_u32()),
+        "name": process.name(),
+        "user": user,
+        "cmdline": (!cmd.is_empty()).then(|| cmd.join(" ")),
+        "exe": process.exe().map(|exe| exe.display().to_string()),
+        "start_time": process.start_time(),
+        "cpu_percent": (process.cpu_usage() * 10.0).round() / 10.0,
+        "memory_bytes": process.memory(),
+        "virtual_memory_bytes": process.virtual_memory(),
+        "status": process.status().to_string(),
+    })
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Selection
+// ═══════════════════════════════════════════════════════════════
+
+enum Selector {
+    Pid(u32),
+    Name(String),
+    ExePath(String),
+    Cmdline(Regex),
+}
+
+impl Selector {
+    fn from_payload(payload: &ProcessKillPayload) -> Result<Self> {
+        let mut selectors = Vec::new();
+        if let Some(pid) = payload.pid {
+            selectors.push(Selector::Pid(pid));
+        }
+        if let Some(name) = &payload.name {
+            selectors.push(Selector::Name(name.clone()));
+        }
+        if let Some(path) = &payload.exe_path {
+            selectors.push(Selector::ExePath(path.clone()));
+        }
+        if let Some(pattern) = &payload.cmdline_pattern {
+            let regex = Regex::new(pattern)
+                .with_context(|| format!("Invalid cmdline_pattern: {}", pattern))?;
+            selectors.push(Selector::Cmdline(regex));
+        }
+
+        match selectors.len() {
+            1 => Ok(selectors.remove(0)),
+            0 => bail!("One of pid, name, exe_path or cmdline_pattern is required"),
+            _ => bail!("Only one of pid, name, exe_path or cmdline_pattern may be given"),
+        }
+    }
+
+    fn matches(&self, process: &Process) -> bool {
+        match self {
+            Selector::Pid(pid) => process.pid().as_u32() == *pid,
+            // The kernel truncates names (15 bytes on Linux); also
+            // compare the executable's file name
+            Selector::Name(name) => {
+                same_name(process.name(), name)
+                    || process.exe()
+                        .and_then(|exe| exe.file_name())
+                        .is_some_and(|file| same_name(&file.to_string_lossy(), name))
+            }
+            Selector::ExePath(path) => process.exe().is_some_and(|exe| exe == std::path::Path::new(path)),
+            Selector::Cmdline(regex) => {
+                let cmd = process.cmd();
+                !cmd.is_empty() && regex.is_match(&cmd.join(" "))
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Selector::Pid(pid) => format!("pid {}", pid),
+            Selector::Name(name) => format!("name {:?}", name),
+            Selector::ExePath(path) => format!("exe_path {:?}", path),
+            Selector::Cmdline(regex) => format!("cmdline_pattern {:?}", regex.as_str()),
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            Selector::Pid(pid) => serde_json::json!({ "pid": pid }),
+            Selector::Name(name) => serde_json::json!({ "name": name }),
+            Selector::ExePath(path) => serde_json::json!({ "exe_path": path }),
+            Selector::Cmdline(regex) => serde_json::json!({ "cmdline_pattern": regex.as_str() }),
+        }
+    }
+}
+
+/// Windows process names are case-insensitive and carry `.exe`.
+fn same_name(actual: &str, wanted: &str) -> bool {
+    if cfg!(target_os = "windows") {
+        let strip = |s: &str| s.to_ascii_lowercase().trim_end_matches(".exe").to_string();
+        strip(actual) == strip(wanted)
+    } else {
+        actual == wanted
+    }
+}
+
+/// Every descendant of `roots`, breadth first.
+fn descendants(sys: &System, roots: &[Pid], exclude: &HashSet<Pid>) -> Vec<Pid> {
+    let mut children: HashMap<Pid, Vec<Pid>> = HashMap::new();
+    for process in sys.processes().values().filter(|p| p.thread_kind().is_none()) {
+        if let Some(parent) = process.parent() {
+            children.entry(parent).or_default().push(process.pid());
+        }
+    }
+
+    let mut found = Vec::new();
+    let mut seen = exclude.clone();
+    let mut queue: std::collections::VecDeque<Pid> = roots.iter().copied().collect();
+    while let Some(pid) = queue.pop_front() {
+        for child in children.get(&pid).into_iter().flatten() {
+            if seen.insert(*child) {
+                found.push(*child);
+                queue.push_back(*child);
+            }
+        }
+    }
+    found
+}
+
+/// PIDs among `pids` that are gone (or zombies) within EXIT_WAIT.
+async fn wait_for_exit(sys: &mut System, pids: &[Pid]) -> HashSet<Pid> {
+    let deadline = Instant::now() + EXIT_WAIT;
+    let mut exited = HashSet::new();
+    loop {
+        for pid in pids {
+            let alive = sys.refresh_process_specifics(*pid, ProcessRefreshKind::new())
+                && sys.process(*pid).is_some_and(|p| p.status() != ProcessStatus::Zombie);
+            if !alive {
+                exited.insert(*pid);
+            }
+        }
+        if exited.len() == pids.len() || Instant::now() >= deadline {
+            return exited;
+        }
+        sleep(EXIT_POLL).await;
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Signals
+// ═══════════════════════════════════════════════════════════════
+
+#[cfg(unix)]
+mod platform {
+    use anyhow::{Result, bail};
+    use nix::sys::signal::{kill, Signal};
+    use sysinfo::{Pid, Process};
+
+    /// Accepts `SIGTERM`, `TERM`, `term` or a number; defaults to SIGKILL.
+    pub fn parse_signal(name: Option<&str>) -> Result<Signal> {
+        let Some(name) = name.map(str::trim).filter(|n| !n.is_empty()) else {
+            return Ok(Signal::SIGKILL);
+        };
+        if let Ok(number) = name.parse::<i32>() {
+            return Signal::try_from(number).map_err(|_| anyhow::anyhow!("Unknown signal number: {}", number));
+        }
+        let upper = name.to_ascii_uppercase();
+        let full = if upper.starts_with("SIG") { upper } else { format!("SIG{}", upper) };
+        match full.parse::<Signal>() {
+            Ok(signal) => Ok(signal),
+            Err(_) => bail!("Unknown signal: {}", name),
+        }
+    }
+
+    pub fn signal_name(signal: Signal) -> &'static str {
+        signal.as_str()
+    }
+
+    /// Signals after which the process is expected to be gone.
+    pub fn terminates(signal: Signal) -> bool {
+        matches!(signal, Signal::SIGKILL | Signal::SIGTERM | Signal::SIGINT | Signal::SIGQUIT | Signal::SIGABRT)
+    }
+
+    pub fn send(_process: Option<&Process>, pid: Pid, signal: Signal) -> Result<(), String> {
+        kill(nix::unistd::Pid::from_raw(pid.as_u32() as i32), signal).map_err(|e| e.desc().to_string())
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use anyhow::{Result, bail};
+    use sysinfo::{Pid, Process};
+
+    /// Windows can only terminate a process outright.
+    #[derive(Debug, Clone, Copy)]
+    pub struct Terminate;
+
+    pub fn parse_signal(name: Option<&str>) -> Result<Terminate> {
+        match name.map(|n| n.trim().to_ascii_uppercase()).as_deref() {
+            None | Some("") | Some("KILL") | Some("SIGKILL") | Some("9")
+            | Some("TERM") | Some("SIGTERM") | Some("15") => Ok(Terminate),
+            Some(other) => bail!("Signal {} is not supported on Windows", other),
+        }
+    }
+
+    pub fn signal_name(_signal: Terminate) -> &'static str {
+        "SIGKILL"
+    }
+
+    pub fn terminates(_signal: Terminate) -> bool {
+        true
+    }
+
+    pub fn send(process: Option<&Process>, _pid: Pid, _signal: Terminate) -> Result<(), String> {
+        match process.map(|p| p.kill()) {
+            Some(true) => Ok(()),
+            Some(false) => Err("TerminateProcess failed".to_string()),
+            None => Err("Process not found".to_string()),
+        }
+    }
+}
@@ -0,0 +1,183 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r Agent - Kernel Statistics (/proc)
+// ─────────────────────────────────────────────────────────────
+//
+// Host counters sysinfo does not expose: per-device disk I/O,
+// pressure stall information, file descriptor usage, context
+// switches and the CPU time split. Linux only; elsewhere every
+// reader returns nothing and the metrics omit those fields.
+// ─────────────────────────────────────────────────────────────
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+
+use crate::comms::protocol::{PressureLine, PressureStall};
+
+/// Cumulative I/O of one block device.
+pub struct DiskStats {
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+}
+
+/// Aggregate CPU time from the `cpu` line of /proc/stat, in ticks.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpuTimes {
+    pub total: u64,
+    pub iowait: u64,
+    pub steal: u64,
+}
+
+/// The parts of /proc/stat the metrics use.
+pub struct KernelStat {
+    pub cpu: CpuTimes,
+    pub context_switches: u64,
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use super::*;
+    use std::path::Path;
+
+    /// /proc/diskstats counts 512-byte sectors whatever the device.
+    const SECTOR_SIZE: u64 = 512;
+
+    /// Cumulative I/O of every block device, by kernel name (sda1, dm-0).
+    pub fn diskstats() -> HashMap<String, DiskStats> {
+        let Ok(content) = std::fs::read_to_string("/proc/diskstats") else {
+            return HashMap::new();
+        };
+        content.lines().filter_map(|line| {
+            // major minor name reads merged sectors_read ms writes merged sectors_written ...
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let sectors = |i: usize| fields.get(i)?.parse::<u64>().ok();
+            Some((fields.get(2)?.to_string(), DiskStats {
+                read_bytes: sectors(5)? * SECTOR_SIZE,
+                write_bytes: sectors(9)? * SECTOR_SIZE,
+            }))
+        }).collect()
+    }
+
+    /// Device families that are not physical disks, or that stack on
+    /// top of one (counting them would count the same I/O twice).
+    const VIRTUAL_DISKS: &[&str] = &["loop", "ram", "zram", "dm-", "md", "sr", "fd", "nbd"];
+
+    /// I/O summed over whole physical disks (partitions excluded).
+    pub fn disk_totals() -> Option<DiskStats> {
+        let stats = diskstats();
+        if stats.is_empty() {
+            return None;
+        }
+        let mut total = DiskStats { read_bytes: 0, write_bytes: 0 };
+        for (name, disk) in stats {
+            let whole_disk = Path::new("/sys/block").join(&name).exists();
+            if whole_disk && !VIRTUAL_DISKS.iter().any(|prefix| name.starts_with(prefix)) {
+                total.read_bytes += disk.read_bytes;
+                total.write_bytes += disk.write_bytes;
+            }
+        }
+        Some(total)
+    }
+
+    /// Kernel name of the device behind a mount's source, following
+    /// symlinks such as /dev/mapper/root -> /dev/dm-0. None for
+    /// sources that are not block devices (tmpfs, overlay).
+    pub fn device_name(source: &OsStr) -> Option<String> {
+        let path = Path::new(source);
+        if !path.starts_with("/dev") {
+            return None;
+        }
+        let resolved = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        resolved.file_name().map(|name| name.to_string_lossy().to_string())
+    }
+
+    /// /proc/pressure/<resource>, None when PSI is not enabled.
+    pub fn pressure(resource: &str) -> Option<PressureStall> {
+        let content = std::fs::read_to_string(format!("/proc/pressure/{}", resource)).ok()?;
+        let mut stall = PressureStall { some: None, full: None };
+        for line in content.lines() {
+            // some avg10=0.12 avg60=0.05 avg300=0.01 total=123456
+            let mut parts = line.split_whitespace();
+            let kind = parts.next();
+            let values: HashMap<&str, &str> = parts.filter_map(|p| p.split_once('=')).collect();
+            let float = |key: &str| values.get(key).and_then(|v| v.parse::<f64>().ok());
+            let parsed = (|| Some(PressureLine {
+                avg10: float("avg10")?,
+                avg60: float("avg60")?,
+                avg300: float("avg300")?,
+                total_us: values.get("total")?.parse().ok()?,
+            }))();
+            match kind {
+                Some("some") => stall.some = parsed,
+                Some("full") => stall.full = parsed,
+                _ => {}
+            }
+        }
+        stall.some.is_some().then_some(stall)
+    }
+
+    /// (allocated, max) file handles from /proc/sys/fs/file-nr.
+    pub fn file_descriptors() -> Option<(u64, u64)> {
+        let content = std::fs::read_to_string("/proc/sys/fs/file-nr").ok()?;
+        let fields: Vec<u64> = content.split_whitespace().filter_map(|f| f.parse().ok()).collect();
+        match fields.as_slice() {
+            // allocated, allocated-but-unused (always 0 since 2.6), max
+            [allocated, unused, max] => Some((allocated.saturating_sub(*unused), *max)),
+            _ => None,
+        }
+    }
+
+    pub fn kernel_stat() -> Option<KernelStat> {
+        let content = std::fs::read_to_string("/proc/stat").ok()?;
+        let mut cpu = None;
+        let mut context_switches = None;
+        for line in content.lines() {
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                // cpu user nice system idle iowait irq softirq steal guest guest_nice
+                Some("cpu") => {
+                    let ticks: Vec<u64> = fields.filter_map(|f| f.parse().ok()).collect();
+                    // guest time is already included in user and nice
+                    cpu = Some(CpuTimes {
+                        total: ticks.iter().take(8).sum(),
+                        iowait: ticks.get(4).copied().unwrap_or(0),
+                        steal: ticks.get(7).copied().unwrap_or(0),
+                    });
+                }
+                Some("ctxt") => context_switches = fields.next().and_then(|f| f.parse().ok()),
+                _ => {}
+            }
+        }
+        Some(KernelStat { cpu: cpu?, context_switches: context_switches? })
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use super::*;
+
+    pub fn diskstats() -> HashMap<String, DiskStats> {
+        HashMap::new()
+    }
+
+    pub fn disk_totals() -> Option<DiskStats> {
+        None
+    }
+
+    pub fn device_name(_source: &OsStr) -> Option<String> {
+        None
+    }
+
+    pub fn pressure(_resource: &str) -> Option<PressureStall> {
+        None
+    }
+
+    pub fn file_descriptors() -> Option<(u64, u64)> {
+        None
+    }
+
+    pub fn kernel_stat() -> Option<KernelStat> {
+        None
+    }
+}
+
+pub use imp::{device_name, disk_totals, diskstats, file_descriptors, kernel_stat, pressure};
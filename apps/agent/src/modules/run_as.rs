@@ -0,0 +1,186 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r Agent - Script Identity (run_as)
+// ─────────────────────────────────────────────────────────────
+//
+// run_script normally runs as the agent's own user. With `run_as`
+// the script runs as that local account instead: the child drops
+// to the user's uid, primary gid and supplementary groups before
+// exec and gets a login-like environment (HOME, USER, LOGNAME,
+// SHELL, PATH) rather than the agent's. Only accounts listed in
+// `run_as_allowed_users` may be used. Linux only.
+// ─────────────────────────────────────────────────────────────
+
+use anyhow::{Result, bail};
+use tokio::process::Command;
+
+use crate::config::AgentConfig;
+
+/// Agent environment variables a run_as script still inherits.
+#[cfg(target_os = "linux")]
+const KEPT_ENV: &[&str] = &["LANG", "LC_ALL", "LC_CTYPE", "TZ"];
+
+#[cfg(target_os = "linux")]
+const USER_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+#[cfg(target_os = "linux")]
+const ROOT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// The account a script runs as.
+#[derive(Debug, Clone)]
+pub struct Identity {
+    pub user: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub groups: Vec<u32>,
+    pub home: String,
+    pub shell: String,
+    /// False when the script simply runs as the agent's own user.
+    switched: bool,
+}
+
+impl Identity {
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "user": self.user,
+            "uid": self.uid,
+            "gid": self.gid,
+            "groups": self.groups,
+            "switched": self.switched,
+        })
+    }
+
+    /// Whether the caller must configure `cmd` with `apply`.
+    pub fn is_switched(&self) -> bool {
+        self.switched
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Linux
+// ═══════════════════════════════════════════════════════════════
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use super::*;
+    use anyhow::Context;
+    use nix::unistd::{getgrouplist, setgid, setgroups, setuid, Gid, Uid, User};
+    use std::ffi::CString;
+
+    /// The agent's own effective user.
+    pub fn current() -> Identity {
+        let uid = Uid::effective();
+        let gid = Gid::effective();
+        let user = User::from_uid(uid).ok().flatten();
+        Identity {
+            user: user.as_ref().map(|u| u.name.clone()).unwrap_or_else(|| uid.to_string()),
+            uid: uid.as_raw(),
+            gid: gid.as_raw(),
+            groups: nix::unistd::getgroups()
+                .map(|groups| groups.into_iter().map(Gid::as_raw).collect())
+                .unwrap_or_default(),
+            home: user.as_ref().map(|u| u.dir.display().to_string()).unwrap_or_default(),
+            shell: user.as_ref().map(|u| u.shell.display().to_string()).unwrap_or_default(),
+            switched: false,
+        }
+    }
+
+    pub fn resolve(name: &str, config: &AgentConfig) -> Result<Identity> {
+        if !config.run_as_allowed_users.iter().any(|allowed| allowed == name) {
+            bail!("run_as user {} is not in this agent's run_as_allowed_users", name);
+        }
+
+        let user = User::from_name(name)
+            .with_context(|| format!("Failed to look up user {}", name))?
+            .with_context(|| format!("Unknown local user: {}", name))?;
+
+        let own = Uid::effective();
+        if user.uid == own {
+            return Ok(Identity { switched: false, ..current() });
+        }
+        if !own.is_root() {
+            bail!("Agent is not running as root and cannot switch to user {}", name);
+        }
+
+        let c_name = CString::new(name).context("User name contains a NUL byte")?;
+        let groups = getgrouplist(&c_name, user.gid)
+            .with_context(|| format!("Failed to list groups of {}", name))?;
+
+        Ok(Identity {
+            user: user.name,
+            uid: user.uid.as_raw(),
+            gid: user.gid.as_raw(),
+            groups: groups.into_iter().map(Gid::as_raw).collect(),
+            home: user.dir.display().to_string(),
+            shell: user.shell.display().to_string(),
+            switched: true,
+        })
+    }
+
+    pub fn apply(identity: &Identity, cmd: &mut Command) {
+        let uid = Uid::from_raw(identity.uid);
+        let gid = Gid::from_raw(identity.gid);
+        let groups: Vec<Gid> = identity.groups.iter().copied().map(Gid::from_raw).collect();
+
+        cmd.env_clear();
+        for key in KEPT_ENV {
+            if let Ok(value) = std::env::var(key) {
+                cmd.env(key, value);
+            }
+        }
+        cmd.env("HOME", &identity.home)
+            .env("USER", &identity.user)
+            .env("LOGNAME", &identity.user)
+            .env("SHELL", if identity.shell.is_empty() { "/bin/sh" } else { &identity.shell })
+            .env("PATH", if uid.is_root() { ROOT_PATH } else { USER_PATH });
+
+        // Groups must be set while still privileged, and the uid last.
+        // std's own uid()/gid() would clear the supplementary groups.
+        unsafe {
+            cmd.pre_exec(move || {
+                setgroups(&groups)?;
+                setgid(gid)?;
+                setuid(uid)?;
+                Ok(())
+            });
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use super::*;
+
+    pub fn current() -> Identity {
+        let user = std::env::var("USERNAME").or_else(|_| std::env::var("USER")).unwrap_or_default();
+        Identity {
+            user,
+            uid: 0,
+            gid: 0,
+            groups: Vec::new(),
+            home: String::new(),
+            shell: String::new(),
+            switched: false,
+        }
+    }
+
+    pub fn resolve(_name: &str, _config: &AgentConfig) -> Result<Identity> {
+        bail!("run_as is only supported on Linux agents")
+    }
+
+    pub fn apply(_identity: &Identity, _cmd: &mut Command) {}
+}
+
+/// Identity for a run_script job: the agent's own user, or the
+/// validated `run_as` account.
+pub fn resolve(run_as: Option<&str>, config: &AgentConfig) -> Result<Identity> {
+    match run_as {
+        Some(name) if !name.is_empty() => imp::resolve(name, config),
+        _ => Ok(imp::current()),
+    }
+}
+
+/// Make `cmd` run as `identity`. No-op unless the user is switched.
+pub fn apply(identity: &Identity, cmd: &mut Command) {
+    if identity.is_switched() {
+        imp::apply(identity, cmd);
+    }
+}
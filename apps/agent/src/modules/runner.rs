@@ -2,27 +2,307 @@
 // MASSVISION Reap3r Agent - Job Runner
 // ─────────────────────────────────────────────────────────────
 
-use anyhow::{Result, Context};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use tokio::process::Command;
-use tokio::time::{timeout, Duration};
-use crate::comms::protocol::{JobRequest, JobResult};
+use tokio::sync::{watch, Notify, RwLock};
+use tokio::time::{sleep, Duration};
+use crate::comms::client::AgentClient;
+use crate::comms::protocol::{
+    JobPayload, JobRequest, JobResult, PayloadError, PowerPayload,
+    RemoteShellStartPayload, RemoteShellStopPayload, RunScriptPayload, ScriptLanguage,
+    ServiceActionPayload,
+};
+use crate::config::AgentConfig;
+use crate::modules::artifacts;
+use crate::modules::limits::{self, Exceeded, Sandbox};
+use crate::modules::output::{OutputCapture, OutputSummary};
+use crate::modules::policy::{Denial, PolicyStore};
+use crate::modules::process;
+use crate::modules::run_as;
+use crate::modules::shell::ShellManager;
+use crate::modules::history::{self, MetricsHistory};
+use crate::modules::signing::{self, Rejection};
+use crate::modules::updater;
+use crate::modules::wol;
+
+/// Agent state that job handlers may need.
+pub struct JobContext {
+    pub client: Arc<RwLock<AgentClient>>,
+    pub config: Arc<AgentConfig>,
+    pub shells: Arc<ShellManager>,
+    pub policy: Arc<PolicyStore>,
+    pub history: Arc<MetricsHistory>,
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Executor
+// ═══════════════════════════════════════════════════════════════
+
+/// Runs jobs concurrently up to `max_concurrent_jobs`, never running
+/// two jobs of the same exclusive group at once.
+pub struct JobExecutor {
+    ctx: Arc<JobContext>,
+    slots: Slots,
+    /// Accepted jobs (queued or running), keyed by job ID.
+    jobs: Mutex<HashMap<String, JobSlot>>,
+    groups: Mutex<HashMap<&'static str, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+struct JobSlot {
+    running: bool,
+    cancel: watch::Sender<bool>,
+}
+
+/// Fires when the server cancels a job.
+#[derive(Clone)]
+pub struct CancelSignal(watch::Receiver<bool>);
+
+impl CancelSignal {
+    /// A signal that never fires, for jobs run outside the executor.
+    pub fn never() -> Self {
+        Self(watch::channel(false).1)
+    }
+
+    pub async fn cancelled(&mut self) {
+        if self.0.wait_for(|cancelled| *cancelled).await.is_err() {
+            // Sender gone without a cancel: never fires
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+impl JobExecutor {
+    pub fn new(ctx: Arc<JobContext>) -> Self {
+        let limit = ctx.policy.max_concurrent_jobs();
+        Self {
+            ctx,
+            slots: Slots::new(limit),
+            jobs: Mutex::new(HashMap::new()),
+            groups: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Register a job before it is spawned. Returns false if the same
+    /// job ID is already queued or running, as happens when the server
+    /// re-sends a job over another channel.
+    pub fn accept(&self, job: &JobRequest) -> bool {
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.contains_key(&job.job_id) {
+            tracing::debug!("Job {} already accepted, ignoring duplicate", job.job_id);
+            return false;
+        }
+        jobs.insert(job.job_id.clone(), JobSlot {
+            running: false,
+            cancel: watch::channel(false).0,
+        });
+        true
+    }
+
+    /// Ask a queued or running job to stop. Returns false if the job
+    /// is unknown (already finished or never received).
+    pub fn cancel(&self, job_id: &str) -> bool {
+        match self.jobs.lock().unwrap().get(job_id) {
+            Some(slot) => {
+                tracing::info!("Cancelling job {}", job_id);
+                slot.cancel.send_replace(true);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Run an accepted job once a slot (and its exclusive group) is free.
+    pub async fn run(&self, job: &JobRequest) -> JobResult {
+        let mut cancel = self.jobs.lock().unwrap()
+            .get(&job.job_id)
+            .map(|slot| CancelSignal(slot.cancel.subscribe()))
+            .unwrap_or_else(CancelSignal::never);
+
+        if let Err(denial) = self.ctx.policy.check(&job.job_type) {
+            self.jobs.lock().unwrap().remove(&job.job_id);
+            tracing::warn!("Refusing job {}: {}", job.job_id, denial.message(&job.job_type));
+            return JobRunner::denied_result(job, denial);
+        }
+
+        // Take the group lock before a slot so queued exclusive jobs
+        // do not sit on capacity other jobs could use
+        let turn = async {
+            let group_guard = match exclusive_group(&job.job_type) {
+                Some(name) => Some(self.group_lock(name).lock_owned().await),
+                None => None,
+            };
+            let permit = self.slots.acquire().await;
+            (group_guard, permit)
+        };
+
+        let _turn = tokio::select! {
+            turn = turn => turn,
+            _ = cancel.cancelled() => {
+                self.jobs.lock().unwrap().remove(&job.job_id);
+                let now = chrono::Utc::now().timestamp();
+                let mut result = JobRunner::cancelled_result(job, None, None, None);
+                result.started_at = now;
+                result.completed_at = now;
+                return result;
+            }
+        };
+
+        if let Some(slot) = self.jobs.lock().unwrap().get_mut(&job.job_id) {
+            slot.running = true;
+        }
+        tracing::info!("Running job {} (type={})", job.job_id, job.job_type);
+
+        let result = JobRunner::execute(job, &self.ctx, cancel).await;
+
+        self.jobs.lock().unwrap().remove(&job.job_id);
+        result
+    }
+
+    /// IDs of the jobs currently executing, for heartbeats.
+    pub fn active_jobs(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.jobs.lock().unwrap()
+            .iter()
+            .filter(|(_, slot)| slot.running)
+            .map(|(id, _)| id.clone())
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    /// True while fewer jobs are accepted than the limit, so polling
+    /// does not pull work the agent cannot start.
+    pub fn has_capacity(&self) -> bool {
+        self.jobs.lock().unwrap().len() < self.slots.limit()
+    }
+
+    /// Follow `max_concurrent_jobs` as the policy changes. Lowering it
+    /// lets running jobs finish; new ones wait until under the limit.
+    pub async fn track_policy(self: Arc<Self>) {
+        let mut changes = self.ctx.policy.subscribe();
+        while changes.changed().await.is_ok() {
+            let limit = self.ctx.policy.max_concurrent_jobs();
+            if self.slots.set_limit(limit) {
+                tracing::info!("Concurrent job limit is now {}", limit);
+            }
+        }
+    }
+
+    fn group_lock(&self, name: &'static str) -> Arc<tokio::sync::Mutex<()>> {
+        Arc::clone(self.groups.lock().unwrap().entry(name).or_default())
+    }
+}
+
+/// Counting semaphore whose size can change while jobs hold slots.
+struct Slots {
+    state: Mutex<SlotCount>,
+    freed: Notify,
+}
+
+struct SlotCount {
+    running: usize,
+    limit: usize,
+}
+
+struct SlotPermit<'a> {
+    slots: &'a Slots,
+}
+
+impl Slots {
+    fn new(limit: usize) -> Self {
+        Self {
+            state: Mutex::new(SlotCount { running: 0, limit: limit.max(1) }),
+            freed: Notify::new(),
+        }
+    }
+
+    async fn acquire(&self) -> SlotPermit<'_> {
+        loop {
+            // Register before checking so a release in between is not missed
+            let freed = self.freed.notified();
+            tokio::pin!(freed);
+            freed.as_mut().enable();
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.running < state.limit {
+                    state.running += 1;
+                    return SlotPermit { slots: self };
+                }
+            }
+            freed.await;
+        }
+    }
+
+    fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+
+    /// Returns false if the limit was already `limit`.
+    fn set_limit(&self, limit: usize) -> bool {
+        let limit = limit.max(1);
+        let changed = std::mem::replace(&mut self.state.lock().unwrap().limit, limit) != limit;
+        self.freed.notify_waiters();
+        changed
+    }
+}
+
+impl Drop for SlotPermit<'_> {
+    fn drop(&mut self) {
+        self.slots.state.lock().unwrap().running -= 1;
+        self.slots.freed.notify_waiters();
+    }
+}
+
+/// Job types that must not overlap with others of the same group.
+fn exclusive_group(job_type: &str) -> Option<&'static str> {
+    match job_type {
+        // Each of these ends with the agent (or host) going away
+        "reboot" | "shutdown" | "agent_update" => Some("power"),
+        "service_restart" | "service_stop" | "service_start" => Some("service"),
+        _ => None,
+    }
+}
 
 pub struct JobRunner;
 
 impl JobRunner {
     /// Execute a job based on its type and payload.
-    pub async fn execute(job: &JobRequest) -> JobResult {
+    pub async fn execute(job: &JobRequest, ctx: &JobContext, cancel: CancelSignal) -> JobResult {
         let started_at = chrono::Utc::now().timestamp();
 
-        let result = match job.job_type.as_str() {
-            "run_script" => Self::run_script(job).await,
-            "reboot" => Self::reboot(job).await,
-            "shutdown" => Self::shutdown(job).await,
-            "service_restart" => Self::service_action(job, "restart").await,
-            "service_stop" => Self::service_action(job, "stop").await,
-            "service_start" => Self::service_action(job, "start").await,
-            "process_kill" => Self::process_kill(job).await,
-            _ => Err(anyhow::anyhow!("Unsupported job type: {}", job.job_type)),
+        let payload = match job.parse_payload() {
+            Ok(payload) => payload,
+            Err(e) => return Self::rejected(job, started_at, e),
+        };
+
+        let result = match payload {
+            JobPayload::RunScript(p) => Self::run_script(job, &p, ctx, cancel).await,
+            JobPayload::Reboot(p) => Self::reboot(job, &p).await,
+            JobPayload::Shutdown(p) => Self::shutdown(job, &p).await,
+            JobPayload::ServiceRestart(p) => Self::service_action(job, &p, "restart").await,
+            JobPayload::ServiceStop(p) => Self::service_action(job, &p, "stop").await,
+            JobPayload::ServiceStart(p) => Self::service_action(job, &p, "start").await,
+            JobPayload::ProcessKill(p) => process::process_kill(job, &p).await,
+            JobPayload::ProcessList(p) => process::process_list(job, &p).await,
+            JobPayload::MetricsHistory(p) => history::metrics_history(job, &p, &ctx.history, &ctx.config).await,
+            JobPayload::RemoteShellStart(p) => Self::remote_shell_start(job, &p, &ctx.shells).await,
+            JobPayload::RemoteShellStop(p) => Self::remote_shell_stop(job, &p, &ctx.shells).await,
+            JobPayload::AgentUpdate(p) => {
+                updater::agent_update(job, &p, &ctx.config, ctx.policy.update_channel().as_deref()).await
+            }
+            JobPayload::ArtifactUpload(p) => artifacts::artifact_upload(job, &p, ctx).await,
+            JobPayload::ArtifactDownload(p) => artifacts::artifact_download(job, &p, ctx).await,
+            JobPayload::WakeOnLan(p) => wol::wake_on_lan(job, &p).await,
+            JobPayload::RemoteDesktopStart(_)
+            | JobPayload::RemoteDesktopStop(_)
+            | JobPayload::RemoteDesktopStream(_)
+            | JobPayload::RemoteDesktopInput(_)
+            | JobPayload::RemoteDesktopPrivacyModeSet(_)
+            | JobPayload::RemoteDesktopInputLockSet(_)
+            | JobPayload::WebcamCapture(_) => {
+                return Self::unsupported(job, started_at, format!("Job type {} is not supported by this agent", job.job_type));
+            }
         };
 
         let completed_at = chrono::Utc::now().timestamp();
@@ -42,114 +322,225 @@ impl JobRunner {
                 stderr: None,
                 exit_code: None,
                 error_message: Some(e.to_string()),
+                artifacts: Vec::new(),
                 result_data: None,
             },
         }
     }
 
+    /// A payload that did not decode: unknown types get their own
+    /// status, malformed payloads fail with the offending field.
+    fn rejected(job: &JobRequest, started_at: i64, error: PayloadError) -> JobResult {
+        tracing::warn!("Rejecting job {}: {}", job.job_id, error);
+        let mut result = Self::unsupported(job, started_at, error.to_string());
+        if let PayloadError::Invalid { job_type, field, message } = error {
+            result.status = "failed".to_string();
+            result.result_data = Some(serde_json::json!({
+                "validation_error": {
+                    "job_type": job_type,
+                    "field": field,
+                    "message": message,
+                }
+            }));
+        }
+        result
+    }
+
+    fn denied_result(job: &JobRequest, denial: Denial) -> JobResult {
+        let now = chrono::Utc::now().timestamp();
+        let mut result = Self::unsupported(job, now, denial.message(&job.job_type));
+        result.status = "failed".to_string();
+        result.result_data = Some(serde_json::json!({
+            "policy_violation": {
+                "job_type": job.job_type,
+                "source": denial.source(),
+            }
+        }));
+        result
+    }
+
+    fn unsupported(job: &JobRequest, started_at: i64, message: String) -> JobResult {
+        JobResult {
+            job_id: job.job_id.clone(),
+            status: "unsupported".to_string(),
+            started_at,
+            completed_at: chrono::Utc::now().timestamp(),
+            stdout: None,
+            stderr: None,
+            exit_code: None,
+            error_message: Some(message),
+            artifacts: Vec::new(),
+            result_data: None,
+        }
+    }
+
     // ═══════════════════════════════════════════════════════════
     // Run Script
     // ═══════════════════════════════════════════════════════════
 
-    async fn run_script(job: &JobRequest) -> Result<JobResult> {
-        let language = job.payload.get("language")
-            .and_then(|v| v.as_str())
-            .unwrap_or("powershell");
+    async fn run_script(
+        job: &JobRequest,
+        payload: &RunScriptPayload,
+        ctx: &JobContext,
+        mut cancel: CancelSignal,
+    ) -> Result<JobResult> {
+        // Nothing from the payload is acted on before this check
+        let signed_by = match signing::verify(&job.job_id, payload, &ctx.config) {
+            Ok(signed_by) => signed_by,
+            Err(rejection) => {
+                tracing::warn!("Refusing script of job {}: {}", job.job_id, rejection.message());
+                return Ok(Self::signature_rejected(job, rejection));
+            }
+        };
 
-        let script = job.payload.get("script")
-            .and_then(|v| v.as_str())
-            .context("Missing script in payload")?;
+        let identity = run_as::resolve(payload.run_as.as_deref(), &ctx.config)?;
+        let limits = limits::effective(payload.limits.as_ref(), &ctx.config.job_limits)?;
+        let (job_id, uid) = (job.job_id.clone(), identity.uid);
+        let sandbox = tokio::task::spawn_blocking(move || Sandbox::prepare(&job_id, limits, uid)).await??;
+        let mut result = Self::spawn_script(job, payload, &identity, &sandbox, ctx, &mut cancel).await?;
 
-        let working_dir = job.payload.get("working_dir")
-            .and_then(|v| v.as_str());
+        let data = result.result_data.get_or_insert_with(|| serde_json::json!({}));
+        data["effective_user"] = identity.to_json();
+        if let Some(limits) = sandbox.to_json() {
+            data["limits"] = limits;
+        }
+        if let Some(key) = signed_by {
+            data["script_signature"] = serde_json::json!({ "verified": true, "key_fingerprint": key });
+        }
+        Ok(result)
+    }
+
+    fn signature_rejected(job: &JobRequest, rejection: Rejection) -> JobResult {
+        JobResult {
+            job_id: job.job_id.clone(),
+            status: "failed".to_string(),
+            started_at: 0,
+            completed_at: 0,
+            stdout: None,
+            stderr: None,
+            exit_code: None,
+            error_message: Some(rejection.message().to_string()),
+            artifacts: Vec::new(),
+            result_data: Some(serde_json::json!({
+                "signature_rejected": { "reason": rejection.reason() }
+            })),
+        }
+    }
 
+    async fn spawn_script(
+        job: &JobRequest,
+        payload: &RunScriptPayload,
+        identity: &run_as::Identity,
+        sandbox: &Sandbox,
+        ctx: &JobContext,
+        cancel: &mut CancelSignal,
+    ) -> Result<JobResult> {
+        let script = payload.script.as_str();
         let timeout_duration = Duration::from_secs(job.timeout_sec);
 
-        let mut cmd = match language {
-            "powershell" => {
+        let mut cmd = match payload.language {
+            ScriptLanguage::Powershell => {
                 let mut c = Command::new("powershell");
                 c.args(["-NoProfile", "-NonInteractive", "-ExecutionPolicy", "Bypass", "-Command", script]);
                 c
             }
-            "bash" => {
+            ScriptLanguage::Bash => {
                 let mut c = Command::new("bash");
                 c.args(["-c", script]);
                 c
             }
-            "python" => {
+            ScriptLanguage::Python => {
                 let mut c = Command::new("python3");
                 c.args(["-c", script]);
                 c
             }
-            "cmd" => {
+            ScriptLanguage::Cmd => {
                 let mut c = Command::new("cmd");
                 c.args(["/C", script]);
                 c
             }
-            _ => {
-                return Ok(JobResult {
-                    job_id: job.job_id.clone(),
-                    status: "failed".to_string(),
-                    started_at: 0,
-                    completed_at: 0,
-                    stdout: None,
-                    stderr: None,
-                    exit_code: None,
-                    error_message: Some(format!("Unsupported language: {}", language)),
-                    result_data: None,
-                });
-            }
         };
 
-        if let Some(dir) = working_dir {
-            cmd.current_dir(dir);
+        // Limits first: joining the cgroup needs the agent's privileges
+        sandbox.apply(&mut cmd);
+        run_as::apply(identity, &mut cmd);
+        match &payload.working_dir {
+            Some(dir) => { cmd.current_dir(dir); }
+            None if identity.is_switched() && std::path::Path::new(&identity.home).is_dir() => {
+                cmd.current_dir(&identity.home);
+            }
+            None => {}
         }
+        cmd.envs(&payload.env_vars);
+
+        let pipe = || if payload.capture_output { std::process::Stdio::piped() } else { std::process::Stdio::null() };
+        cmd.stdin(std::process::Stdio::null());
+        cmd.stdout(pipe());
+        cmd.stderr(pipe());
+        cmd.kill_on_drop(true);
+        platform::new_process_group(&mut cmd);
+
+        let mut child = match cmd.spawn() {
+            Ok(child) => child,
+            Err(e) => return Ok(JobResult {
+                job_id: job.job_id.clone(),
+                status: "failed".to_string(),
+                started_at: 0,
+                completed_at: 0,
+                stdout: None,
+                stderr: None,
+                exit_code: None,
+                error_message: Some(format!("Process execution error: {}", e)),
+                artifacts: Vec::new(),
+                result_data: None,
+            }),
+        };
 
-        // Set environment variables
-        if let Some(env_vars) = job.payload.get("env_vars").and_then(|v| v.as_object()) {
-            for (key, value) in env_vars {
-                if let Some(val) = value.as_str() {
-                    cmd.env(key, val);
-                }
-            }
+        let output = OutputCapture::start(
+            &job.job_id,
+            Arc::clone(&ctx.client),
+            child.stdout.take(),
+            child.stderr.take(),
+            sandbox.limits().max_output_bytes,
+        );
+
+        enum Outcome {
+            Exited(std::io::Result<std::process::ExitStatus>),
+            TimedOut,
+            Cancelled,
+            OverLimit(Exceeded),
         }
 
-        cmd.stdout(std::process::Stdio::piped());
-        cmd.stderr(std::process::Stdio::piped());
-
-        let output = timeout(timeout_duration, cmd.output()).await;
-
-        match output {
-            Ok(Ok(out)) => {
-                let exit_code = out.status.code().unwrap_or(-1);
-                let stdout = String::from_utf8_lossy(&out.stdout).to_string();
-                let stderr = String::from_utf8_lossy(&out.stderr).to_string();
-
-                // Truncate output to 1MB
-                let stdout = if stdout.len() > 1_048_576 {
-                    format!("{}... [truncated]", &stdout[..1_048_576])
-                } else {
-                    stdout
-                };
-                let stderr = if stderr.len() > 1_048_576 {
-                    format!("{}... [truncated]", &stderr[..1_048_576])
-                } else {
-                    stderr
-                };
+        let outcome = tokio::select! {
+            status = child.wait() => Outcome::Exited(status),
+            _ = sleep(timeout_duration) => Outcome::TimedOut,
+            _ = cancel.cancelled() => Outcome::Cancelled,
+            exceeded = sandbox.cpu_exceeded() => Outcome::OverLimit(exceeded),
+            limit = output.limit_reached() => Outcome::OverLimit(Exceeded::Output(limit)),
+        };
+
+        match outcome {
+            Outcome::Exited(Ok(status)) => {
+                let exit_code = status.code().unwrap_or(-1);
+                let output = output.finish(OUTPUT_DRAIN_GRACE).await;
 
+                if let Some(exceeded) = sandbox.exit_reason(&status) {
+                    return Ok(Self::over_limit_result(job, output, Some(exit_code), exceeded));
+                }
                 Ok(JobResult {
                     job_id: job.job_id.clone(),
                     status: if exit_code == 0 { "success" } else { "failed" }.to_string(),
                     started_at: 0,
                     completed_at: 0,
-                    stdout: if stdout.is_empty() { None } else { Some(stdout) },
-                    stderr: if stderr.is_empty() { None } else { Some(stderr) },
+                    artifacts: Vec::new(),
+                    result_data: Some(output.result_data()),
+                    stdout: output.stdout,
+                    stderr: output.stderr,
                     exit_code: Some(exit_code),
                     error_message: None,
-                    result_data: None,
                 })
             }
-            Ok(Err(e)) => Ok(JobResult {
+            Outcome::Exited(Err(e)) => Ok(JobResult {
                 job_id: job.job_id.clone(),
                 status: "failed".to_string(),
                 started_at: 0,
@@ -158,19 +549,82 @@ impl JobRunner {
                 stderr: None,
                 exit_code: None,
                 error_message: Some(format!("Process execution error: {}", e)),
+                artifacts: Vec::new(),
                 result_data: None,
             }),
-            Err(_) => Ok(JobResult {
-                job_id: job.job_id.clone(),
-                status: "timeout".to_string(),
-                started_at: 0,
-                completed_at: 0,
-                stdout: None,
-                stderr: None,
-                exit_code: None,
-                error_message: Some(format!("Job timed out after {} seconds", job.timeout_sec)),
-                result_data: None,
-            }),
+            Outcome::TimedOut => {
+                tracing::warn!("Job {} timed out after {}s, terminating", job.job_id, job.timeout_sec);
+                let exit_code = platform::terminate_tree(&mut child, TERMINATE_GRACE).await;
+                let output = output.finish(OUTPUT_DRAIN_GRACE).await;
+                Ok(JobResult {
+                    job_id: job.job_id.clone(),
+                    status: "timeout".to_string(),
+                    started_at: 0,
+                    completed_at: 0,
+                    artifacts: Vec::new(),
+                    result_data: Some(output.result_data()),
+                    stdout: output.stdout,
+                    stderr: output.stderr,
+                    exit_code,
+                    error_message: Some(format!("Job timed out after {} seconds", job.timeout_sec)),
+                })
+            }
+            Outcome::Cancelled => {
+                let exit_code = platform::terminate_tree(&mut child, TERMINATE_GRACE).await;
+                let output = output.finish(OUTPUT_DRAIN_GRACE).await;
+                let result_data = output.result_data();
+                let mut result = Self::cancelled_result(job, output.stdout, output.stderr, exit_code);
+                result.result_data = Some(result_data);
+                Ok(result)
+            }
+            Outcome::OverLimit(exceeded) => {
+                tracing::warn!("Job {}: {}", job.job_id, exceeded.message());
+                let exit_code = platform::terminate_tree(&mut child, TERMINATE_GRACE).await;
+                let output = output.finish(OUTPUT_DRAIN_GRACE).await;
+                Ok(Self::over_limit_result(job, output, exit_code, exceeded))
+            }
+        }
+    }
+
+    fn over_limit_result(
+        job: &JobRequest,
+        output: OutputSummary,
+        exit_code: Option<i32>,
+        exceeded: Exceeded,
+    ) -> JobResult {
+        let mut result_data = output.result_data();
+        result_data["limit_exceeded"] = exceeded.to_json();
+        JobResult {
+            job_id: job.job_id.clone(),
+            status: "failed".to_string(),
+            started_at: 0,
+            completed_at: 0,
+            stdout: output.stdout,
+            stderr: output.stderr,
+            exit_code,
+            error_message: Some(exceeded.message()),
+            artifacts: Vec::new(),
+            result_data: Some(result_data),
+        }
+    }
+
+    fn cancelled_result(
+        job: &JobRequest,
+        stdout: Option<String>,
+        stderr: Option<String>,
+        exit_code: Option<i32>,
+    ) -> JobResult {
+        JobResult {
+            job_id: job.job_id.clone(),
+            status: "cancelled".to_string(),
+            started_at: 0,
+            completed_at: 0,
+            stdout,
+            stderr,
+            exit_code,
+            error_message: Some("Job cancelled by server".to_string()),
+            artifacts: Vec::new(),
+            result_data: None,
         }
     }
 
@@ -178,23 +632,26 @@ impl JobRunner {
     // System Power Actions
     // ═══════════════════════════════════════════════════════════
 
-    async fn reboot(job: &JobRequest) -> Result<JobResult> {
-        let force = job.payload.get("force").and_then(|v| v.as_bool()).unwrap_or(false);
+    async fn reboot(job: &JobRequest, payload: &PowerPayload) -> Result<JobResult> {
+        let force = payload.force;
+        let delay = payload.delay_sec;
 
-        tracing::warn!("Executing REBOOT (force={})", force);
+        tracing::warn!("Executing REBOOT (force={}, delay={}s, reason={:?})", force, delay, payload.reason);
 
         #[cfg(target_os = "windows")]
         let mut cmd = {
             let mut c = Command::new("shutdown");
-            c.args(["/r", "/t", "5"]);
+            c.args(["/r", "/t", &delay.max(5).to_string()]);
             if force { c.arg("/f"); }
+            if !payload.reason.is_empty() { c.args(["/c", &payload.reason]); }
             c
         };
 
         #[cfg(not(target_os = "windows"))]
         let mut cmd = {
             let mut c = Command::new("shutdown");
-            c.args(["-r", "+0"]);
+            c.args(["-r", &format!("+{}", delay / 60)]);
+            if !payload.reason.is_empty() { c.arg(&payload.reason); }
             c
         };
 
@@ -209,21 +666,23 @@ impl JobRunner {
             stderr: if output.stderr.is_empty() { None } else { Some(String::from_utf8_lossy(&output.stderr).to_string()) },
             exit_code: output.status.code(),
             error_message: None,
+            artifacts: Vec::new(),
             result_data: None,
         })
     }
 
-    async fn shutdown(job: &JobRequest) -> Result<JobResult> {
-        let force = job.payload.get("force").and_then(|v| v.as_bool()).unwrap_or(false);
-        let delay = job.payload.get("delay_sec").and_then(|v| v.as_u64()).unwrap_or(0);
+    async fn shutdown(job: &JobRequest, payload: &PowerPayload) -> Result<JobResult> {
+        let force = payload.force;
+        let delay = payload.delay_sec;
 
-        tracing::warn!("Executing SHUTDOWN (force={}, delay={}s)", force, delay);
+        tracing::warn!("Executing SHUTDOWN (force={}, delay={}s, reason={:?})", force, delay, payload.reason);
 
         #[cfg(target_os = "windows")]
         let mut cmd = {
             let mut c = Command::new("shutdown");
             c.args(["/s", "/t", &delay.to_string()]);
             if force { c.arg("/f"); }
+            if !payload.reason.is_empty() { c.args(["/c", &payload.reason]); }
             c
         };
 
@@ -235,6 +694,7 @@ impl JobRunner {
             } else {
                 c.args(["-h", &format!("+{}", delay / 60)]);
             }
+            if !payload.reason.is_empty() { c.arg(&payload.reason); }
             c
         };
 
@@ -249,6 +709,7 @@ impl JobRunner {
             stderr: if output.stderr.is_empty() { None } else { Some(String::from_utf8_lossy(&output.stderr).to_string()) },
             exit_code: output.status.code(),
             error_message: None,
+            artifacts: Vec::new(),
             result_data: None,
         })
     }
@@ -257,10 +718,8 @@ impl JobRunner {
     // Service Management
     // ═══════════════════════════════════════════════════════════
 
-    async fn service_action(job: &JobRequest, action: &str) -> Result<JobResult> {
-        let service_name = job.payload.get("service_name")
-            .and_then(|v| v.as_str())
-            .context("Missing service_name in payload")?;
+    async fn service_action(job: &JobRequest, payload: &ServiceActionPayload, action: &str) -> Result<JobResult> {
+        let service_name = payload.service_name.as_str();
 
         tracing::info!("Service {}: {}", action, service_name);
 
@@ -273,7 +732,7 @@ impl JobRunner {
         };
 
         #[cfg(not(target_os = "windows"))]
-        let cmd_args = vec!["systemctl", action, service_name];
+        let cmd_args = ["systemctl", action, service_name];
 
         #[cfg(target_os = "windows")]
         let output = Command::new("powershell")
@@ -299,45 +758,140 @@ impl JobRunner {
             stderr: if output.stderr.is_empty() { None } else { Some(String::from_utf8_lossy(&output.stderr).to_string()) },
             exit_code: Some(exit_code),
             error_message: None,
+            artifacts: Vec::new(),
             result_data: None,
         })
     }
 
     // ═══════════════════════════════════════════════════════════
-    // Process Kill
+    // Remote Shell
     // ═══════════════════════════════════════════════════════════
 
-    async fn process_kill(job: &JobRequest) -> Result<JobResult> {
-        let pid = job.payload.get("pid")
-            .and_then(|v| v.as_u64())
-            .context("Missing pid in payload")? as u32;
+    async fn remote_shell_start(
+        job: &JobRequest,
+        payload: &RemoteShellStartPayload,
+        shells: &Arc<ShellManager>,
+    ) -> Result<JobResult> {
+        let shell = payload.shell.as_str();
+        let (cols, rows) = (payload.cols, payload.rows);
 
-        tracing::info!("Killing process PID: {}", pid);
+        // The job ID doubles as the session ID the UI subscribes to
+        let started = shells.start(&job.job_id, shell, cols, rows).await?;
 
-        #[cfg(target_os = "windows")]
-        let output = Command::new("taskkill")
-            .args(["/PID", &pid.to_string(), "/F"])
-            .output()
-            .await?;
+        Ok(JobResult {
+            job_id: job.job_id.clone(),
+            status: "success".to_string(),
+            started_at: 0,
+            completed_at: 0,
+            stdout: None,
+            stderr: None,
+            exit_code: None,
+            error_message: None,
+            artifacts: Vec::new(),
+            result_data: Some(serde_json::json!({
+                "session_id": started.session_id,
+                "shell": started.shell,
+                "pid": started.pid,
+                "cols": cols,
+                "rows": rows,
+            })),
+        })
+    }
 
-        #[cfg(not(target_os = "windows"))]
-        let output = Command::new("kill")
-            .args(["-9", &pid.to_string()])
-            .output()
-            .await?;
+    async fn remote_shell_stop(
+        job: &JobRequest,
+        payload: &RemoteShellStopPayload,
+        shells: &Arc<ShellManager>,
+    ) -> Result<JobResult> {
+        let session_id = payload.session_id.as_str();
 
-        let exit_code = output.status.code().unwrap_or(-1);
+        let found = shells.stop(session_id).await;
 
         Ok(JobResult {
             job_id: job.job_id.clone(),
-            status: if exit_code == 0 { "success" } else { "failed" }.to_string(),
+            status: if found { "success" } else { "failed" }.to_string(),
             started_at: 0,
             completed_at: 0,
-            stdout: Some(String::from_utf8_lossy(&output.stdout).to_string()),
-            stderr: if output.stderr.is_empty() { None } else { Some(String::from_utf8_lossy(&output.stderr).to_string()) },
-            exit_code: Some(exit_code),
-            error_message: None,
-            result_data: None,
+            stdout: None,
+            stderr: None,
+            exit_code: None,
+            error_message: if found { None } else { Some(format!("No active shell session: {}", session_id)) },
+            artifacts: Vec::new(),
+            result_data: Some(serde_json::json!({ "session_id": session_id })),
         })
     }
 }
+
+// ═══════════════════════════════════════════════════════════════
+// Process Termination
+// ═══════════════════════════════════════════════════════════════
+
+/// Time a cancelled, timed-out or over-limit script gets to exit
+/// after SIGTERM.
+const TERMINATE_GRACE: Duration = Duration::from_secs(10);
+
+/// How long to wait for pipes to close once the script has exited.
+const OUTPUT_DRAIN_GRACE: Duration = Duration::from_secs(2);
+
+#[cfg(unix)]
+mod platform {
+    use nix::sys::signal::{killpg, Signal};
+    use nix::unistd::Pid;
+    use tokio::process::{Child, Command};
+    use tokio::time::{timeout, Duration};
+
+    /// Make the child lead its own process group so the whole tree
+    /// can be signalled at once.
+    pub fn new_process_group(cmd: &mut Command) {
+        cmd.process_group(0);
+    }
+
+    /// SIGTERM the child's process group, then SIGKILL whatever is
+    /// left after `grace`. Returns the child's exit code, if any.
+    pub async fn terminate_tree(child: &mut Child, grace: Duration) -> Option<i32> {
+        let Some(pid) = child.id() else {
+            return child.wait().await.ok().and_then(|s| s.code());
+        };
+        let pgid = Pid::from_raw(pid as i32);
+
+        let _ = killpg(pgid, Signal::SIGTERM);
+        let status = match timeout(grace, child.wait()).await {
+            Ok(status) => status,
+            Err(_) => {
+                tracing::warn!("Process group {} ignored SIGTERM, killing", pid);
+                let _ = killpg(pgid, Signal::SIGKILL);
+                child.wait().await
+            }
+        };
+        // Stragglers that outlived the group leader
+        let _ = killpg(pgid, Signal::SIGKILL);
+        status.ok().and_then(|s| s.code())
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use tokio::process::{Child, Command};
+    use tokio::time::Duration;
+
+    const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+
+    pub fn new_process_group(cmd: &mut Command) {
+        cmd.creation_flags(CREATE_NEW_PROCESS_GROUP);
+    }
+
+    /// Console scripts cannot be asked to exit, so the whole tree is
+    /// killed at once (`taskkill /T /F`).
+    pub async fn terminate_tree(child: &mut Child, _grace: Duration) -> Option<i32> {
+        if let Some(pid) = child.id() {
+            let _ = Command::new("taskkill")
+                .args(["/PID", &pid.to_string(), "/T", "/F"])
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .status()
+                .await;
+        }
+        let _ = child.start_kill();
+        child.wait().await.ok().and_then(|s| s.code())
+    }
+}
@@ -0,0 +1,518 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r Agent - Remote Shell Sessions
+// ─────────────────────────────────────────────────────────────
+//
+// Each remote_shell_start job spawns a PTY-backed shell whose
+// session ID is the job ID. Output is batched and pushed as
+// `shell_output` envelopes; operator input, resize and stop
+// events are pushed over the WebSocket, or fetched with
+// `shell_poll` while sessions exist and the socket is down.
+// ─────────────────────────────────────────────────────────────
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex, RwLock};
+use tokio::time::{interval, sleep, Duration, Instant, MissedTickBehavior};
+
+use crate::comms::client::AgentClient;
+use crate::comms::protocol::{ShellEvent, ShellOutputPayload};
+
+/// Output is coalesced for this long before being sent.
+const OUTPUT_FLUSH_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Cadence of the input poll while at least one session is open.
+const INPUT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Sessions are torn down when the server has been unreachable this long.
+const DISCONNECT_GRACE: Duration = Duration::from_secs(60);
+
+/// Maximum bytes sent in a single `shell_output` envelope.
+const MAX_OUTPUT_CHUNK: usize = 64 * 1024;
+
+enum ShellControl {
+    Input(Vec<u8>),
+    Resize { cols: u16, rows: u16 },
+    Stop(&'static str),
+}
+
+struct SessionHandle {
+    control: mpsc::UnboundedSender<ShellControl>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ShellStarted {
+    pub session_id: String,
+    pub shell: String,
+    pub pid: u32,
+}
+
+pub struct ShellManager {
+    client: Arc<RwLock<AgentClient>>,
+    idle_timeout: Duration,
+    sessions: Mutex<HashMap<String, SessionHandle>>,
+}
+
+impl ShellManager {
+    pub fn new(client: Arc<RwLock<AgentClient>>, idle_timeout_sec: u64) -> Self {
+        Self {
+            client,
+            idle_timeout: Duration::from_secs(idle_timeout_sec),
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Spawn a new PTY session identified by `session_id`.
+    pub async fn start(
+        self: &Arc<Self>,
+        session_id: &str,
+        shell: &str,
+        cols: u16,
+        rows: u16,
+    ) -> Result<ShellStarted> {
+        let mut sessions = self.sessions.lock().await;
+        if sessions.contains_key(session_id) {
+            anyhow::bail!("Shell session already exists: {}", session_id);
+        }
+
+        let shell_path = resolve_shell(shell)?;
+        let pty = pty::spawn(&shell_path, cols, rows)?;
+        let pid = pty.pid();
+
+        tracing::info!("Remote shell started: session={} shell={} pid={}", session_id, shell_path, pid);
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        sessions.insert(session_id.to_string(), SessionHandle { control: tx });
+        drop(sessions);
+
+        let manager = Arc::clone(self);
+        let id = session_id.to_string();
+        tokio::spawn(async move {
+            manager.run_session(id, pty, rx).await;
+        });
+
+        Ok(ShellStarted {
+            session_id: session_id.to_string(),
+            shell: shell_path,
+            pid,
+        })
+    }
+
+    /// Request termination of a session. Returns false if it is unknown.
+    pub async fn stop(&self, session_id: &str) -> bool {
+        self.send_control(session_id, ShellControl::Stop("stopped by operator")).await
+    }
+
+    /// Terminate every open session (agent shutdown / server loss).
+    pub async fn stop_all(&self, reason: &'static str) {
+        let sessions = self.sessions.lock().await;
+        for handle in sessions.values() {
+            let _ = handle.control.send(ShellControl::Stop(reason));
+        }
+    }
+
+    pub async fn session_ids(&self) -> Vec<String> {
+        self.sessions.lock().await.keys().cloned().collect()
+    }
+
+    /// Route an operator event to its session.
+    pub async fn dispatch(&self, event: ShellEvent) {
+        match event {
+            ShellEvent::Input { session_id, data } => {
+                self.send_control(&session_id, ShellControl::Input(data.into_bytes())).await;
+            }
+            ShellEvent::Resize { session_id, cols, rows } => {
+                self.send_control(&session_id, ShellControl::Resize { cols, rows }).await;
+            }
+            ShellEvent::Stop { session_id } => {
+                self.send_control(&session_id, ShellControl::Stop("stopped by operator")).await;
+            }
+        }
+    }
+
+    async fn send_control(&self, session_id: &str, control: ShellControl) -> bool {
+        match self.sessions.lock().await.get(session_id) {
+            Some(handle) => handle.control.send(control).is_ok(),
+            None => false,
+        }
+    }
+
+    // ═══════════════════════════════════════════════════════════
+    // Input Poll Loop
+    // ═══════════════════════════════════════════════════════════
+
+    /// Fetch operator input for open sessions until the agent exits.
+    pub async fn input_loop(self: Arc<Self>) {
+        let mut last_success = Instant::now();
+
+        loop {
+            sleep(INPUT_POLL_INTERVAL).await;
+
+            let session_ids = self.session_ids().await;
+            if session_ids.is_empty() {
+                last_success = Instant::now();
+                continue;
+            }
+
+            // Events are pushed over the WebSocket while it is connected
+            let c = self.client.read().await;
+            if c.ws_connected() {
+                last_success = Instant::now();
+                continue;
+            }
+            let result = c.poll_shell_events(session_ids).await;
+            drop(c);
+
+            match result {
+                Ok(events) => {
+                    last_success = Instant::now();
+                    for event in events {
+                        self.dispatch(event).await;
+                    }
+                }
+                Err(e) => {
+                    tracing::debug!("Shell poll failed: {}", e);
+                    if last_success.elapsed() > DISCONNECT_GRACE {
+                        tracing::warn!("Server unreachable, closing all remote shell sessions");
+                        self.stop_all("server disconnected").await;
+                        last_success = Instant::now();
+                    }
+                }
+            }
+        }
+    }
+
+    // ═══════════════════════════════════════════════════════════
+    // Session Task
+    // ═══════════════════════════════════════════════════════════
+
+    async fn run_session(
+        self: Arc<Self>,
+        session_id: String,
+        mut pty: pty::Pty,
+        mut control: mpsc::UnboundedReceiver<ShellControl>,
+    ) {
+        let mut output_rx = pty.take_reader();
+        let mut decoder = Utf8Carry::default();
+        let mut pending = String::new();
+        let mut seq = 0u64;
+        let mut last_activity = Instant::now();
+
+        let mut flush = interval(OUTPUT_FLUSH_INTERVAL);
+        flush.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut idle_check = interval(Duration::from_secs(5));
+
+        let reason = loop {
+            tokio::select! {
+                chunk = output_rx.recv() => match chunk {
+                    Some(bytes) => {
+                        // A shell still printing (tail -f, a build) is not idle
+                        last_activity = Instant::now();
+                        pending.push_str(&decoder.decode(&bytes));
+                        if pending.len() >= MAX_OUTPUT_CHUNK {
+                            self.send_output(&session_id, &mut seq, std::mem::take(&mut pending), None).await;
+                        }
+                    }
+                    None => break "shell exited",
+                },
+                _ = flush.tick() => {
+                    if !pending.is_empty() {
+                        self.send_output(&session_id, &mut seq, std::mem::take(&mut pending), None).await;
+                    }
+                }
+                msg = control.recv() => match msg {
+                    Some(ShellControl::Input(bytes)) => {
+                        last_activity = Instant::now();
+                        if let Err(e) = pty.write(bytes).await {
+                            tracing::warn!("Shell {} input failed: {}", session_id, e);
+                        }
+                    }
+                    Some(ShellControl::Resize { cols, rows }) => {
+                        if let Err(e) = pty.resize(cols, rows) {
+                            tracing::warn!("Shell {} resize failed: {}", session_id, e);
+                        }
+                    }
+                    Some(ShellControl::Stop(reason)) => break reason,
+                    None => break "session dropped",
+                },
+                _ = idle_check.tick() => {
+                    if last_activity.elapsed() >= self.idle_timeout {
+                        break "idle timeout";
+                    }
+                }
+            }
+        };
+
+        self.sessions.lock().await.remove(&session_id);
+
+        // Drain whatever the shell printed before it went away
+        while let Ok(bytes) = output_rx.try_recv() {
+            pending.push_str(&decoder.decode(&bytes));
+        }
+        if !pending.is_empty() {
+            self.send_output(&session_id, &mut seq, pending, None).await;
+        }
+
+        let exit_code = pty.terminate().await;
+        tracing::info!("Remote shell closed: session={} reason={} exit={:?}", session_id, reason, exit_code);
+
+        self.send_output(&session_id, &mut seq, String::new(), Some((reason, exit_code))).await;
+    }
+
+    async fn send_output(
+        &self,
+        session_id: &str,
+        seq: &mut u64,
+        data: String,
+        closed: Option<(&str, Option<i32>)>,
+    ) {
+        *seq += 1;
+        let payload = ShellOutputPayload {
+            session_id: session_id.to_string(),
+            seq: *seq,
+            data,
+            closed: closed.is_some(),
+            close_reason: closed.map(|(reason, _)| reason.to_string()),
+            exit_code: closed.and_then(|(_, code)| code),
+        };
+
+        let c = self.client.read().await;
+        if let Err(e) = c.send_shell_output(payload).await {
+            tracing::warn!("Failed to send shell output for {}: {}", session_id, e);
+        }
+    }
+}
+
+/// Map the requested shell to an executable present on this host.
+fn resolve_shell(requested: &str) -> Result<String> {
+    let candidates: &[&str] = match requested {
+        "bash" => &["/bin/bash", "/usr/bin/bash", "/bin/sh"],
+        "sh" => &["/bin/sh"],
+        // The UI defaults to PowerShell; use pwsh when installed, a POSIX shell otherwise
+        "powershell" => &["/usr/bin/pwsh", "/usr/local/bin/pwsh", "/bin/bash", "/bin/sh"],
+        "cmd" => &["/bin/bash", "/bin/sh"],
+        other => anyhow::bail!("Unsupported shell: {}", other),
+    };
+
+    candidates
+        .iter()
+        .find(|path| std::path::Path::new(path).exists())
+        .map(|path| path.to_string())
+        .with_context(|| format!("No usable shell found for '{}'", requested))
+}
+
+/// Decodes a byte stream to UTF-8, carrying incomplete trailing
+/// sequences over to the next chunk instead of mangling them.
+#[derive(Default)]
+pub struct Utf8Carry {
+    carry: Vec<u8>,
+}
+
+impl Utf8Carry {
+    pub fn decode(&mut self, bytes: &[u8]) -> String {
+        self.carry.extend_from_slice(bytes);
+        let buf = std::mem::take(&mut self.carry);
+        let mut out = String::with_capacity(buf.len());
+
+        // Invalid bytes become U+FFFD one at a time, so an incomplete
+        // sequence at the end is still carried over after one
+        let mut rest = buf.as_slice();
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(s) => {
+                    out.push_str(s);
+                    break;
+                }
+                Err(e) => {
+                    let (valid, tail) = rest.split_at(e.valid_up_to());
+                    out.push_str(&String::from_utf8_lossy(valid));
+                    match e.error_len() {
+                        Some(len) => {
+                            out.push(char::REPLACEMENT_CHARACTER);
+                            rest = &tail[len..];
+                        }
+                        None => {
+                            self.carry = tail.to_vec();
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Platform-specific PTY handling
+// ═══════════════════════════════════════════════════════════════
+
+#[cfg(unix)]
+mod pty {
+    use anyhow::{Context, Result};
+    use nix::libc;
+    use nix::pty::{openpty, Winsize};
+    use nix::sys::signal::{killpg, Signal};
+    use nix::unistd::Pid;
+    use std::io::{Read, Write};
+    use std::os::fd::AsRawFd;
+    use std::os::unix::process::CommandExt;
+    use std::process::{Child, Command, Stdio};
+    use tokio::sync::mpsc;
+    use tokio::time::{sleep, Duration};
+
+    pub struct Pty {
+        master: std::fs::File,
+        child: Option<Child>,
+        reader: Option<mpsc::Receiver<Vec<u8>>>,
+    }
+
+    pub fn spawn(shell: &str, cols: u16, rows: u16) -> Result<Pty> {
+        let winsize = Winsize { ws_row: rows, ws_col: cols, ws_xpixel: 0, ws_ypixel: 0 };
+        let pair = openpty(Some(&winsize), None).context("Failed to allocate PTY")?;
+        // openpty has no O_CLOEXEC; without this the shell, and every
+        // job spawned meanwhile, would inherit the master
+        for fd in [pair.master.as_raw_fd(), pair.slave.as_raw_fd()] {
+            if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+                return Err(std::io::Error::last_os_error()).context("Failed to set FD_CLOEXEC on PTY");
+            }
+        }
+
+        let mut cmd = Command::new(shell);
+        cmd.env("TERM", "xterm-256color")
+            .stdin(Stdio::from(pair.slave.try_clone()?))
+            .stdout(Stdio::from(pair.slave.try_clone()?))
+            .stderr(Stdio::from(pair.slave));
+        if let Some(home) = std::env::var_os("HOME") {
+            cmd.current_dir(home);
+        }
+
+        // New session with the PTY slave as controlling terminal, so the
+        // shell gets job control and the whole group can be signalled.
+        unsafe {
+            cmd.pre_exec(|| {
+                nix::unistd::setsid()?;
+                if libc::ioctl(0, libc::TIOCSCTTY as _, 0) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        let child = cmd.spawn().with_context(|| format!("Failed to spawn {}", shell))?;
+        let master = std::fs::File::from(pair.master);
+
+        let (tx, rx) = mpsc::channel(64);
+        let mut reader = master.try_clone()?;
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            loop {
+                // EIO signals that every slave handle was closed (shell exited)
+                match reader.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        if tx.blocking_send(buf[..n].to_vec()).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(Pty { master, child: Some(child), reader: Some(rx) })
+    }
+
+    impl Pty {
+        pub fn pid(&self) -> u32 {
+            self.child.as_ref().map(|c| c.id()).unwrap_or(0)
+        }
+
+        pub fn take_reader(&mut self) -> mpsc::Receiver<Vec<u8>> {
+            self.reader.take().expect("PTY reader already taken")
+        }
+
+        pub async fn write(&self, bytes: Vec<u8>) -> Result<()> {
+            let mut master = self.master.try_clone()?;
+            tokio::task::spawn_blocking(move || master.write_all(&bytes)).await??;
+            Ok(())
+        }
+
+        pub fn resize(&self, cols: u16, rows: u16) -> Result<()> {
+            let winsize = libc::winsize { ws_row: rows, ws_col: cols, ws_xpixel: 0, ws_ypixel: 0 };
+            let rc = unsafe { libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ as _, &winsize) };
+            if rc < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            Ok(())
+        }
+
+        /// Hang up the session's process group, escalate to SIGKILL
+        /// if it lingers, and reap the shell.
+        pub async fn terminate(&mut self) -> Option<i32> {
+            let mut child = self.child.take()?;
+            let pgid = Pid::from_raw(child.id() as i32);
+
+            if let Ok(Some(status)) = child.try_wait() {
+                let _ = killpg(pgid, Signal::SIGKILL);
+                return status.code();
+            }
+
+            let _ = killpg(pgid, Signal::SIGHUP);
+            for _ in 0..20 {
+                if let Ok(Some(status)) = child.try_wait() {
+                    let _ = killpg(pgid, Signal::SIGKILL);
+                    return status.code();
+                }
+                sleep(Duration::from_millis(100)).await;
+            }
+
+            let _ = killpg(pgid, Signal::SIGKILL);
+            tokio::task::spawn_blocking(move || child.wait().ok().and_then(|s| s.code()))
+                .await
+                .ok()
+                .flatten()
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod pty {
+    use anyhow::Result;
+    use tokio::sync::mpsc;
+
+    pub struct Pty;
+
+    pub fn spawn(_shell: &str, _cols: u16, _rows: u16) -> Result<Pty> {
+        anyhow::bail!("Remote shell is only supported on Unix agents")
+    }
+
+    impl Pty {
+        pub fn pid(&self) -> u32 { 0 }
+        pub fn take_reader(&mut self) -> mpsc::Receiver<Vec<u8>> { mpsc::channel(1).1 }
+        pub async fn write(&self, _bytes: Vec<u8>) -> Result<()> { Ok(()) }
+        pub fn resize(&self, _cols: u16, _rows: u16) -> Result<()> { Ok(()) }
+        pub async fn terminate(&mut self) -> Option<i32> { None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn carries_split_sequences_to_the_next_chunk() {
+        let mut decoder = Utf8Carry::default();
+        let bytes = "héllo €".as_bytes();
+        assert_eq!(decoder.decode(&bytes[..2]), "h");
+        assert_eq!(decoder.decode(&bytes[2..bytes.len() - 1]), "éllo ");
+        assert_eq!(decoder.decode(&bytes[bytes.len() - 1..]), "€");
+    }
+
+    #[test]
+    fn invalid_bytes_do_not_drop_the_carry() {
+        let mut decoder = Utf8Carry::default();
+        // 0xff is never valid; 0xe2 0x82 starts a "€" finished next chunk
+        assert_eq!(decoder.decode(&[b'a', 0xff, b'b', 0xe2, 0x82]), "a\u{fffd}b");
+        assert_eq!(decoder.decode(&[0xac, b'c']), "€c");
+    }
+}
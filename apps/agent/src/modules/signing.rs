@@ -0,0 +1,210 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r Agent - Script Signatures
+// ─────────────────────────────────────────────────────────────
+//
+// With `require_signed_scripts`, run_script only launches scripts
+// signed (Ed25519) by one of the organization keys pinned in
+// `script_signing_keys`, so a compromised backend cannot run code
+// of its own. The signature covers everything that decides what
+// runs: language, script, working_dir, run_as and env_vars, plus the
+// job ID and an expiry so a captured signature cannot be replayed
+// as another job later. It is encoded as a compact JSON array (see
+// scriptSigningMessage in packages/shared/src/jobs.ts). A signature
+// that is present is checked whenever keys are pinned, even when
+// signing is not required.
+// ─────────────────────────────────────────────────────────────
+
+use base64::Engine;
+use ed25519_dalek::{Signature, VerifyingKey};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+use crate::comms::protocol::RunScriptPayload;
+use crate::config::AgentConfig;
+
+/// First field of the signed message; bumped if the format changes.
+const MESSAGE_VERSION: &str = "reap3r-script-v2";
+
+/// Why a script was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    /// Signing is required and the job has no signature.
+    Unsigned,
+    /// Signing is required but no usable key is pinned.
+    NoTrustedKey,
+    /// Not valid base64 or not 64 bytes.
+    Malformed,
+    /// No pinned key verifies the signature.
+    Invalid,
+    /// `signature_expires_at` is missing or in the past.
+    Expired,
+}
+
+impl Rejection {
+    pub fn reason(self) -> &'static str {
+        match self {
+            Rejection::Unsigned => "unsigned",
+            Rejection::NoTrustedKey => "no_trusted_key",
+            Rejection::Malformed => "malformed_signature",
+            Rejection::Invalid => "invalid_signature",
+            Rejection::Expired => "expired_signature",
+        }
+    }
+
+    pub fn message(self) -> &'static str {
+        match self {
+            Rejection::Unsigned => "Script is not signed and this agent requires signed scripts",
+            Rejection::NoTrustedKey => "This agent requires signed scripts but has no valid script_signing_keys",
+            Rejection::Malformed => "Script signature is not a base64 Ed25519 signature",
+            Rejection::Invalid => "Script signature does not match any trusted signing key",
+            Rejection::Expired => "Script signature has expired or has no signature_expires_at",
+        }
+    }
+}
+
+/// Check the script's signature. Returns the fingerprint of the key
+/// that verified it, or None for an unsigned script that is allowed.
+pub fn verify(job_id: &str, payload: &RunScriptPayload, config: &AgentConfig) -> Result<Option<String>, Rejection> {
+    verify_at(job_id, payload, config, chrono::Utc::now().timestamp())
+}
+
+fn verify_at(job_id: &str, payload: &RunScriptPayload, config: &AgentConfig, now: i64) -> Result<Option<String>, Rejection> {
+    let signature = match payload.signature.as_deref() {
+        Some(signature) if !signature.trim().is_empty() => signature,
+        _ if config.require_signed_scripts => return Err(Rejection::Unsigned),
+        _ => return Ok(None),
+    };
+
+    let keys = trusted_keys(config);
+    if keys.is_empty() {
+        if config.require_signed_scripts {
+            return Err(Rejection::NoTrustedKey);
+        }
+        // Nothing to check against; same as an unsigned script
+        return Ok(None);
+    }
+
+    if payload.signature_expires_at.is_none_or(|expires_at| expires_at <= now) {
+        return Err(Rejection::Expired);
+    }
+
+    let signature = base64::engine::general_purpose::STANDARD
+        .decode(signature.trim())
+        .ok()
+        .and_then(|bytes| Signature::from_slice(&bytes).ok())
+        .ok_or(Rejection::Malformed)?;
+
+    let message = signing_message(job_id, payload);
+    keys.iter()
+        .find(|key| key.verify_strict(&message, &signature).is_ok())
+        .map(|key| Some(fingerprint(key)))
+        .ok_or(Rejection::Invalid)
+}
+
+/// The bytes the organization key signs.
+fn signing_message(job_id: &str, payload: &RunScriptPayload) -> Vec<u8> {
+    let env: Vec<(&str, &str)> = payload.env_vars.iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect::<BTreeMap<_, _>>()
+        .into_iter()
+        .collect();
+    serde_json::to_vec(&(
+        MESSAGE_VERSION,
+        job_id,
+        payload.signature_expires_at,
+        payload.language,
+        &payload.script,
+        &payload.working_dir,
+        &payload.run_as,
+        env,
+    ))
+    .unwrap_or_default()
+}
+
+fn trusted_keys(config: &AgentConfig) -> Vec<VerifyingKey> {
+    config.script_signing_keys.iter().filter_map(|encoded| {
+        let key = base64::engine::general_purpose::STANDARD
+            .decode(encoded.trim())
+            .ok()
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok());
+        if key.is_none() {
+            tracing::warn!("Ignoring invalid script signing key {:?}", encoded);
+        }
+        key
+    }).collect()
+}
+
+/// Short identifier of a key for audit trails.
+fn fingerprint(key: &VerifyingKey) -> String {
+    hex::encode(&Sha256::digest(key.as_bytes())[..8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn payload(signature: Option<&str>, expires_at: Option<i64>) -> RunScriptPayload {
+        serde_json::from_value(serde_json::json!({
+            "language": "bash",
+            "script": "echo \"é\"\t\u{1b}",
+            "run_as": "deploy",
+            "env_vars": { "B": "2", "A": "1" },
+            "signature": signature,
+            "signature_expires_at": expires_at,
+        }))
+        .unwrap()
+    }
+
+    fn config(keys: &[&SigningKey], required: bool) -> AgentConfig {
+        let mut config: AgentConfig = toml::from_str("server_url = \"https://reap3r.test\"").unwrap();
+        config.script_signing_keys = keys.iter()
+            .map(|key| base64::engine::general_purpose::STANDARD.encode(key.verifying_key().as_bytes()))
+            .collect();
+        config.require_signed_scripts = required;
+        config
+    }
+
+    fn sign(key: &SigningKey, job_id: &str, payload: &RunScriptPayload) -> String {
+        base64::engine::general_purpose::STANDARD.encode(key.sign(&signing_message(job_id, payload)).to_bytes())
+    }
+
+    #[test]
+    fn message_matches_the_js_encoding() {
+        // JSON.stringify(scriptSigningMessage output) for the same payload
+        let expected = r#"["reap3r-script-v2","job-1",1700000000,"bash","echo \"é\"\t\u001b",null,"deploy",[["A","1"],["B","2"]]]"#;
+        let message = signing_message("job-1", &payload(None, Some(NOW)));
+        assert_eq!(String::from_utf8(message).unwrap(), expected);
+    }
+
+    #[test]
+    fn signature_is_bound_to_the_job_and_expiry() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let config = config(&[&key], true);
+        let unsigned = payload(None, Some(NOW + 60));
+        let signed = payload(Some(&sign(&key, "job-1", &unsigned)), Some(NOW + 60));
+
+        assert!(verify_at("job-1", &signed, &config, NOW).unwrap().is_some());
+        assert_eq!(verify_at("job-2", &signed, &config, NOW), Err(Rejection::Invalid));
+        assert_eq!(verify_at("job-1", &signed, &config, NOW + 60), Err(Rejection::Expired));
+    }
+
+    #[test]
+    fn signature_without_expiry_is_refused() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let unsigned = payload(None, None);
+        let signed = payload(Some(&sign(&key, "job-1", &unsigned)), None);
+        assert_eq!(verify_at("job-1", &signed, &config(&[&key], false), NOW), Err(Rejection::Expired));
+    }
+
+    #[test]
+    fn signed_job_without_pinned_keys_runs_as_unsigned_unless_required() {
+        let signed = payload(Some("not even base64"), Some(NOW + 60));
+        assert_eq!(verify_at("job-1", &signed, &config(&[], false), NOW), Ok(None));
+        assert_eq!(verify_at("job-1", &signed, &config(&[], true), NOW), Err(Rejection::NoTrustedKey));
+        assert_eq!(verify_at("job-1", &payload(None, None), &config(&[], true), NOW), Err(Rejection::Unsigned));
+    }
+}
@@ -0,0 +1,584 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r Agent - Self Update
+// ─────────────────────────────────────────────────────────────
+//
+// agent_update flow:
+//   1. Download to "<exe>.new", verifying SHA-256 and an Ed25519
+//      signature over "{version}|{channel}|{sha256}" against the
+//      pinned update key, so a signed build cannot be replayed as
+//      another version (or to downgrade agents)
+//   2. Keep the running binary as "<exe>.prev", rename the new one
+//      over "<exe>" and record the attempt in update-state.json
+//   3. Start a guard (the previous binary, --update-guard) outside
+//      the service cgroup, then restart the service
+//   4. The new binary reports success after its first heartbeat the
+//      server answers over HTTP and removes the state file; if that does not happen before
+//      the deadline the guard restores "<exe>.prev" and restarts,
+//      and the old version reports the failure
+// ─────────────────────────────────────────────────────────────
+
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::RwLock;
+use tokio::time::{sleep, Duration};
+
+use crate::comms::client::AgentClient;
+use crate::comms::outbox::Outbox;
+use crate::comms::protocol::{AgentUpdatePayload, HeartbeatPayload, JobRequest, JobResult};
+use crate::config::AgentConfig;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Build-time fallback for `update_public_key`.
+const BUILTIN_UPDATE_KEY: Option<&str> = option_env!("REAP3R_UPDATE_PUBLIC_KEY");
+
+/// Past the health deadline by this much, a leftover state file no
+/// longer means an update is in progress.
+const STALE_STATE_GRACE_SEC: i64 = 300;
+
+/// In-flight update, persisted across the restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateState {
+    pub job_id: String,
+    pub from_version: String,
+    pub to_version: String,
+    pub exe_path: PathBuf,
+    pub backup_path: PathBuf,
+    pub service_name: String,
+    pub started_at: i64,
+    pub deadline: i64,
+    pub rollback_on_failure: bool,
+    #[serde(default)]
+    pub rolled_back: bool,
+}
+
+pub fn state_path() -> PathBuf {
+    AgentConfig::data_dir().join("update-state.json")
+}
+
+fn load_state(path: &Path) -> Option<UpdateState> {
+    let content = std::fs::read(path).ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+fn save_state(path: &Path, state: &UpdateState) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(state)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Job Handler
+// ═══════════════════════════════════════════════════════════════
+
+/// `policy_channel` is the agent policy's `update_channel`; builds
+/// from any other channel are refused.
+pub async fn agent_update(
+    job: &JobRequest,
+    payload: &AgentUpdatePayload,
+    config: &AgentConfig,
+    policy_channel: Option<&str>,
+) -> Result<JobResult> {
+    let version = payload.version.as_str();
+    let channel = payload.channel.as_str();
+    let url = payload.url.as_str();
+    let sha256 = payload.sha256.as_str();
+    let signature = payload.signature.as_str();
+    let force = payload.force;
+    let rollback_on_failure = payload.rollback_on_failure;
+
+    platform::ensure_supported()?;
+
+    // The channel is covered by the signature, so it cannot be relabelled
+    if let Some(allowed) = policy_channel {
+        if channel != allowed {
+            bail!("Update is from the {} channel but the agent policy follows {}", channel, allowed);
+        }
+    }
+
+    if version == VERSION && !force {
+        return Ok(update_result(job, "success", None, serde_json::json!({
+            "version": VERSION,
+            "message": "Already running the requested version",
+        })));
+    }
+
+    match compare_versions(version, VERSION) {
+        Some(Ordering::Less) if !payload.allow_downgrade => {
+            bail!("Refusing to downgrade from {} to {} without allow_downgrade", VERSION, version);
+        }
+        None if !payload.allow_downgrade => {
+            bail!("Cannot compare version {:?} with {}; set allow_downgrade to install it anyway", version, VERSION);
+        }
+        _ => {}
+    }
+
+    let state_file = state_path();
+    if let Some(state) = load_state(&state_file) {
+        if chrono::Utc::now().timestamp() < state.deadline + STALE_STATE_GRACE_SEC {
+            bail!("Another update is already in progress");
+        }
+        tracing::warn!("Removing stale state of the update to {}", state.to_version);
+        let _ = std::fs::remove_file(&state_file);
+    }
+
+    let verifying_key = pinned_key(config)?;
+    let exe_path = std::env::current_exe().context("Cannot locate running binary")?;
+    let staged_path = sibling(&exe_path, "new");
+    let backup_path = sibling(&exe_path, "prev");
+
+    tracing::warn!("Updating agent {} -> {} ({}) from {}", VERSION, version, channel, url);
+
+    // ── Download + verify ───────────────────────────────────
+    let digest = download(url, &staged_path).await?;
+    if !digest.eq_ignore_ascii_case(sha256) {
+        let _ = std::fs::remove_file(&staged_path);
+        bail!("SHA-256 mismatch: expected {}, got {}", sha256, digest);
+    }
+    if let Err(e) = verify_signature(&signing_message(version, channel, &digest), signature, &verifying_key) {
+        let _ = std::fs::remove_file(&staged_path);
+        return Err(e);
+    }
+
+    // ── Stage ───────────────────────────────────────────────
+    platform::make_executable(&staged_path)?;
+    std::fs::copy(&exe_path, &backup_path).context("Failed to back up current binary")?;
+    std::fs::rename(&staged_path, &exe_path).context("Failed to install new binary")?;
+
+    let now = chrono::Utc::now().timestamp();
+    let state = UpdateState {
+        job_id: job.job_id.clone(),
+        from_version: VERSION.to_string(),
+        to_version: version.to_string(),
+        exe_path: exe_path.clone(),
+        backup_path: backup_path.clone(),
+        service_name: config.service_name.clone(),
+        started_at: now,
+        deadline: now + config.update_health_deadline_sec as i64,
+        rollback_on_failure,
+        rolled_back: false,
+    };
+    save_state(&state_file, &state)?;
+
+    // ── Guard + restart ─────────────────────────────────────
+    if rollback_on_failure {
+        if let Err(e) = platform::spawn_guard(&backup_path, &state_file) {
+            // Without a guard a broken build could not be undone
+            let _ = std::fs::rename(&backup_path, &exe_path);
+            let _ = std::fs::remove_file(&state_file);
+            return Err(e.context("Failed to start update guard"));
+        }
+    }
+
+    let service = config.service_name.clone();
+    tokio::spawn(async move {
+        // Give the runner time to report the interim result
+        sleep(Duration::from_secs(3)).await;
+        if let Err(e) = platform::restart_service(&service) {
+            tracing::error!("Failed to restart agent service: {}", e);
+        }
+    });
+
+    // The final success/failure is reported by whichever binary runs next
+    Ok(update_result(job, "running", None, serde_json::json!({
+        "stage": "restarting",
+        "from_version": VERSION,
+        "to_version": version,
+        "health_deadline": state.deadline,
+    })))
+}
+
+fn update_result(
+    job: &JobRequest,
+    status: &str,
+    error: Option<String>,
+    data: serde_json::Value,
+) -> JobResult {
+    JobResult {
+        job_id: job.job_id.clone(),
+        status: status.to_string(),
+        started_at: 0,
+        completed_at: 0,
+        stdout: None,
+        stderr: None,
+        exit_code: None,
+        error_message: error,
+        artifacts: Vec::new(),
+        result_data: Some(data),
+    }
+}
+
+fn pinned_key(config: &AgentConfig) -> Result<VerifyingKey> {
+    let encoded = config.update_public_key.as_deref()
+        .or(BUILTIN_UPDATE_KEY)
+        .context("No update signing key pinned; refusing to update")?;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .context("Update public key is not valid base64")?;
+    let bytes: [u8; 32] = bytes.try_into()
+        .map_err(|_| anyhow::anyhow!("Update public key must be 32 bytes"))?;
+    VerifyingKey::from_bytes(&bytes).context("Invalid update public key")
+}
+
+/// What a release signature covers. The SHA-256 binds the binary;
+/// version and channel keep a signed build from being offered as
+/// anything else.
+fn signing_message(version: &str, channel: &str, sha256: &str) -> String {
+    format!("{}|{}|{}", version, channel, sha256.to_ascii_lowercase())
+}
+
+fn verify_signature(message: &str, signature: &str, key: &VerifyingKey) -> Result<()> {
+    let sig_bytes = base64::engine::general_purpose::STANDARD
+        .decode(signature.trim())
+        .context("Signature is not valid base64")?;
+    let signature = Signature::from_slice(&sig_bytes).context("Malformed signature")?;
+    key.verify_strict(message.as_bytes(), &signature)
+        .map_err(|_| anyhow::anyhow!("Update signature verification failed"))
+}
+
+/// Compare dotted numeric versions ("1.2.10" > "1.2.9"). A
+/// pre-release ("1.3.0-rc.1") sorts before its release. None when
+/// either version is not of that form.
+fn compare_versions(a: &str, b: &str) -> Option<Ordering> {
+    fn parse(version: &str) -> Option<(Vec<u64>, bool)> {
+        let version = version.trim().trim_start_matches('v');
+        let version = version.split('+').next()?;
+        let (release, pre) = match version.split_once('-') {
+            Some((release, _)) => (release, true),
+            None => (version, false),
+        };
+        let parts = release.split('.').map(|p| p.parse().ok()).collect::<Option<Vec<u64>>>()?;
+        Some((parts, pre))
+    }
+
+    let ((mut a, a_pre), (mut b, b_pre)) = (parse(a)?, parse(b)?);
+    let len = a.len().max(b.len());
+    a.resize(len, 0);
+    b.resize(len, 0);
+    // Same release: the pre-release is the older one
+    Some(a.cmp(&b).then(b_pre.cmp(&a_pre)))
+}
+
+/// Stream `url` into `dest`, returning the hex SHA-256 of the body.
+async fn download(url: &str, dest: &Path) -> Result<String> {
+    let http = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(10))
+        .timeout(Duration::from_secs(600))
+        .user_agent(format!("MASSVISION-Agent/{}", VERSION))
+        .build()?;
+
+    let mut response = http.get(url).send().await
+        .context("Update download failed")?;
+    if !response.status().is_success() {
+        bail!("Update download failed (HTTP {})", response.status());
+    }
+
+    let mut file = tokio::fs::File::create(dest).await
+        .with_context(|| format!("Failed to create {:?}", dest))?;
+    let mut hasher = Sha256::new();
+    while let Some(chunk) = response.chunk().await? {
+        hasher.update(&chunk);
+        file.write_all(&chunk).await?;
+    }
+    file.sync_all().await?;
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", suffix));
+    path.with_file_name(name)
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Post-restart Confirmation
+// ═══════════════════════════════════════════════════════════════
+
+/// Called once at startup: settle an update left by the previous run.
+pub async fn finish_pending(
+    client: &RwLock<AgentClient>,
+    outbox: &Outbox,
+    capabilities: Vec<String>,
+) {
+    let path = state_path();
+    let Some(state) = load_state(&path) else { return };
+
+    let result = if state.rolled_back {
+        failed_result(&state, format!(
+            "Version {} did not check in within {}s; rolled back to {}",
+            state.to_version,
+            state.deadline - state.started_at,
+            state.from_version,
+        ))
+    } else if VERSION != state.to_version {
+        failed_result(&state, format!(
+            "Expected version {} after restart but {} is running",
+            state.to_version, VERSION,
+        ))
+    } else {
+        // Healthy means the server answered a heartbeat from this
+        // binary. It goes over HTTP: a frame queued on the socket says
+        // nothing about whether the server is reachable.
+        let mut healthy = false;
+        loop {
+            let payload = HeartbeatPayload {
+                status: "online".to_string(),
+                uptime_sec: sysinfo::System::uptime(),
+                agent_version: VERSION.to_string(),
+                active_jobs: vec![],
+                capabilities: capabilities.clone(),
+            };
+            if client.read().await.heartbeat_http(payload).await.is_ok() {
+                healthy = true;
+                break;
+            }
+            if chrono::Utc::now().timestamp() >= state.deadline {
+                break;
+            }
+            sleep(Duration::from_secs(5)).await;
+        }
+
+        if !healthy {
+            if state.rollback_on_failure {
+                // The guard rolls back and the previous version reports
+                tracing::error!("Update health deadline passed without a heartbeat");
+                return;
+            }
+            // No guard will ever clear the state file; without this every
+            // later update would be refused as already in progress
+            let result = failed_result(&state, format!(
+                "Version {} did not check in within {}s; rollback disabled, still running {}",
+                state.to_version,
+                state.deadline - state.started_at,
+                state.to_version,
+            ));
+            let _ = std::fs::remove_file(&path);
+            report(client, outbox, result).await;
+            return;
+        }
+
+        tracing::info!("Update to {} confirmed healthy", state.to_version);
+        JobResult {
+            job_id: state.job_id.clone(),
+            status: "success".to_string(),
+            started_at: state.started_at,
+            completed_at: chrono::Utc::now().timestamp(),
+            stdout: None,
+            stderr: None,
+            exit_code: None,
+            error_message: None,
+            artifacts: Vec::new(),
+            result_data: Some(serde_json::json!({
+                "from_version": state.from_version,
+                "to_version": state.to_version,
+            })),
+        }
+    };
+
+    // Removing the state file is what stands the guard down
+    let _ = std::fs::remove_file(&path);
+    report(client, outbox, result).await;
+}
+
+async fn report(client: &RwLock<AgentClient>, outbox: &Outbox, result: JobResult) {
+    if let Err(e) = client.read().await.report_job_result(result.clone()).await {
+        tracing::warn!("Failed to report update result, queued for retry: {}", e);
+        if let Err(e) = outbox.push("job_result", &result) {
+            tracing::error!("Failed to queue update result: {}", e);
+        }
+    }
+}
+
+fn failed_result(state: &UpdateState, message: String) -> JobResult {
+    tracing::error!("{}", message);
+    JobResult {
+        job_id: state.job_id.clone(),
+        status: "failed".to_string(),
+        started_at: state.started_at,
+        completed_at: chrono::Utc::now().timestamp(),
+        stdout: None,
+        stderr: None,
+        exit_code: None,
+        error_message: Some(message),
+        artifacts: Vec::new(),
+        result_data: Some(serde_json::json!({
+            "from_version": state.from_version,
+            "to_version": state.to_version,
+            "rolled_back": state.rolled_back,
+        })),
+    }
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Rollback Guard (runs as `<exe>.prev --update-guard <state>`)
+// ═══════════════════════════════════════════════════════════════
+
+pub fn run_guard(state_file: &Path) -> Result<()> {
+    let state = load_state(state_file).context("No pending update state")?;
+    tracing::info!("Update guard watching {} until {}", state.to_version, state.deadline);
+
+    while chrono::Utc::now().timestamp() < state.deadline {
+        if !state_file.exists() {
+            tracing::info!("Update confirmed, guard exiting");
+            return Ok(());
+        }
+        std::thread::sleep(std::time::Duration::from_secs(2));
+    }
+
+    let Some(mut state) = load_state(state_file) else {
+        return Ok(());
+    };
+
+    tracing::error!("Version {} missed its health deadline, rolling back", state.to_version);
+
+    let restore = sibling(&state.exe_path, "rollback");
+    std::fs::copy(&state.backup_path, &restore)?;
+    platform::make_executable(&restore)?;
+    std::fs::rename(&restore, &state.exe_path)?;
+
+    state.rolled_back = true;
+    save_state(state_file, &state)?;
+
+    platform::restart_service(&state.service_name)
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Platform-specific implementations
+// ═══════════════════════════════════════════════════════════════
+
+#[cfg(unix)]
+mod platform {
+    use anyhow::{Context, Result, bail};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::process::CommandExt;
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+
+    pub fn ensure_supported() -> Result<()> {
+        // systemd sets INVOCATION_ID for every unit it starts
+        if std::env::var_os("INVOCATION_ID").is_none() {
+            bail!("Agent is not running under systemd; cannot restart itself");
+        }
+        Ok(())
+    }
+
+    pub fn make_executable(path: &Path) -> Result<()> {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+        Ok(())
+    }
+
+    /// Run the guard in its own transient unit so restarting the
+    /// agent service does not take it down with the service cgroup.
+    pub fn spawn_guard(binary: &Path, state_file: &Path) -> Result<()> {
+        let unit = format!("massvision-agent-update-guard-{}", chrono::Utc::now().timestamp());
+        let status = Command::new("systemd-run")
+            .args(["--quiet", "--collect", "--unit", &unit])
+            .arg(binary)
+            .arg("--update-guard")
+            .arg(state_file)
+            .status();
+
+        if matches!(status, Ok(s) if s.success()) {
+            return Ok(());
+        }
+
+        tracing::warn!("systemd-run unavailable, starting update guard as a detached process");
+        let mut cmd = Command::new(binary);
+        cmd.arg("--update-guard")
+            .arg(state_file)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        unsafe {
+            cmd.pre_exec(|| {
+                nix::unistd::setsid()?;
+                Ok(())
+            });
+        }
+        cmd.spawn().context("Failed to spawn update guard")?;
+        Ok(())
+    }
+
+    pub fn restart_service(service: &str) -> Result<()> {
+        let status = Command::new("systemctl")
+            .args(["restart", "--no-block", service])
+            .status()
+            .context("Failed to run systemctl")?;
+        if !status.success() {
+            bail!("systemctl restart {} exited with {}", service, status);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(unix))]
+mod platform {
+    use anyhow::{Result, bail};
+    use std::path::Path;
+
+    pub fn ensure_supported() -> Result<()> {
+        bail!("Self-update is only supported on systemd hosts")
+    }
+
+    pub fn make_executable(_path: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    pub fn spawn_guard(_binary: &Path, _state_file: &Path) -> Result<()> {
+        bail!("Self-update is only supported on systemd hosts")
+    }
+
+    pub fn restart_service(_service: &str) -> Result<()> {
+        bail!("Self-update is only supported on systemd hosts")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    const SHA: &str = "9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08";
+
+    fn sign(key: &SigningKey, message: &str) -> String {
+        base64::engine::general_purpose::STANDARD.encode(key.sign(message.as_bytes()).to_bytes())
+    }
+
+    #[test]
+    fn signature_covers_version_and_channel() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let signature = sign(&key, &signing_message("1.2.0", "stable", SHA));
+        let verifying = key.verifying_key();
+
+        assert!(verify_signature(&signing_message("1.2.0", "stable", SHA), &signature, &verifying).is_ok());
+        assert!(verify_signature(&signing_message("1.1.0", "stable", SHA), &signature, &verifying).is_err());
+        assert!(verify_signature(&signing_message("1.2.0", "beta", SHA), &signature, &verifying).is_err());
+    }
+
+    #[test]
+    fn signing_message_lowercases_digest() {
+        assert_eq!(signing_message("1.2.0", "stable", "ABCdef"), "1.2.0|stable|abcdef");
+    }
+
+    #[test]
+    fn compares_versions_numerically() {
+        assert_eq!(compare_versions("1.2.10", "1.2.9"), Some(Ordering::Greater));
+        assert_eq!(compare_versions("1.2", "1.2.0"), Some(Ordering::Equal));
+        assert_eq!(compare_versions("v2.0.0", "1.9.9"), Some(Ordering::Greater));
+        assert_eq!(compare_versions("1.3.0-rc.1", "1.3.0"), Some(Ordering::Less));
+        assert_eq!(compare_versions("1.3.0", "1.3.0-rc.1"), Some(Ordering::Greater));
+        assert_eq!(compare_versions("1.0.0+build.5", "1.0.0"), Some(Ordering::Equal));
+        assert_eq!(compare_versions("nightly", "1.0.0"), None);
+    }
+}
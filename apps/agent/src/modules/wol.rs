@@ -0,0 +1,210 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r Agent - Wake-on-LAN
+// ─────────────────────────────────────────────────────────────
+//
+// wake_on_lan: build the magic packet (6 × 0xFF, then the target
+// MAC 16 times, then an optional 4- or 6-byte SecureOn password)
+// and broadcast it over UDP. Without an explicit broadcast_address
+// the packet goes to the directed broadcast of every up, non-
+// loopback IPv4 interface, so one online agent per site can wake
+// the sleeping machines around it.
+// ─────────────────────────────────────────────────────────────
+
+use anyhow::{Context, Result, bail};
+use std::net::{Ipv4Addr, SocketAddrV4};
+use tokio::net::UdpSocket;
+use tokio::time::{sleep, Duration};
+
+use crate::comms::protocol::{JobRequest, JobResult, WakeOnLanPayload};
+
+const DEFAULT_PORT: u16 = 9;
+
+/// UDP gives no delivery guarantee; NICs only need to see one copy.
+const REPEAT: usize = 3;
+const REPEAT_GAP: Duration = Duration::from_millis(100);
+
+// ═══════════════════════════════════════════════════════════════
+// Job Handler
+// ═══════════════════════════════════════════════════════════════
+
+pub async fn wake_on_lan(job: &JobRequest, payload: &WakeOnLanPayload) -> Result<JobResult> {
+    let mac = parse_hex_bytes(&payload.target_mac)
+        .filter(|bytes| bytes.len() == 6)
+        .with_context(|| format!("Invalid target_mac: {}", payload.target_mac))?;
+
+    let password = match payload.secureon_password.as_deref() {
+        Some(raw) if !raw.is_empty() => Some(
+            parse_hex_bytes(raw)
+                .filter(|bytes| bytes.len() == 4 || bytes.len() == 6)
+                .context("secureon_password must be 4 or 6 bytes of hex")?,
+        ),
+        _ => None,
+    };
+
+    let port = match payload.port {
+        Some(0) => bail!("Invalid port: 0"),
+        Some(port) => port,
+        None => DEFAULT_PORT,
+    };
+
+    let packet = magic_packet(&mac, password.as_deref());
+
+    let targets = match payload.broadcast_address.as_deref() {
+        Some(addr) if !addr.is_empty() => {
+            let broadcast: Ipv4Addr = addr.parse()
+                .with_context(|| format!("Invalid broadcast_address: {}", addr))?;
+            vec![explicit_target(broadcast)]
+        }
+        _ => interface_targets()?,
+    };
+    if targets.is_empty() {
+        bail!("No IPv4 interface to send the magic packet from");
+    }
+
+    let mut sent_from = Vec::new();
+    let mut failed = Vec::new();
+    for target in &targets {
+        match send(&packet, target, port).await {
+            Ok(()) => {
+                tracing::info!(
+                    "Sent Wake-on-LAN for {} to {}:{} via {}",
+                    format_mac(&mac), target.broadcast, port, target.interface,
+                );
+                sent_from.push(target.to_json());
+            }
+            Err(e) => {
+                tracing::warn!("Wake-on-LAN via {} failed: {:#}", target.interface, e);
+                let mut entry = target.to_json();
+                entry["error"] = serde_json::json!(format!("{:#}", e));
+                failed.push(entry);
+            }
+        }
+    }
+
+    if sent_from.is_empty() {
+        bail!("Magic packet could not be sent from any interface");
+    }
+
+    Ok(JobResult {
+        job_id: job.job_id.clone(),
+        status: "success".to_string(),
+        started_at: 0,
+        completed_at: 0,
+        stdout: None,
+        stderr: None,
+        exit_code: None,
+        error_message: None,
+        artifacts: Vec::new(),
+        result_data: Some(serde_json::json!({
+            "target_mac": format_mac(&mac),
+            "target_agent_id": payload.target_agent_id,
+            "port": port,
+            "secureon": password.is_some(),
+            "sent_from": sent_from,
+            "failed": failed,
+        })),
+    })
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Targets
+// ═══════════════════════════════════════════════════════════════
+
+struct Target {
+    interface: String,
+    /// Source address the socket binds to; unspecified lets the
+    /// routing table choose.
+    source: Ipv4Addr,
+    broadcast: Ipv4Addr,
+}
+
+impl Target {
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "interface": self.interface,
+            "address": self.source.to_string(),
+            "broadcast_address": self.broadcast.to_string(),
+        })
+    }
+}
+
+/// The operator named the broadcast address: send from the interface
+/// on that subnet, or let the routing table pick one.
+fn explicit_target(broadcast: Ipv4Addr) -> Target {
+    let local = if_addrs::get_if_addrs().unwrap_or_default().into_iter().find_map(|iface| {
+        match iface.addr {
+            if_addrs::IfAddr::V4(v4) if !iface.is_loopback()
+                && directed_broadcast(v4.ip, v4.netmask) == broadcast => Some((iface.name, v4.ip)),
+            _ => None,
+        }
+    });
+    match local {
+        Some((interface, source)) => Target { interface, source, broadcast },
+        None => Target { interface: "default".to_string(), source: Ipv4Addr::UNSPECIFIED, broadcast },
+    }
+}
+
+fn interface_targets() -> Result<Vec<Target>> {
+    let interfaces = if_addrs::get_if_addrs().context("Failed to list network interfaces")?;
+    let mut targets = Vec::new();
+    for iface in interfaces {
+        let if_addrs::IfAddr::V4(v4) = &iface.addr else { continue };
+        // Point-to-point links (/31, /32) have no neighbours to wake
+        if iface.is_loopback() || v4.prefixlen >= 31 {
+            continue;
+        }
+        let broadcast = v4.broadcast.unwrap_or_else(|| directed_broadcast(v4.ip, v4.netmask));
+        if targets.iter().any(|t: &Target| t.broadcast == broadcast && t.source == v4.ip) {
+            continue;
+        }
+        targets.push(Target { interface: iface.name.clone(), source: v4.ip, broadcast });
+    }
+    Ok(targets)
+}
+
+fn directed_broadcast(ip: Ipv4Addr, netmask: Ipv4Addr) -> Ipv4Addr {
+    Ipv4Addr::from(u32::from(ip) | !u32::from(netmask))
+}
+
+async fn send(packet: &[u8], target: &Target, port: u16) -> Result<()> {
+    let socket = UdpSocket::bind(SocketAddrV4::new(target.source, 0)).await
+        .with_context(|| format!("Cannot bind {}", target.source))?;
+    socket.set_broadcast(true)?;
+
+    let dest = SocketAddrV4::new(target.broadcast, port);
+    for i in 0..REPEAT {
+        if i > 0 {
+            sleep(REPEAT_GAP).await;
+        }
+        socket.send_to(packet, dest).await
+            .with_context(|| format!("Send to {} failed", dest))?;
+    }
+    Ok(())
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Magic Packet
+// ═══════════════════════════════════════════════════════════════
+
+fn magic_packet(mac: &[u8], password: Option<&[u8]>) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(6 + 16 * 6 + 6);
+    packet.extend_from_slice(&[0xFF; 6]);
+    for _ in 0..16 {
+        packet.extend_from_slice(mac);
+    }
+    if let Some(password) = password {
+        packet.extend_from_slice(password);
+    }
+    packet
+}
+
+/// Accepts `aa:bb:cc:dd:ee:ff`, `aa-bb-cc-dd-ee-ff`, `aabb.ccdd.eeff`
+/// and bare hex.
+fn parse_hex_bytes(value: &str) -> Option<Vec<u8>> {
+    let digits: String = value.chars().filter(|c| !matches!(c, ':' | '-' | '.' | ' ')).collect();
+    hex::decode(digits).ok()
+}
+
+fn format_mac(mac: &[u8]) -> String {
+    mac.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(":")
+}
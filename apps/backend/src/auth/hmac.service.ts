@@ -3,6 +3,7 @@
 // ─────────────────────────────────────────────────────────────
 
 import crypto from 'crypto';
+import { v4 as uuidv4 } from 'uuid';
 import { config } from '../config/index.js';
 import type { AgentEnvelope } from '@massvision/shared';
 import { queryOne } from '../db/connection.js';
@@ -89,10 +90,7 @@ export async function validateEnvelope(
   // We use the HMAC secret derived from agent_secret_hash for simplicity:
   // In production, the agent_secret is exchanged during enrollment and both sides use it.
   // Here we verify using the global HMAC secret + agent_id as the key.
-  const hmacKey = crypto
-    .createHmac('sha256', config.AGENT_HMAC_SECRET)
-    .update(envelope.agent_id)
-    .digest('hex');
+  const hmacKey = deriveAgentKey(envelope.agent_id);
 
   const hmacValid = verifyHmacWithKey(hmacKey, envelope);
   if (!hmacValid) {
@@ -111,3 +109,25 @@ function verifyHmacWithKey(key: string, envelope: AgentEnvelope): boolean {
     return false;
   }
 }
+
+function deriveAgentKey(agentId: string): string {
+  return crypto
+    .createHmac('sha256', config.AGENT_HMAC_SECRET)
+    .update(agentId)
+    .digest('hex');
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Server → Agent envelopes (WebSocket channel)
+// ═══════════════════════════════════════════════════════════════
+
+export function signServerEnvelope<T>(agentId: string, type: string, payload: T): AgentEnvelope<T> {
+  const unsigned = {
+    agent_id: agentId,
+    ts: Math.floor(Date.now() / 1000),
+    nonce: uuidv4(),
+    type: type as AgentEnvelope['type'],
+    payload,
+  };
+  return { ...unsigned, hmac: computeHmac(deriveAgentKey(agentId), unsigned) };
+}
@@ -38,6 +38,10 @@ const configSchema = z.object({
   ZABBIX_API_URL: z.string().optional(),
   ZABBIX_API_TOKEN: z.string().optional(),
 
+  // Artifacts collected from agents
+  ARTIFACT_STORAGE_DIR: z.string().default('./data/artifacts'),
+  ARTIFACT_MAX_BYTES: z.coerce.number().default(2 * 1024 * 1024 * 1024),
+
   // Agent Update
   AGENT_UPDATE_BASE_URL: z.string().default('https://updates.massvision.io'),
 });
@@ -0,0 +1,9 @@
+-- ═══════════════════════════════════════════════════════════════
+-- MASSVISION Reap3r - Migration 003: Running Job Results
+-- agent_update reports an interim "running" result while the new
+-- binary is being health-checked
+-- ═══════════════════════════════════════════════════════════════
+
+ALTER TABLE job_results DROP CONSTRAINT IF EXISTS job_results_status_check;
+ALTER TABLE job_results ADD CONSTRAINT job_results_status_check
+  CHECK (status IN ('running', 'success', 'failed', 'timeout'));
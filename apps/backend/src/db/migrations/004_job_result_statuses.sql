@@ -0,0 +1,8 @@
+-- ═══════════════════════════════════════════════════════════════
+-- MASSVISION Reap3r - Migration 004: Job Result Statuses
+-- Agents report "cancelled" results for jobs stopped mid-run
+-- ═══════════════════════════════════════════════════════════════
+
+ALTER TABLE job_results DROP CONSTRAINT IF EXISTS job_results_status_check;
+ALTER TABLE job_results ADD CONSTRAINT job_results_status_check
+  CHECK (status IN ('running', 'success', 'failed', 'timeout', 'cancelled'));
@@ -0,0 +1,14 @@
+-- ═══════════════════════════════════════════════════════════════
+-- MASSVISION Reap3r - Migration 005: Job Output Stream
+-- Incremental stdout/stderr chunks sent while a job runs
+-- ═══════════════════════════════════════════════════════════════
+
+CREATE TABLE job_output_chunks (
+  id BIGSERIAL PRIMARY KEY,
+  job_id UUID NOT NULL REFERENCES jobs(id) ON DELETE CASCADE,
+  seq INT NOT NULL,
+  stream VARCHAR(10) NOT NULL CHECK (stream IN ('stdout', 'stderr')),
+  data TEXT NOT NULL,
+  timestamp TIMESTAMPTZ NOT NULL,
+  UNIQUE (job_id, seq)
+);
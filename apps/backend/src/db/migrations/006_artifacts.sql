@@ -0,0 +1,22 @@
+-- ═══════════════════════════════════════════════════════════════
+-- MASSVISION Reap3r - Migration 006: Artifacts
+-- Files collected from endpoints by artifact_upload jobs; the
+-- bytes live under ARTIFACT_STORAGE_DIR/<id>
+-- ═══════════════════════════════════════════════════════════════
+
+CREATE TABLE artifacts (
+  id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+  organization_id UUID NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+  agent_id UUID NOT NULL REFERENCES agents(id) ON DELETE CASCADE,
+  job_id UUID NOT NULL REFERENCES jobs(id) ON DELETE CASCADE,
+  filename VARCHAR(255) NOT NULL,
+  mime_type VARCHAR(100) NOT NULL,
+  size_bytes BIGINT NOT NULL,
+  sha256 CHAR(64) NOT NULL,
+  status VARCHAR(20) NOT NULL DEFAULT 'uploading' CHECK (status IN ('uploading', 'complete')),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  completed_at TIMESTAMPTZ,
+  UNIQUE (job_id, sha256)
+);
+
+CREATE INDEX idx_artifacts_org ON artifacts(organization_id);
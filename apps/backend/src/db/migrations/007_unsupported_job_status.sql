@@ -0,0 +1,13 @@
+-- ═══════════════════════════════════════════════════════════════
+-- MASSVISION Reap3r - Migration 007: Unsupported Job Status
+-- Agents answer job types they do not know with "unsupported"
+-- instead of a generic failure
+-- ═══════════════════════════════════════════════════════════════
+
+ALTER TABLE jobs DROP CONSTRAINT IF EXISTS jobs_status_check;
+ALTER TABLE jobs ADD CONSTRAINT jobs_status_check
+  CHECK (status IN ('pending', 'queued', 'running', 'success', 'failed', 'timeout', 'cancelled', 'agent_offline', 'unsupported'));
+
+ALTER TABLE job_results DROP CONSTRAINT IF EXISTS job_results_status_check;
+ALTER TABLE job_results ADD CONSTRAINT job_results_status_check
+  CHECK (status IN ('running', 'success', 'failed', 'timeout', 'cancelled', 'unsupported'));
@@ -0,0 +1,23 @@
+-- ═══════════════════════════════════════════════════════════════
+-- MASSVISION Reap3r - Migration 008: Process List
+-- New processes.view permission for process_list jobs, granted
+-- wherever processes.kill already is; policies that allow
+-- process_kill also allow process_list
+-- ═══════════════════════════════════════════════════════════════
+
+UPDATE roles
+SET permissions = array_append(permissions, 'processes.view'), updated_at = NOW()
+WHERE 'processes.kill' = ANY(permissions)
+  AND NOT 'processes.view' = ANY(permissions);
+
+UPDATE agents
+SET policy = jsonb_set(policy, '{allowed_job_types}', (policy->'allowed_job_types') || '"process_list"'::jsonb)
+WHERE jsonb_typeof(policy->'allowed_job_types') = 'array'
+  AND policy->'allowed_job_types' ? 'process_kill'
+  AND NOT policy->'allowed_job_types' ? 'process_list';
+
+UPDATE enrollment_tokens
+SET policy = jsonb_set(policy, '{allowed_job_types}', (policy->'allowed_job_types') || '"process_list"'::jsonb)
+WHERE jsonb_typeof(policy->'allowed_job_types') = 'array'
+  AND policy->'allowed_job_types' ? 'process_kill'
+  AND NOT policy->'allowed_job_types' ? 'process_list';
@@ -0,0 +1,15 @@
+-- ═══════════════════════════════════════════════════════════════
+-- MASSVISION Reap3r - Migration 009: Metrics History
+-- Policies with an explicit allowed_job_types list also allow
+-- metrics_history, which only reads the agent's sample buffer
+-- ═══════════════════════════════════════════════════════════════
+
+UPDATE agents
+SET policy = jsonb_set(policy, '{allowed_job_types}', (policy->'allowed_job_types') || '"metrics_history"'::jsonb)
+WHERE jsonb_typeof(policy->'allowed_job_types') = 'array'
+  AND NOT policy->'allowed_job_types' ? 'metrics_history';
+
+UPDATE enrollment_tokens
+SET policy = jsonb_set(policy, '{allowed_job_types}', (policy->'allowed_job_types') || '"metrics_history"'::jsonb)
+WHERE jsonb_typeof(policy->'allowed_job_types') = 'array'
+  AND NOT policy->'allowed_job_types' ? 'metrics_history';
@@ -0,0 +1,28 @@
+-- ═══════════════════════════════════════════════════════════════
+-- MASSVISION Reap3r - Migration 010: Agent Alerts
+-- Threshold alerts evaluated on the agent against its policy's
+-- alert_rules; one row per firing, closed when it resolves
+-- ═══════════════════════════════════════════════════════════════
+
+CREATE TABLE agent_alerts (
+  id BIGSERIAL PRIMARY KEY,
+  agent_id UUID NOT NULL REFERENCES agents(id) ON DELETE CASCADE,
+  organization_id UUID NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+  rule_id VARCHAR(100) NOT NULL,
+  rule_name VARCHAR(255),
+  -- Mount point, interface or sensor; '' for host-wide metrics
+  instance VARCHAR(255) NOT NULL DEFAULT '',
+  metric VARCHAR(100) NOT NULL,
+  severity VARCHAR(20) NOT NULL CHECK (severity IN ('info', 'warning', 'critical')),
+  state VARCHAR(20) NOT NULL CHECK (state IN ('firing', 'resolved')),
+  operator VARCHAR(2) NOT NULL,
+  threshold DOUBLE PRECISION NOT NULL,
+  value DOUBLE PRECISION,
+  started_at TIMESTAMPTZ NOT NULL,
+  resolved_at TIMESTAMPTZ,
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- At most one open alert per rule and instance
+CREATE UNIQUE INDEX idx_agent_alerts_open ON agent_alerts(agent_id, rule_id, instance) WHERE state = 'firing';
+CREATE INDEX idx_agent_alerts_org ON agent_alerts(organization_id, state, started_at DESC);
@@ -20,10 +20,12 @@ import { authRoutes } from './routes/ui/auth.routes.js';
 import { agentRoutes } from './routes/ui/agents.routes.js';
 import { jobRoutes } from './routes/ui/jobs.routes.js';
 import { dashboardRoutes } from './routes/ui/dashboard.routes.js';
+import { artifactRoutes } from './routes/ui/artifacts.routes.js';
 import { agentV2Routes } from './routes/agent-v2/agent.routes.js';
 
 // WebSocket
 import { setupWebSocket } from './websocket/handler.js';
+import { setupAgentChannel } from './websocket/agent-channel.js';
 
 // Background jobs
 import { startBackgroundJobs } from './jobs/scheduler.js';
@@ -167,8 +169,10 @@ await app.register(authRoutes);
 await app.register(agentRoutes, { prefix: '/api' });
 await app.register(jobRoutes, { prefix: '/api' });
 await app.register(dashboardRoutes, { prefix: '/api' });
+await app.register(artifactRoutes, { prefix: '/api' });
 await app.register(agentV2Routes);
 await setupWebSocket(app);
+await setupAgentChannel(app);
 
 // ═══════════════════════════════════════════════════════════════
 // Start Server
@@ -7,6 +7,8 @@ import { z } from 'zod';
 import { validateEnvelope } from '../../auth/hmac.service.js';
 import * as agentService from '../../services/agent.service.js';
 import * as jobService from '../../services/job.service.js';
+import * as shellService from '../../services/shell.service.js';
+import * as artifactService from '../../services/artifact.service.js';
 import {
   agentHeartbeatCounter,
   enrollmentCounter,
@@ -16,8 +18,15 @@ import type {
   EnrollRequest,
   HeartbeatPayload,
   MetricsPayload,
+  AlertPayload,
   InventoryPayload,
   JobResult,
+  JobOutputChunk,
+  ShellOutputPayload,
+  ArtifactUploadInit,
+  ArtifactChunk,
+  ArtifactUploadComplete,
+  ShellPollPayload,
 } from '@massvision/shared';
 
 const enrollSchema = z.object({
@@ -88,17 +97,20 @@ export async function agentV2Routes(app: FastifyInstance): Promise<void> {
   }, async (request, reply) => {
     const envelope = (request as unknown as Record<string, unknown>).envelope as AgentEnvelope<HeartbeatPayload>;
 
-    await agentService.processHeartbeat(envelope.agent_id, envelope.payload);
+    const policy = await agentService.processHeartbeat(envelope.agent_id, envelope.payload);
     agentHeartbeatCounter.inc();
 
-    // Check for pending jobs
+    // Check for pending jobs and cancellations
     const nextJob = await jobService.getNextJobForAgent(envelope.agent_id);
+    const cancelJobs = await jobService.drainJobCancels(envelope.agent_id);
 
     return reply.send({
       success: true,
       data: {
         ack: true,
         pending_job: nextJob ?? null,
+        cancel_jobs: cancelJobs,
+        policy,
       },
     });
   });
@@ -114,6 +126,17 @@ export async function agentV2Routes(app: FastifyInstance): Promise<void> {
     return reply.send({ success: true, data: { ack: true } });
   });
 
+  // ─── POST /agent-v2/alert ───
+  app.post('/agent-v2/alert', {
+    preHandler: validateAgentEnvelope,
+  }, async (request, reply) => {
+    const envelope = (request as unknown as Record<string, unknown>).envelope as AgentEnvelope<AlertPayload>;
+
+    await agentService.processAlert(envelope.agent_id, envelope.payload);
+
+    return reply.send({ success: true, data: { ack: true } });
+  });
+
   // ─── POST /agent-v2/inventory ───
   app.post('/agent-v2/inventory', {
     preHandler: validateAgentEnvelope,
@@ -143,6 +166,83 @@ export async function agentV2Routes(app: FastifyInstance): Promise<void> {
     }
   });
 
+  // ─── POST /agent-v2/job-output ───
+  app.post('/agent-v2/job-output', {
+    preHandler: validateAgentEnvelope,
+    // Flushed up to 4x/s per running job; every request is HMAC-signed
+    config: { rateLimit: false },
+  }, async (request, reply) => {
+    const envelope = (request as unknown as Record<string, unknown>).envelope as AgentEnvelope<JobOutputChunk>;
+
+    try {
+      await jobService.processJobOutput(envelope.agent_id, envelope.payload);
+      return reply.send({ success: true, data: { ack: true } });
+    } catch (error) {
+      const message = error instanceof Error ? error.message : 'Failed to process job output';
+      return reply.code(400).send({
+        success: false,
+        error: { code: 'JOB_OUTPUT_FAILED', message },
+      });
+    }
+  });
+
+  // ─── POST /agent-v2/artifacts/init ───
+  app.post('/agent-v2/artifacts/init', {
+    preHandler: validateAgentEnvelope,
+  }, async (request, reply) => {
+    const envelope = (request as unknown as Record<string, unknown>).envelope as AgentEnvelope<ArtifactUploadInit>;
+
+    try {
+      const session = await artifactService.initUpload(envelope.agent_id, envelope.payload);
+      return reply.send({ success: true, data: session });
+    } catch (error) {
+      const message = error instanceof Error ? error.message : 'Failed to start artifact upload';
+      return reply.code(400).send({
+        success: false,
+        error: { code: 'ARTIFACT_INIT_FAILED', message },
+      });
+    }
+  });
+
+  // ─── POST /agent-v2/artifacts/chunk ───
+  app.post('/agent-v2/artifacts/chunk', {
+    preHandler: validateAgentEnvelope,
+    // 512 KiB of base64 plus the envelope; signed, so not IP rate-limited
+    bodyLimit: 2 * 1024 * 1024,
+    config: { rateLimit: false },
+  }, async (request, reply) => {
+    const envelope = (request as unknown as Record<string, unknown>).envelope as AgentEnvelope<ArtifactChunk>;
+
+    try {
+      const received = await artifactService.writeChunk(envelope.agent_id, envelope.payload);
+      return reply.send({ success: true, data: { received_bytes: received } });
+    } catch (error) {
+      const message = error instanceof Error ? error.message : 'Failed to store artifact chunk';
+      return reply.code(409).send({
+        success: false,
+        error: { code: 'ARTIFACT_CHUNK_FAILED', message },
+      });
+    }
+  });
+
+  // ─── POST /agent-v2/artifacts/complete ───
+  app.post('/agent-v2/artifacts/complete', {
+    preHandler: validateAgentEnvelope,
+  }, async (request, reply) => {
+    const envelope = (request as unknown as Record<string, unknown>).envelope as AgentEnvelope<ArtifactUploadComplete>;
+
+    try {
+      const artifact = await artifactService.completeUpload(envelope.agent_id, envelope.payload.artifact_id);
+      return reply.send({ success: true, data: artifact });
+    } catch (error) {
+      const message = error instanceof Error ? error.message : 'Failed to complete artifact upload';
+      return reply.code(409).send({
+        success: false,
+        error: { code: 'ARTIFACT_COMPLETE_FAILED', message },
+      });
+    }
+  });
+
   // ─── POST /agent-v2/jobs/next ───
   app.post('/agent-v2/jobs/next', {
     preHandler: validateAgentEnvelope,
@@ -156,4 +256,29 @@ export async function agentV2Routes(app: FastifyInstance): Promise<void> {
       data: job ?? null,
     });
   });
+
+  // ─── POST /agent-v2/shell/output ───
+  app.post('/agent-v2/shell/output', {
+    preHandler: validateAgentEnvelope,
+  }, async (request, reply) => {
+    const envelope = (request as unknown as Record<string, unknown>).envelope as AgentEnvelope<ShellOutputPayload>;
+    const agent = (request as unknown as Record<string, unknown>).agentRecord as { organization_id: string };
+
+    if (!(await shellService.publishShellOutput(envelope.agent_id, agent.organization_id, envelope.payload))) {
+      return reply.status(403).send({ success: false, error: 'Shell session belongs to another agent' });
+    }
+
+    return reply.send({ success: true, data: { ack: true } });
+  });
+
+  // ─── POST /agent-v2/shell/poll ───
+  app.post('/agent-v2/shell/poll', {
+    preHandler: validateAgentEnvelope,
+  }, async (request, reply) => {
+    const envelope = (request as unknown as Record<string, unknown>).envelope as AgentEnvelope<ShellPollPayload>;
+
+    const events = await shellService.drainShellEvents(envelope.agent_id, envelope.payload.session_ids ?? []);
+
+    return reply.send({ success: true, data: { events } });
+  });
 }
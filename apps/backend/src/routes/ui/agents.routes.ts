@@ -3,9 +3,30 @@
 // ─────────────────────────────────────────────────────────────
 
 import type { FastifyInstance } from 'fastify';
+import { z } from 'zod';
 import { requireAuth, requirePermission } from '../../auth/auth.service.js';
 import * as agentService from '../../services/agent.service.js';
-import type { JwtPayload } from '@massvision/shared';
+import type { AgentPolicy, JobType, JwtPayload } from '@massvision/shared';
+
+const policySchema = z.object({
+  metrics_interval_sec: z.number().int().min(5).max(86400),
+  inventory_interval_sec: z.number().int().min(60).max(7 * 86400),
+  allowed_job_types: z.array(z.string()),
+  max_concurrent_jobs: z.number().int().min(1).max(64),
+  update_channel: z.enum(['stable', 'beta', 'canary']),
+  alert_rules: z.array(z.object({
+    id: z.string().min(1).max(100),
+    name: z.string().max(255).optional(),
+    metric: z.string().min(1).max(100),
+    operator: z.enum(['>', '>=', '<', '<=']),
+    threshold: z.number(),
+    clear_threshold: z.number().optional(),
+    for_samples: z.number().int().min(1).max(1000).optional(),
+    for_sec: z.number().int().min(0).max(86400).optional(),
+    instance: z.string().max(255).optional(),
+    severity: z.enum(['info', 'warning', 'critical']).optional(),
+  })).max(100).optional(),
+});
 
 export async function agentRoutes(app: FastifyInstance): Promise<void> {
   // Apply auth to all routes
@@ -76,6 +97,29 @@ export async function agentRoutes(app: FastifyInstance): Promise<void> {
     return reply.send({ success: true, data: metrics });
   });
 
+  // ─── GET /agents/:id/alerts ───
+  app.get('/agents/:id/alerts', {
+    preHandler: requirePermission('agents.view'),
+  }, async (request, reply) => {
+    const user = request.user as JwtPayload;
+    const { id } = request.params as { id: string };
+    const query = request.query as Record<string, string>;
+
+    const agent = await agentService.getAgent(id, user.org_id);
+    if (!agent) {
+      return reply.code(404).send({
+        success: false,
+        error: { code: 'NOT_FOUND', message: 'Agent not found' },
+      });
+    }
+
+    const state = query.state === 'firing' || query.state === 'resolved' ? query.state : undefined;
+    const limit = Math.min(parseInt(query.limit ?? '100', 10) || 100, 1000);
+
+    const alerts = await agentService.getAgentAlerts(id, state, limit);
+    return reply.send({ success: true, data: alerts });
+  });
+
   // ─── GET /agents/:id/inventory/latest ───
   app.get('/agents/:id/inventory/latest', {
     preHandler: requirePermission('agents.view'),
@@ -102,6 +146,26 @@ export async function agentRoutes(app: FastifyInstance): Promise<void> {
     return reply.send({ success: true, data: inventory });
   });
 
+  // ─── PUT /agents/:id/policy ───
+  app.put('/agents/:id/policy', {
+    preHandler: requirePermission('agents.manage'),
+  }, async (request, reply) => {
+    const user = request.user as JwtPayload;
+    const { id } = request.params as { id: string };
+    const body = policySchema.parse(request.body);
+    const policy: AgentPolicy = { ...body, allowed_job_types: body.allowed_job_types as JobType[] };
+
+    const updated = await agentService.updateAgentPolicy(id, user.org_id, user.sub, policy);
+    if (!updated) {
+      return reply.code(404).send({
+        success: false,
+        error: { code: 'NOT_FOUND', message: 'Agent not found' },
+      });
+    }
+
+    return reply.send({ success: true, data: policy });
+  });
+
   // ─── DELETE /agents/:id ───
   app.delete('/agents/:id', {
     preHandler: requirePermission('agents.delete'),
@@ -0,0 +1,37 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r - UI Artifact Routes
+// ─────────────────────────────────────────────────────────────
+
+import type { FastifyInstance } from 'fastify';
+import { requireAuth, requirePermission } from '../../auth/auth.service.js';
+import * as artifactService from '../../services/artifact.service.js';
+import type { JwtPayload } from '@massvision/shared';
+
+export async function artifactRoutes(app: FastifyInstance): Promise<void> {
+  app.addHook('preHandler', requireAuth(app));
+
+  // ─── GET /artifacts/:id/download ───
+  // Whoever may collect files from endpoints may also retrieve them
+  app.get('/artifacts/:id/download', {
+    preHandler: requirePermission('artifacts.upload'),
+  }, async (request, reply) => {
+    const user = request.user as JwtPayload;
+    const { id } = request.params as { id: string };
+
+    const opened = await artifactService.openArtifact(id, user.org_id);
+    if (!opened) {
+      return reply.code(404).send({
+        success: false,
+        error: { code: 'NOT_FOUND', message: 'Artifact not found' },
+      });
+    }
+
+    const { artifact, stream } = opened;
+    return reply
+      .header('Content-Type', artifact.mime_type)
+      .header('Content-Length', artifact.size_bytes)
+      .header('Content-Disposition', `attachment; filename="${artifact.filename.replace(/"/g, '')}"`)
+      .header('X-Checksum-SHA256', artifact.sha256)
+      .send(stream);
+  });
+}
@@ -15,6 +15,8 @@ const createJobSchema = z.object({
   payload: z.record(z.unknown()),
   timeout_sec: z.number().int().min(1).max(7200).optional(),
   priority: z.enum(['low', 'normal', 'high', 'critical']).optional(),
+  /** Client-chosen ID, required for signed scripts (signed over it) */
+  job_id: z.string().uuid().optional(),
 });
 
 export async function jobRoutes(app: FastifyInstance): Promise<void> {
@@ -48,6 +50,7 @@ export async function jobRoutes(app: FastifyInstance): Promise<void> {
         timeout_sec: body.timeout_sec,
         priority: body.priority,
         created_by: user.sub,
+        job_id: body.job_id,
       });
 
       return reply.code(201).send({ success: true, data: job });
@@ -125,6 +128,26 @@ export async function jobRoutes(app: FastifyInstance): Promise<void> {
     return reply.send({ success: true, data: job.result });
   });
 
+  // ─── GET /jobs/:id/output ───
+  app.get('/jobs/:id/output', {
+    preHandler: requirePermission('jobs.view'),
+  }, async (request, reply) => {
+    const user = request.user as JwtPayload;
+    const { id } = request.params as { id: string };
+    const { after_seq } = request.query as { after_seq?: string };
+    const afterSeq = parseInt(after_seq ?? '', 10);
+
+    const chunks = await jobService.getJobOutput(id, user.org_id, Number.isNaN(afterSeq) ? -1 : afterSeq);
+    if (!chunks) {
+      return reply.code(404).send({
+        success: false,
+        error: { code: 'NOT_FOUND', message: 'Job not found' },
+      });
+    }
+
+    return reply.send({ success: true, data: chunks });
+  });
+
   // ─── POST /jobs/:id/cancel ───
   app.post('/jobs/:id/cancel', {
     preHandler: requirePermission('jobs.cancel'),
@@ -136,7 +159,7 @@ export async function jobRoutes(app: FastifyInstance): Promise<void> {
     if (!cancelled) {
       return reply.code(400).send({
         success: false,
-        error: { code: 'CANCEL_FAILED', message: 'Job cannot be cancelled (already completed or running)' },
+        error: { code: 'CANCEL_FAILED', message: 'Job cannot be cancelled (already completed)' },
       });
     }
 
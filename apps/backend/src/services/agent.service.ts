@@ -19,6 +19,7 @@ import type {
   InventoryPayload,
   AgentCapabilityName,
   AgentPolicy,
+  AlertPayload,
 } from '@massvision/shared';
 
 // ═══════════════════════════════════════════════════════════════
@@ -70,7 +71,8 @@ export async function enrollAgent(req: EnrollRequest): Promise<EnrollResponse |
       'run_script', 'remote_shell_start', 'remote_shell_stop',
       'remote_desktop_start', 'remote_desktop_stop',
       'reboot', 'shutdown',
-      'service_restart', 'service_stop', 'service_start', 'process_kill',
+      'service_restart', 'service_stop', 'service_start', 'process_kill', 'process_list',
+      'wake_on_lan', 'agent_update', 'artifact_upload', 'artifact_download', 'metrics_history',
     ],
     max_concurrent_jobs: 5,
     update_channel: 'stable',
@@ -142,16 +144,17 @@ export async function enrollAgent(req: EnrollRequest): Promise<EnrollResponse |
 // Heartbeat
 // ═══════════════════════════════════════════════════════════════
 
+/** Returns the agent's current policy so the response can carry it. */
 export async function processHeartbeat(
   agentId: string,
   payload: HeartbeatPayload,
-): Promise<void> {
-  const agent = await queryOne<{ status: string; organization_id: string }>(
-    'SELECT status, organization_id FROM agents WHERE id = $1',
+): Promise<AgentPolicy | null> {
+  const agent = await queryOne<{ status: string; organization_id: string; policy: AgentPolicy | null }>(
+    'SELECT status, organization_id, policy FROM agents WHERE id = $1',
     [agentId],
   );
 
-  if (!agent) return;
+  if (!agent) return null;
 
   const previousStatus = agent.status;
   const newStatus = payload.status;
@@ -197,6 +200,47 @@ export async function processHeartbeat(
       new_status: newStatus,
     }));
   }
+
+  return agent.policy;
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Policy
+// ═══════════════════════════════════════════════════════════════
+
+export async function getAgentPolicy(agentId: string): Promise<AgentPolicy | null> {
+  const row = await queryOne<{ policy: AgentPolicy | null }>(
+    'SELECT policy FROM agents WHERE id = $1',
+    [agentId],
+  );
+  return row?.policy ?? null;
+}
+
+/** Replace an agent's policy; connected agents receive it at once. */
+export async function updateAgentPolicy(
+  agentId: string,
+  organizationId: string,
+  userId: string,
+  policy: AgentPolicy,
+): Promise<boolean> {
+  const updated = await queryOne<{ id: string }>(
+    'UPDATE agents SET policy = $1 WHERE id = $2 AND organization_id = $3 RETURNING id',
+    [JSON.stringify(policy), agentId, organizationId],
+  );
+  if (!updated) return false;
+
+  await createAuditLog({
+    organization_id: organizationId,
+    user_id: userId,
+    agent_id: agentId,
+    action: 'agent.policy_updated',
+    resource_type: 'agent',
+    resource_id: agentId,
+    details: { policy },
+  });
+
+  await redis.publish('agent:policy', JSON.stringify({ agent_id: agentId }));
+  return true;
 }
 
 // ═══════════════════════════════════════════════════════════════
@@ -207,7 +251,7 @@ export async function processMetrics(agentId: string, payload: MetricsPayload):
   await queryOne(
     `INSERT INTO metrics_timeseries (agent_id, timestamp, cpu_usage, memory_used_bytes, memory_total_bytes, 
      disk_used_bytes, disk_total_bytes, network_rx_bytes_sec, network_tx_bytes_sec, processes_count, raw_data)
-     VALUES ($1, to_timestamp($2), $3, $4, $5, $6, $7, $8, $9, $10, $11)`,
+     VALUES ($1, to_timestamp($2::double precision / 1000), $3, $4, $5, $6, $7, $8, $9, $10, $11)`,
     [
       agentId,
       payload.timestamp,
@@ -230,6 +274,59 @@ export async function processMetrics(agentId: string, payload: MetricsPayload):
   await redis.publish('agent:metrics', JSON.stringify({ agent_id: agentId, metrics: payload }));
 }
 
+// ═══════════════════════════════════════════════════════════════
+// Alerts
+// ═══════════════════════════════════════════════════════════════
+
+export async function processAlert(agentId: string, payload: AlertPayload): Promise<void> {
+  const agent = await queryOne<{ organization_id: string }>(
+    'SELECT organization_id FROM agents WHERE id = $1',
+    [agentId],
+  );
+  if (!agent) return;
+
+  const instance = payload.instance ?? '';
+  if (payload.state === 'firing') {
+    await query(
+      `INSERT INTO agent_alerts (agent_id, organization_id, rule_id, rule_name, instance, metric, severity,
+       state, operator, threshold, value, started_at)
+       VALUES ($1, $2, $3, $4, $5, $6, $7, 'firing', $8, $9, $10, to_timestamp($11::double precision / 1000))
+       ON CONFLICT (agent_id, rule_id, instance) WHERE state = 'firing'
+       DO UPDATE SET value = EXCLUDED.value, severity = EXCLUDED.severity, updated_at = NOW()`,
+      [
+        agentId, agent.organization_id, payload.rule_id, payload.rule_name, instance, payload.metric,
+        payload.severity, payload.operator, payload.threshold, payload.value, payload.started_at,
+      ],
+    );
+  } else {
+    await query(
+      `UPDATE agent_alerts
+       SET state = 'resolved', value = COALESCE($4, value),
+           resolved_at = to_timestamp($5::double precision / 1000), updated_at = NOW()
+       WHERE agent_id = $1 AND rule_id = $2 AND instance = $3 AND state = 'firing'`,
+      [agentId, payload.rule_id, instance, payload.value, payload.timestamp],
+    );
+  }
+
+  await redis.publish('agent:alert', JSON.stringify({
+    agent_id: agentId,
+    organization_id: agent.organization_id,
+    alert: payload,
+  }));
+}
+
+export async function getAgentAlerts(agentId: string, state?: 'firing' | 'resolved', limit: number = 100) {
+  return queryMany(
+    `SELECT rule_id, rule_name, instance, metric, severity, state, operator, threshold, value,
+            started_at, resolved_at, updated_at
+     FROM agent_alerts
+     WHERE agent_id = $1 AND ($2::varchar IS NULL OR state = $2)
+     ORDER BY started_at DESC
+     LIMIT $3`,
+    [agentId, state ?? null, limit],
+  );
+}
+
 // ═══════════════════════════════════════════════════════════════
 // Inventory
 // ═══════════════════════════════════════════════════════════════
@@ -237,7 +334,7 @@ export async function processMetrics(agentId: string, payload: MetricsPayload):
 export async function processInventory(agentId: string, payload: InventoryPayload): Promise<void> {
   await queryOne(
     `INSERT INTO inventory_snapshots (agent_id, timestamp, os_info, hardware_info, software, services, users, network_config, raw_data)
-     VALUES ($1, to_timestamp($2), $3, $4, $5, $6, $7, $8, $9)`,
+     VALUES ($1, to_timestamp($2::double precision / 1000), $3, $4, $5, $6, $7, $8, $9)`,
     [
       agentId,
       payload.timestamp,
@@ -359,7 +456,8 @@ export async function getAgentMetrics(
 ) {
   return queryMany(
     `SELECT timestamp, cpu_usage, memory_used_bytes, memory_total_bytes,
-            disk_used_bytes, disk_total_bytes, network_rx_bytes_sec, network_tx_bytes_sec, processes_count
+            disk_used_bytes, disk_total_bytes, network_rx_bytes_sec, network_tx_bytes_sec, processes_count,
+            raw_data->'top_processes' AS top_processes
      FROM metrics_timeseries
      WHERE agent_id = $1 AND timestamp >= $2 AND timestamp <= $3
      ORDER BY timestamp DESC
@@ -0,0 +1,179 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r - Artifact Storage
+// ─────────────────────────────────────────────────────────────
+//
+// Resumable uploads from artifact_upload jobs. The agent opens a
+// session, appends chunks at the offset the server reports, then
+// completes; the SHA-256 is verified before the artifact becomes
+// downloadable. The file size on disk is the resume point.
+// ─────────────────────────────────────────────────────────────
+
+import crypto from 'crypto';
+import fs from 'fs';
+import path from 'path';
+import { queryOne } from '../db/connection.js';
+import { config } from '../config/index.js';
+import type {
+  ArtifactChunk,
+  ArtifactUploadInit,
+  ArtifactUploadSession,
+  JobArtifact,
+} from '@massvision/shared';
+
+/** Raw bytes per chunk the agent should send (base64 inflates this by a third). */
+export const ARTIFACT_CHUNK_BYTES = 512 * 1024;
+
+interface ArtifactRecord {
+  id: string;
+  organization_id: string;
+  agent_id: string;
+  job_id: string;
+  filename: string;
+  mime_type: string;
+  size_bytes: string;
+  sha256: string;
+  status: 'uploading' | 'complete';
+}
+
+function storagePath(artifactId: string): string {
+  return path.join(config.ARTIFACT_STORAGE_DIR, artifactId);
+}
+
+async function storedBytes(artifactId: string): Promise<number> {
+  try {
+    return (await fs.promises.stat(storagePath(artifactId))).size;
+  } catch {
+    return 0;
+  }
+}
+
+function toJobArtifact(record: ArtifactRecord): JobArtifact {
+  return {
+    artifact_id: record.id,
+    filename: record.filename,
+    mime_type: record.mime_type,
+    size_bytes: Number(record.size_bytes),
+    sha256: record.sha256,
+    url: `/api/artifacts/${record.id}/download`,
+  };
+}
+
+async function getUploading(agentId: string, artifactId: string): Promise<ArtifactRecord> {
+  const record = await queryOne<ArtifactRecord>(
+    'SELECT * FROM artifacts WHERE id = $1 AND agent_id = $2',
+    [artifactId, agentId],
+  );
+  if (!record) throw new Error('Unknown artifact');
+  if (record.status !== 'uploading') throw new Error('Artifact already complete');
+  return record;
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Agent → Backend
+// ═══════════════════════════════════════════════════════════════
+
+/** Open (or resume) the upload session for a job's artifact. */
+export async function initUpload(agentId: string, init: ArtifactUploadInit): Promise<ArtifactUploadSession> {
+  const job = await queryOne<{ organization_id: string }>(
+    `SELECT organization_id FROM jobs WHERE id = $1 AND agent_id = $2 AND type = 'artifact_upload'`,
+    [init.job_id, agentId],
+  );
+  if (!job) throw new Error(`Job ${init.job_id} not found for agent ${agentId}`);
+  if (init.size_bytes > config.ARTIFACT_MAX_BYTES) {
+    throw new Error(`Artifact exceeds ${config.ARTIFACT_MAX_BYTES} bytes`);
+  }
+
+  const record = await queryOne<ArtifactRecord>(
+    `INSERT INTO artifacts (organization_id, agent_id, job_id, filename, mime_type, size_bytes, sha256)
+     VALUES ($1, $2, $3, $4, $5, $6, $7)
+     ON CONFLICT (job_id, sha256) DO UPDATE SET filename = EXCLUDED.filename
+     RETURNING *`,
+    [
+      job.organization_id,
+      agentId,
+      init.job_id,
+      path.basename(init.filename),
+      init.mime_type,
+      init.size_bytes,
+      init.sha256.toLowerCase(),
+    ],
+  );
+  if (!record) throw new Error('Could not create artifact');
+
+  await fs.promises.mkdir(config.ARTIFACT_STORAGE_DIR, { recursive: true });
+
+  return {
+    artifact_id: record.id,
+    received_bytes: record.status === 'complete' ? Number(record.size_bytes) : await storedBytes(record.id),
+    chunk_size: ARTIFACT_CHUNK_BYTES,
+    complete: record.status === 'complete',
+  };
+}
+
+/** Append a chunk. Out-of-order offsets are refused with the resume point. */
+export async function writeChunk(agentId: string, chunk: ArtifactChunk): Promise<number> {
+  const record = await getUploading(agentId, chunk.artifact_id);
+  const received = await storedBytes(record.id);
+
+  if (chunk.offset !== received) {
+    throw new Error(`Expected offset ${received}`);
+  }
+
+  const data = Buffer.from(chunk.data, 'base64');
+  if (received + data.length > Number(record.size_bytes)) {
+    throw new Error('Chunk exceeds declared size');
+  }
+
+  await fs.promises.appendFile(storagePath(record.id), data);
+  return received + data.length;
+}
+
+/** Verify size and SHA-256, then make the artifact downloadable. Idempotent. */
+export async function completeUpload(agentId: string, artifactId: string): Promise<JobArtifact> {
+  const existing = await queryOne<ArtifactRecord>(
+    `SELECT * FROM artifacts WHERE id = $1 AND agent_id = $2 AND status = 'complete'`,
+    [artifactId, agentId],
+  );
+  if (existing) return toJobArtifact(existing);
+
+  const record = await getUploading(agentId, artifactId);
+  const file = storagePath(record.id);
+
+  const received = await storedBytes(record.id);
+  if (received !== Number(record.size_bytes)) {
+    throw new Error(`Received ${received} of ${record.size_bytes} bytes`);
+  }
+
+  const hash = crypto.createHash('sha256');
+  for await (const data of fs.createReadStream(file)) {
+    hash.update(data as Buffer);
+  }
+  if (hash.digest('hex') !== record.sha256) {
+    // Start over: the agent re-uploads from offset 0
+    await fs.promises.rm(file, { force: true });
+    throw new Error('Uploaded data does not match sha256');
+  }
+
+  const completed = await queryOne<ArtifactRecord>(
+    `UPDATE artifacts SET status = 'complete', completed_at = NOW() WHERE id = $1 RETURNING *`,
+    [record.id],
+  );
+  return toJobArtifact(completed ?? record);
+}
+
+// ═══════════════════════════════════════════════════════════════
+// UI
+// ═══════════════════════════════════════════════════════════════
+
+export async function openArtifact(
+  artifactId: string,
+  organizationId: string,
+): Promise<{ artifact: JobArtifact; stream: fs.ReadStream } | null> {
+  const record = await queryOne<ArtifactRecord>(
+    `SELECT * FROM artifacts WHERE id = $1 AND organization_id = $2 AND status = 'complete'`,
+    [artifactId, organizationId],
+  );
+  if (!record) return null;
+
+  return { artifact: toJobArtifact(record), stream: fs.createReadStream(storagePath(record.id)) };
+}
@@ -0,0 +1,82 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r - Job Service Tests
+// ─────────────────────────────────────────────────────────────
+
+import { generateKeyPairSync, randomUUID, sign, verify } from 'node:crypto';
+import { beforeEach, describe, expect, it, vi } from 'vitest';
+import { scriptSigningMessage } from '@massvision/shared';
+import type { RunScriptPayload } from '@massvision/shared';
+import { createJob } from './job.service.js';
+
+const db = vi.hoisted(() => ({ queryOne: vi.fn(), queryMany: vi.fn(), transaction: vi.fn() }));
+const redis = vi.hoisted(() => ({ lpush: vi.fn(), publish: vi.fn() }));
+
+vi.mock('../db/connection.js', () => db);
+vi.mock('../db/redis.js', () => ({ redis }));
+vi.mock('./audit.service.js', () => ({ createAuditLog: vi.fn() }));
+
+const AGENT_ID = randomUUID();
+const ORG_ID = randomUUID();
+
+/** Answers the queries createJob makes; `insert` decides the INSERT's row. */
+function mockDatabase(insert: (params: unknown[]) => unknown) {
+  db.queryOne.mockImplementation(async (sql: string, params: unknown[]) => {
+    if (sql.includes('FROM agents')) return { id: AGENT_ID, status: 'online', hostname: 'host-a' };
+    if (sql.includes('FROM agent_capabilities')) return { enabled: true };
+    if (sql.includes('INSERT INTO jobs')) return insert(params);
+    return null;
+  });
+}
+
+function runScript(job_id: string | undefined, payload: RunScriptPayload) {
+  return createJob({
+    organization_id: ORG_ID,
+    agent_id: AGENT_ID,
+    type: 'run_script',
+    payload: payload as unknown as Record<string, unknown>,
+    created_by: randomUUID(),
+    job_id,
+  });
+}
+
+describe('createJob with signed scripts', () => {
+  const { publicKey, privateKey } = generateKeyPairSync('ed25519');
+  const script: RunScriptPayload = {
+    language: 'bash',
+    script: 'systemctl restart nginx',
+    capture_output: true,
+    env_vars: { B: '2', A: '1' },
+    signature_expires_at: Math.floor(Date.now() / 1000) + 600,
+  };
+
+  beforeEach(() => {
+    vi.clearAllMocks();
+    mockDatabase((params) => ({ id: params[0], status: params[4] }));
+  });
+
+  it('queues the job under the ID the script was signed for', async () => {
+    // Operator side: pick the ID, sign offline, then create the job
+    const jobId = randomUUID();
+    const signature = sign(null, Buffer.from(scriptSigningMessage(jobId, script)), privateKey).toString('base64');
+
+    const job = await runScript(jobId, { ...script, signature });
+    expect(job.id).toBe(jobId);
+
+    // Agent side: the queued job verifies against the message it rebuilds
+    const queued = JSON.parse(redis.lpush.mock.calls[0][1]);
+    expect(queued.job_id).toBe(jobId);
+    const message = Buffer.from(scriptSigningMessage(queued.job_id, queued.payload));
+    expect(verify(null, message, publicKey, Buffer.from(queued.payload.signature, 'base64'))).toBe(true);
+  });
+
+  it('refuses a signed script without a job_id', async () => {
+    await expect(runScript(undefined, { ...script, signature: 'c2ln' })).rejects.toThrow(/job_id/);
+    expect(db.queryOne).not.toHaveBeenCalled();
+  });
+
+  it('refuses a job_id that is already in use', async () => {
+    mockDatabase(() => null);
+    await expect(runScript(randomUUID(), { ...script, signature: 'c2ln' })).rejects.toThrow(/already in use/);
+    expect(redis.lpush).not.toHaveBeenCalled();
+  });
+});
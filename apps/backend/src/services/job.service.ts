@@ -3,7 +3,7 @@
 // ─────────────────────────────────────────────────────────────
 
 import { v4 as uuidv4 } from 'uuid';
-import { queryOne, queryMany, query, transaction } from '../db/connection.js';
+import { queryOne, queryMany, transaction } from '../db/connection.js';
 import { redis } from '../db/redis.js';
 import { createAuditLog } from './audit.service.js';
 import { JOB_TYPE_CAPABILITY } from '@massvision/shared';
@@ -16,8 +16,12 @@ import type {
   JobStatus,
   JobPriority,
   JobResult,
+  JobOutputChunk,
 } from '@massvision/shared';
 
+/** Cancellations the agent has not picked up within a day are dropped. */
+const CANCEL_TTL_SEC = 86_400;
+
 // ═══════════════════════════════════════════════════════════════
 // Create Job
 // ═══════════════════════════════════════════════════════════════
@@ -30,10 +34,19 @@ export interface CreateJobParams {
   timeout_sec?: number;
   priority?: JobPriority;
   created_by: string;
+  /**
+   * Chosen by the client instead of generated. Signed run_script jobs
+   * need it: the ID is part of the signed message, so it must be
+   * known before the script is signed offline.
+   */
+  job_id?: string;
 }
 
 export async function createJob(params: CreateJobParams): Promise<Job> {
-  const jobId = uuidv4();
+  if (params.type === 'run_script' && params.payload.signature && !params.job_id) {
+    throw new Error('Signed scripts must be created with the job_id they were signed for');
+  }
+  const jobId = params.job_id ?? uuidv4();
 
   // Verify agent belongs to org and is online
   const agent = await queryOne<{ id: string; status: string; hostname: string }>(
@@ -71,6 +84,7 @@ export async function createJob(params: CreateJobParams): Promise<Job> {
   const job = await queryOne<Job>(
     `INSERT INTO jobs (id, organization_id, agent_id, type, status, priority, payload, timeout_sec, created_by)
      VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+     ON CONFLICT (id) DO NOTHING
      RETURNING *`,
     [
       jobId,
@@ -85,7 +99,10 @@ export async function createJob(params: CreateJobParams): Promise<Job> {
     ],
   );
 
-  if (!job) throw new Error('Failed to create job');
+  if (!job) {
+    // A client-chosen ID that already exists: never reuse a signed ID
+    throw new Error(params.job_id ? 'Job ID already in use' : 'Failed to create job');
+  }
 
   // Audit
   await createAuditLog({
@@ -128,18 +145,26 @@ export async function createJob(params: CreateJobParams): Promise<Job> {
 // ═══════════════════════════════════════════════════════════════
 
 export async function getNextJobForAgent(agentId: string): Promise<Record<string, unknown> | null> {
-  const jobJson = await redis.rpop(`agent:jobs:${agentId}`);
-  if (!jobJson) return null;
+  for (;;) {
+    const jobJson = await redis.rpop(`agent:jobs:${agentId}`);
+    if (!jobJson) return null;
 
-  const jobRequest = JSON.parse(jobJson) as Record<string, unknown>;
+    const jobRequest = JSON.parse(jobJson) as Record<string, unknown>;
 
-  // Update job status to queued
-  await queryOne(
-    `UPDATE jobs SET status = 'queued' WHERE id = $1 AND status = 'pending'`,
-    [jobRequest.job_id],
-  );
+    // Update job status to queued; jobs cancelled while pending are skipped
+    const queued = await queryOne<{ id: string }>(
+      `UPDATE jobs SET status = 'queued' WHERE id = $1 AND status = 'pending' RETURNING id`,
+      [jobRequest.job_id],
+    );
+    if (queued) return jobRequest;
+  }
+}
 
-  return jobRequest;
+/** Job IDs the agent must stop, cleared once handed over. */
+export async function drainJobCancels(agentId: string): Promise<string[]> {
+  const key = `agent:cancels:${agentId}`;
+  const results = await redis.multi().smembers(key).del(key).exec();
+  return (results?.[0]?.[1] ?? []) as string[];
 }
 
 // ═══════════════════════════════════════════════════════════════
@@ -222,6 +247,55 @@ export async function processJobResult(agentId: string, result: JobResult): Prom
   }));
 }
 
+// ═══════════════════════════════════════════════════════════════
+// Job Output Stream (Agent → Backend)
+// ═══════════════════════════════════════════════════════════════
+
+export async function processJobOutput(agentId: string, chunk: JobOutputChunk): Promise<void> {
+  const job = await queryOne<{ organization_id: string }>(
+    'SELECT organization_id FROM jobs WHERE id = $1 AND agent_id = $2',
+    [chunk.job_id, agentId],
+  );
+  if (!job) {
+    throw new Error(`Job ${chunk.job_id} not found for agent ${agentId}`);
+  }
+
+  // Chunks may be replayed after a reconnect; seq makes them idempotent
+  await queryOne(
+    `INSERT INTO job_output_chunks (job_id, seq, stream, data, timestamp)
+     VALUES ($1, $2, $3, $4, to_timestamp($5::double precision / 1000))
+     ON CONFLICT (job_id, seq) DO NOTHING`,
+    [chunk.job_id, chunk.seq, chunk.stream, chunk.data, chunk.timestamp],
+  );
+
+  await redis.publish('job:output', JSON.stringify({
+    ...chunk,
+    agent_id: agentId,
+    organization_id: job.organization_id,
+  }));
+}
+
+export async function getJobOutput(
+  jobId: string,
+  organizationId: string,
+  afterSeq: number,
+): Promise<JobOutputChunk[] | null> {
+  const job = await queryOne<{ id: string }>(
+    'SELECT id FROM jobs WHERE id = $1 AND organization_id = $2',
+    [jobId, organizationId],
+  );
+  if (!job) return null;
+
+  return queryMany<JobOutputChunk>(
+    `SELECT job_id, seq, stream, data, (EXTRACT(EPOCH FROM timestamp) * 1000)::double precision AS timestamp
+     FROM job_output_chunks
+     WHERE job_id = $1 AND seq > $2
+     ORDER BY seq
+     LIMIT 1000`,
+    [jobId, afterSeq],
+  );
+}
+
 // ═══════════════════════════════════════════════════════════════
 // Job Queries
 // ═══════════════════════════════════════════════════════════════
@@ -317,13 +391,20 @@ export async function getJob(jobId: string, organizationId: string): Promise<Job
 }
 
 export async function cancelJob(jobId: string, organizationId: string, userId: string): Promise<boolean> {
-  const result = await query(
+  const job = await queryOne<{ agent_id: string }>(
     `UPDATE jobs SET status = 'cancelled', completed_at = NOW()
-     WHERE id = $1 AND organization_id = $2 AND status IN ('pending', 'queued')`,
+     WHERE id = $1 AND organization_id = $2 AND status IN ('pending', 'queued', 'running')
+     RETURNING agent_id`,
     [jobId, organizationId],
   );
 
-  if ((result.rowCount ?? 0) > 0) {
+  if (job) {
+    // The agent may already hold the job: tell it to stop. It reports a
+    // `cancelled` result with whatever output the script produced.
+    const key = `agent:cancels:${job.agent_id}`;
+    await redis.multi().sadd(key, jobId).expire(key, CANCEL_TTL_SEC).exec();
+    await redis.publish('job:cancel', JSON.stringify({ agent_id: job.agent_id, job_id: jobId }));
+
     await createAuditLog({
       organization_id: organizationId,
       user_id: userId,
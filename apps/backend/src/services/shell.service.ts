@@ -0,0 +1,90 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r - Remote Shell Relay
+// ─────────────────────────────────────────────────────────────
+
+import { redis } from '../db/redis.js';
+import { queryOne, queryMany } from '../db/connection.js';
+import type { ShellEvent, ShellOutputPayload } from '@massvision/shared';
+
+const EVENT_TTL_SEC = 300;
+
+// ═══════════════════════════════════════════════════════════════
+// Session Ownership
+// ═══════════════════════════════════════════════════════════════
+// Session IDs are remote_shell_start job IDs. An agent may only
+// drain or publish its own sessions, and a user may only drive the
+// sessions they started.
+
+export async function isAgentSession(agentId: string, sessionId: string): Promise<boolean> {
+  const row = await queryOne<{ id: string }>(
+    `SELECT id FROM jobs WHERE id::text = $1 AND agent_id = $2 AND type = 'remote_shell_start'`,
+    [sessionId, agentId],
+  );
+  return row !== null;
+}
+
+export async function isUserSession(userId: string, orgId: string, sessionId: string): Promise<boolean> {
+  const row = await queryOne<{ id: string }>(
+    `SELECT id FROM jobs
+     WHERE id::text = $1 AND organization_id = $2 AND created_by = $3 AND type = 'remote_shell_start'`,
+    [sessionId, orgId, userId],
+  );
+  return row !== null;
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Agent → UI
+// ═══════════════════════════════════════════════════════════════
+
+/** Returns false, publishing nothing, for a session of another agent. */
+export async function publishShellOutput(
+  agentId: string,
+  organizationId: string,
+  payload: ShellOutputPayload,
+): Promise<boolean> {
+  if (!(await isAgentSession(agentId, payload.session_id))) return false;
+
+  await redis.publish('shell:output', JSON.stringify({
+    ...payload,
+    agent_id: agentId,
+    organization_id: organizationId,
+  }));
+
+  if (payload.closed) {
+    await redis.del(`shell:events:${payload.session_id}`);
+  }
+  return true;
+}
+
+// ═══════════════════════════════════════════════════════════════
+// UI → Agent
+// ═══════════════════════════════════════════════════════════════
+
+export async function queueShellEvent(event: ShellEvent): Promise<void> {
+  if (!event.session_id) return;
+  const key = `shell:events:${event.session_id}`;
+  await redis.multi().rpush(key, JSON.stringify(event)).expire(key, EVENT_TTL_SEC).exec();
+
+  // Wakes the agent WebSocket channel, if the agent is connected
+  await redis.publish('shell:event_queued', JSON.stringify({ session_id: event.session_id }));
+}
+
+/** Sessions that are not the agent's own are skipped. */
+export async function drainShellEvents(agentId: string, sessionIds: string[]): Promise<ShellEvent[]> {
+  if (sessionIds.length === 0) return [];
+  const owned = await queryMany<{ id: string }>(
+    `SELECT id::text AS id FROM jobs WHERE id::text = ANY($1) AND agent_id = $2 AND type = 'remote_shell_start'`,
+    [sessionIds, agentId],
+  );
+
+  const events: ShellEvent[] = [];
+  for (const { id: sessionId } of owned) {
+    const key = `shell:events:${sessionId}`;
+    const results = await redis.multi().lrange(key, 0, -1).del(key).exec();
+    const raw = (results?.[0]?.[1] ?? []) as string[];
+    for (const item of raw) {
+      events.push(JSON.parse(item) as ShellEvent);
+    }
+  }
+  return events;
+}
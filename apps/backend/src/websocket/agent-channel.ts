@@ -0,0 +1,189 @@
+// ─────────────────────────────────────────────────────────────
+// MASSVISION Reap3r - Agent WebSocket Channel
+// ─────────────────────────────────────────────────────────────
+//
+// Persistent socket for agents at /agent-v2/ws. Frames are the
+// same signed envelopes as the HTTP API. The first frame must be
+// a valid `hello`; afterwards jobs, cancellations, shell events
+// and policy changes are pushed and agent reports are processed
+// as they arrive.
+// ─────────────────────────────────────────────────────────────
+
+import type { FastifyInstance } from 'fastify';
+import type { WebSocket } from 'ws';
+import Redis from 'ioredis';
+import { config } from '../config/index.js';
+import { queryOne } from '../db/connection.js';
+import { validateEnvelope, signServerEnvelope } from '../auth/hmac.service.js';
+import * as agentService from '../services/agent.service.js';
+import * as jobService from '../services/job.service.js';
+import * as shellService from '../services/shell.service.js';
+import { agentHeartbeatCounter } from '../services/metrics.service.js';
+import type {
+  AgentEnvelope,
+  HeartbeatPayload,
+  MetricsPayload,
+  AlertPayload,
+  InventoryPayload,
+  JobResult,
+  JobOutputChunk,
+  ShellOutputPayload,
+} from '@massvision/shared';
+
+interface AgentConnection {
+  socket: WebSocket;
+  agentId: string;
+  orgId: string;
+}
+
+const HELLO_TIMEOUT_MS = 10_000;
+
+const connections = new Map<string, AgentConnection>();
+
+function send(conn: AgentConnection, type: string, payload: unknown): void {
+  try {
+    conn.socket.send(JSON.stringify(signServerEnvelope(conn.agentId, type, payload)));
+  } catch {
+    // Socket closing; the agent falls back to HTTP
+  }
+}
+
+async function pushPendingJobs(conn: AgentConnection): Promise<void> {
+  let job = await jobService.getNextJobForAgent(conn.agentId);
+  while (job) {
+    send(conn, 'job', job);
+    job = await jobService.getNextJobForAgent(conn.agentId);
+  }
+
+  for (const jobId of await jobService.drainJobCancels(conn.agentId)) {
+    send(conn, 'job_cancel', { job_id: jobId });
+  }
+}
+
+async function pushPolicy(conn: AgentConnection): Promise<void> {
+  const policy = await agentService.getAgentPolicy(conn.agentId);
+  if (policy) send(conn, 'policy', policy);
+}
+
+async function handleFrame(conn: AgentConnection, envelope: AgentEnvelope): Promise<void> {
+  switch (envelope.type) {
+    case 'heartbeat':
+      await agentService.processHeartbeat(conn.agentId, envelope.payload as HeartbeatPayload);
+      agentHeartbeatCounter.inc();
+      // Socket frames are otherwise unacknowledged; the agent's health
+      // only counts heartbeats the server has processed
+      send(conn, 'heartbeat_ack', { ack: true });
+      await pushPendingJobs(conn);
+      break;
+    case 'metrics':
+      await agentService.processMetrics(conn.agentId, envelope.payload as MetricsPayload);
+      break;
+    case 'alert':
+      await agentService.processAlert(conn.agentId, envelope.payload as AlertPayload);
+      break;
+    case 'inventory':
+      await agentService.processInventory(conn.agentId, envelope.payload as InventoryPayload);
+      break;
+    case 'job_result':
+      await jobService.processJobResult(conn.agentId, envelope.payload as JobResult);
+      break;
+    case 'job_output':
+      await jobService.processJobOutput(conn.agentId, envelope.payload as JobOutputChunk);
+      break;
+    case 'shell_output':
+      if (!(await shellService.publishShellOutput(conn.agentId, conn.orgId, envelope.payload as ShellOutputPayload))) {
+        throw new Error('Shell session belongs to another agent');
+      }
+      break;
+  }
+}
+
+export async function setupAgentChannel(app: FastifyInstance): Promise<void> {
+  const subscriber = new Redis(config.REDIS_URL);
+  subscriber.subscribe('job:created', 'job:cancel', 'shell:event_queued', 'agent:policy');
+
+  subscriber.on('message', async (channel: string, message: string) => {
+    try {
+      const data = JSON.parse(message);
+
+      if (channel === 'job:created' || channel === 'job:cancel') {
+        const conn = connections.get(data.agent_id);
+        if (conn) await pushPendingJobs(conn);
+      } else if (channel === 'agent:policy') {
+        const conn = connections.get(data.agent_id);
+        if (conn) await pushPolicy(conn);
+      } else if (channel === 'shell:event_queued') {
+        // Session IDs are remote_shell_start job IDs
+        const job = await queryOne<{ agent_id: string }>(
+          'SELECT agent_id FROM jobs WHERE id = $1',
+          [data.session_id],
+        );
+        const conn = job ? connections.get(job.agent_id) : undefined;
+        if (!conn) return;
+
+        for (const event of await shellService.drainShellEvents(conn.agentId, [data.session_id])) {
+          send(conn, 'shell_event', event);
+        }
+      }
+    } catch {
+      // Ignore malformed notifications
+    }
+  });
+
+  app.get('/agent-v2/ws', { websocket: true }, (socket) => {
+    let conn: AgentConnection | null = null;
+
+    const helloTimer = setTimeout(() => {
+      if (!conn) socket.close(4001, 'Hello required');
+    }, HELLO_TIMEOUT_MS);
+
+    socket.on('message', async (rawData: Buffer) => {
+      let envelope: AgentEnvelope;
+      try {
+        envelope = JSON.parse(rawData.toString()) as AgentEnvelope;
+      } catch {
+        return;
+      }
+
+      const result = await validateEnvelope(envelope);
+      if (!result.valid || (conn && envelope.agent_id !== conn.agentId)) {
+        if (!conn) socket.close(4001, 'Authentication failed');
+        return;
+      }
+
+      if (!conn) {
+        if (envelope.type !== 'hello') {
+          socket.close(4001, 'Hello required');
+          return;
+        }
+        clearTimeout(helloTimer);
+
+        // One socket per agent: a reconnect replaces the stale one
+        connections.get(envelope.agent_id)?.socket.close(4000, 'Replaced');
+        conn = { socket, agentId: envelope.agent_id, orgId: result.agent.organization_id };
+        connections.set(conn.agentId, conn);
+
+        send(conn, 'hello_ack', { server_time: Math.floor(Date.now() / 1000) });
+        await pushPolicy(conn);
+        await pushPendingJobs(conn);
+        return;
+      }
+
+      try {
+        await handleFrame(conn, envelope);
+      } catch (error) {
+        app.log.warn({ err: error, agent_id: conn.agentId, type: envelope.type }, 'Agent frame failed');
+      }
+    });
+
+    const cleanup = () => {
+      clearTimeout(helloTimer);
+      if (conn && connections.get(conn.agentId) === conn) {
+        connections.delete(conn.agentId);
+      }
+    };
+
+    socket.on('close', cleanup);
+    socket.on('error', cleanup);
+  });
+}
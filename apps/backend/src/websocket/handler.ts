@@ -8,13 +8,17 @@ import { redis } from '../db/redis.js';
 import Redis from 'ioredis';
 import { config } from '../config/index.js';
 import { wsConnectionsGauge } from '../services/metrics.service.js';
-import type { JwtPayload, WsMessage } from '@massvision/shared';
+import * as shellService from '../services/shell.service.js';
+import type { JwtPayload, Permission, WsMessage } from '@massvision/shared';
 
 interface WsClient {
   socket: WebSocket;
   userId: string;
   orgId: string;
+  permissions: Permission[];
   subscriptions: Set<string>;
+  /** Shell sessions this client has been checked against */
+  shellSessions: Set<string>;
 }
 
 const clients = new Map<string, WsClient>();
@@ -26,8 +30,11 @@ export async function setupWebSocket(app: FastifyInstance): Promise<void> {
   subscriber.subscribe(
     'agent:status_changed',
     'agent:metrics',
+    'agent:alert',
     'job:created',
     'job:status_changed',
+    'job:output',
+    'shell:output',
   );
 
   subscriber.on('message', (channel: string, message: string) => {
@@ -54,6 +61,44 @@ export async function setupWebSocket(app: FastifyInstance): Promise<void> {
             data,
             timestamp: Date.now(),
           };
+        } else if (channel === 'agent:alert') {
+          // Alerts go to the whole organization, not just agent subscribers
+          event = {
+            event: 'agent.alert',
+            data,
+            timestamp: Date.now(),
+          };
+        } else if (channel === 'shell:output') {
+          // Only to the client driving the session
+          if (!client.subscriptions.has(`shell:${data.session_id}`)) continue;
+          if (data.data) {
+            client.socket.send(JSON.stringify({
+              event: 'remote_shell.data',
+              data,
+              timestamp: Date.now(),
+            }));
+          }
+          if (data.closed) {
+            event = {
+              event: 'notification',
+              data: {
+                type: 'shell_closed',
+                agent_id: data.agent_id,
+                session_id: data.session_id,
+                reason: data.close_reason,
+              },
+              timestamp: Date.now(),
+            };
+          }
+        } else if (channel === 'job:output') {
+          // Only to clients watching the job or its agent
+          if (!client.subscriptions.has(`job:${data.job_id}`)
+            && !client.subscriptions.has(`agent:${data.agent_id}`)) continue;
+          event = {
+            event: 'job.output',
+            data,
+            timestamp: Date.now(),
+          };
         } else if (channel === 'job:status_changed' || channel === 'job:created') {
           event = {
             event: 'job.status_changed',
@@ -86,11 +131,13 @@ export async function setupWebSocket(app: FastifyInstance): Promise<void> {
 
     let userId: string;
     let orgId: string;
+    let permissions: Permission[];
 
     try {
       const decoded = app.jwt.verify<JwtPayload>(token);
       userId = decoded.sub;
       orgId = decoded.org_id;
+      permissions = decoded.permissions ?? [];
     } catch {
       socket.close(4001, 'Authentication failed');
       return;
@@ -100,7 +147,9 @@ export async function setupWebSocket(app: FastifyInstance): Promise<void> {
       socket,
       userId,
       orgId,
+      permissions,
       subscriptions: new Set(),
+      shellSessions: new Set(),
     };
 
     clients.set(clientId, client);
@@ -113,7 +162,13 @@ export async function setupWebSocket(app: FastifyInstance): Promise<void> {
 
         switch (msg.action) {
           case 'subscribe': {
-            if (msg.channel) {
+            if (typeof msg.channel !== 'string') break;
+            if (msg.channel.startsWith('shell:')) {
+              // Shell output is only for the user who opened the session
+              authorizeShell(client, msg.channel.slice('shell:'.length))
+                .then((ok) => { if (ok) client.subscriptions.add(msg.channel); })
+                .catch(() => {});
+            } else {
               client.subscriptions.add(msg.channel);
             }
             break;
@@ -125,11 +180,25 @@ export async function setupWebSocket(app: FastifyInstance): Promise<void> {
             break;
           }
           case 'remote_shell_input': {
-            // Forward to agent via Redis
-            redis.publish(`shell:input:${msg.session_id}`, JSON.stringify({
-              data: msg.data,
-              user_id: userId,
-            }));
+            // Queued in Redis until the agent polls or its socket picks it up
+            authorizeShell(client, msg.session_id)
+              .then((ok) => ok && shellService.queueShellEvent({
+                type: 'input',
+                session_id: msg.session_id,
+                data: msg.data,
+              }))
+              .catch(() => {});
+            break;
+          }
+          case 'remote_shell_resize': {
+            authorizeShell(client, msg.session_id)
+              .then((ok) => ok && shellService.queueShellEvent({
+                type: 'resize',
+                session_id: msg.session_id,
+                cols: msg.cols,
+                rows: msg.rows,
+              }))
+              .catch(() => {});
             break;
           }
           case 'remote_desktop_input': {
@@ -164,6 +233,17 @@ export async function setupWebSocket(app: FastifyInstance): Promise<void> {
   });
 }
 
+// Shell sessions drive a PTY on the agent: they need remote.shell
+// and must have been started by this user, in this organization
+async function authorizeShell(client: WsClient, sessionId: unknown): Promise<boolean> {
+  if (typeof sessionId !== 'string' || !client.permissions.includes('remote.shell')) return false;
+  if (client.shellSessions.has(sessionId)) return true;
+
+  const ok = await shellService.isUserSession(client.userId, client.orgId, sessionId);
+  if (ok) client.shellSessions.add(sessionId);
+  return ok;
+}
+
 // Broadcast to specific org
 export function broadcastToOrg(orgId: string, event: WsMessage): void {
   for (const [, client] of clients) {
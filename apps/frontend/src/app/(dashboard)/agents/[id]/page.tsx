@@ -30,8 +30,9 @@ import {
 import Link from 'next/link';
 import RunScriptDialog from '@/components/RunScriptDialog';
 import RemoteShellPanel from '@/components/RemoteShellPanel';
+import ProcessListPanel from '@/components/ProcessListPanel';
 
-type TabKey = 'overview' | 'metrics' | 'inventory' | 'jobs' | 'shell';
+type TabKey = 'overview' | 'metrics' | 'inventory' | 'processes' | 'jobs' | 'shell';
 
 export default function AgentDetailPage() {
   const params = useParams();
@@ -109,6 +110,7 @@ export default function AgentDetailPage() {
     { key: 'overview', label: 'Overview', icon: <Activity className="w-4 h-4" /> },
     { key: 'metrics', label: 'Metrics', icon: <Cpu className="w-4 h-4" /> },
     { key: 'inventory', label: 'Inventory', icon: <Package className="w-4 h-4" /> },
+    { key: 'processes', label: 'Processes', icon: <Activity className="w-4 h-4" /> },
     { key: 'jobs', label: 'Jobs', icon: <Play className="w-4 h-4" /> },
     { key: 'shell', label: 'Remote Shell', icon: <Terminal className="w-4 h-4" /> },
   ];
@@ -191,6 +193,9 @@ export default function AgentDetailPage() {
       {activeTab === 'inventory' && (
         <InventoryTab inventory={inventory} />
       )}
+      {activeTab === 'processes' && (
+        <ProcessListPanel agentId={agentId} isOnline={isOnline} />
+      )}
       {activeTab === 'jobs' && (
         <JobsTab agentId={agentId} />
       )}
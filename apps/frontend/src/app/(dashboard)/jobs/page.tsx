@@ -36,7 +36,7 @@ export default function JobsListPage() {
     'remote_desktop_start', 'remote_desktop_stop',
     'reboot', 'shutdown', 'wake_on_lan', 'agent_update',
     'service_restart', 'service_stop', 'service_start',
-    'process_kill', 'artifact_upload', 'artifact_download',
+    'process_kill', 'process_list', 'metrics_history', 'artifact_upload', 'artifact_download',
     'webcam_capture',
   ];
 
@@ -0,0 +1,204 @@
+'use client';
+
+import { useEffect, useState, useCallback } from 'react';
+import { useCreateJob, useJob, useListProcesses } from '@/hooks/useApi';
+import { formatBytes, cn } from '@/lib/utils';
+import type { ProcessInfo, ProcessListResult, ProcessSortKey } from '@massvision/shared';
+import { Activity, RefreshCw, Search, XCircle } from 'lucide-react';
+
+interface ProcessListPanelProps {
+  agentId: string;
+  isOnline: boolean;
+}
+
+const AUTO_REFRESH_MS = 5000;
+const FINISHED = ['success', 'failed', 'timeout', 'cancelled', 'agent_offline', 'unsupported'];
+
+const COLUMNS: { key: ProcessSortKey | null; label: string }[] = [
+  { key: 'pid', label: 'PID' },
+  { key: 'name', label: 'Name' },
+  { key: null, label: 'User' },
+  { key: 'cpu', label: 'CPU %' },
+  { key: 'memory', label: 'Memory' },
+  { key: 'start_time', label: 'Started' },
+  { key: null, label: 'Status' },
+  { key: null, label: 'Command Line' },
+];
+
+export default function ProcessListPanel({ agentId, isOnline }: ProcessListPanelProps) {
+  const [sortBy, setSortBy] = useState<ProcessSortKey>('cpu');
+  const [nameFilter, setNameFilter] = useState('');
+  const [userFilter, setUserFilter] = useState('');
+  const [limit, setLimit] = useState(50);
+  const [autoRefresh, setAutoRefresh] = useState(true);
+  const [jobId, setJobId] = useState<string | null>(null);
+  const [snapshot, setSnapshot] = useState<ProcessListResult | null>(null);
+  const [error, setError] = useState<string | null>(null);
+
+  const listProcesses = useListProcesses();
+  const createJob = useCreateJob();
+  const { data: jobResp } = useJob(jobId ?? '');
+
+  const job = jobResp?.data as Record<string, unknown> | undefined;
+  const status = job?.status as string | undefined;
+  const pending = listProcesses.isPending || (!!jobId && !FINISHED.includes(status ?? ''));
+
+  const refresh = useCallback(() => {
+    if (!isOnline || listProcesses.isPending) return;
+    listProcesses.mutate(
+      {
+        agent_id: agentId,
+        payload: {
+          sort_by: sortBy,
+          name: nameFilter.trim() || undefined,
+          user: userFilter.trim() || undefined,
+          limit,
+        },
+      },
+      {
+        onSuccess: (resp) => setJobId((resp?.data as Record<string, unknown>)?.id as string),
+      },
+    );
+  }, [agentId, isOnline, sortBy, nameFilter, userFilter, limit, listProcesses]);
+
+  // Pick up the snapshot once the agent has answered
+  useEffect(() => {
+    if (!job || job.id !== jobId || !FINISHED.includes(status ?? '')) return;
+    const result = job.result as Record<string, unknown> | null | undefined;
+    if (status === 'success' && result?.result_data) {
+      setSnapshot(result.result_data as ProcessListResult);
+      setError(null);
+    } else {
+      setError((result?.error_message as string) ?? `Process listing ended with status ${status}`);
+    }
+  }, [job, jobId, status]);
+
+  // Initial listing, and a new one whenever the sort order changes
+  // eslint-disable-next-line react-hooks/exhaustive-deps
+  useEffect(() => { refresh(); }, [agentId, sortBy]);
+
+  useEffect(() => {
+    if (!autoRefresh || pending || !snapshot) return;
+    const timer = setTimeout(refresh, AUTO_REFRESH_MS);
+    return () => clearTimeout(timer);
+  }, [autoRefresh, pending, snapshot, refresh]);
+
+  const handleKill = (process: ProcessInfo) => {
+    if (!confirm(`Terminate ${process.name} (PID ${process.pid})?`)) return;
+    createJob.mutate({
+      agent_id: agentId,
+      type: 'process_kill',
+      payload: { pid: process.pid, signal: 'SIGTERM' },
+    });
+  };
+
+  if (!isOnline) {
+    return (
+      <div className="card p-12 text-center">
+        <Activity className="w-12 h-12 mx-auto text-surface-600 mb-4" />
+        <h3 className="text-lg font-medium text-white mb-2">Agent is offline</h3>
+        <p className="text-surface-400 text-sm">Processes can only be listed on an online agent</p>
+      </div>
+    );
+  }
+
+  return (
+    <div className="space-y-4">
+      {/* Filters */}
+      <div className="card p-4 flex flex-wrap items-center gap-3">
+        <div className="relative">
+          <Search className="w-4 h-4 absolute left-3 top-1/2 -translate-y-1/2 text-surface-500" />
+          <input
+            className="input pl-9 w-48"
+            placeholder="Process name"
+            value={nameFilter}
+            onChange={(e) => setNameFilter(e.target.value)}
+            onKeyDown={(e) => e.key === 'Enter' && refresh()}
+          />
+        </div>
+        <input
+          className="input w-36"
+          placeholder="User"
+          value={userFilter}
+          onChange={(e) => setUserFilter(e.target.value)}
+          onKeyDown={(e) => e.key === 'Enter' && refresh()}
+        />
+        <select className="input w-28" value={limit} onChange={(e) => setLimit(Number(e.target.value))}>
+          {[25, 50, 100, 250].map((n) => <option key={n} value={n}>Top {n}</option>)}
+        </select>
+        <label className="flex items-center gap-2 text-sm text-surface-400">
+          <input type="checkbox" checked={autoRefresh} onChange={(e) => setAutoRefresh(e.target.checked)} />
+          Auto refresh
+        </label>
+        <button onClick={refresh} disabled={pending} className="btn-secondary btn-sm ml-auto">
+          <RefreshCw className={cn('w-4 h-4', pending && 'animate-spin')} /> Refresh
+        </button>
+      </div>
+
+      {error && (
+        <div className="card p-4 text-sm text-red-400">{error}</div>
+      )}
+
+      {snapshot && (
+        <div className="card overflow-hidden">
+          <div className="px-4 py-2 text-xs text-surface-400 border-b border-surface-700">
+            Showing {snapshot.returned} of {snapshot.matched} matching ({snapshot.total} processes, {snapshot.cpu_count} CPUs)
+          </div>
+          <div className="overflow-x-auto">
+            <table className="w-full text-sm">
+              <thead>
+                <tr className="border-b border-surface-700 bg-surface-800/50 text-surface-400 text-xs uppercase">
+                  {COLUMNS.map((col) => (
+                    <th
+                      key={col.label}
+                      onClick={() => col.key && setSortBy(col.key)}
+                      className={cn(
+                        'text-left py-2 px-3 font-medium',
+                        col.key && 'cursor-pointer hover:text-white',
+                        col.key === sortBy && 'text-brand-400',
+                      )}
+                    >
+                      {col.label}
+                    </th>
+                  ))}
+                  <th className="py-2 px-3" />
+                </tr>
+              </thead>
+              <tbody className="divide-y divide-surface-700/50">
+                {snapshot.processes.map((p) => (
+                  <tr key={p.pid} className="text-surface-300 hover:bg-surface-800/50">
+                    <td className="py-1.5 px-3 font-mono text-xs">{p.pid}</td>
+                    <td className="py-1.5 px-3 text-white">{p.name}</td>
+                    <td className="py-1.5 px-3 text-xs">{p.user ?? '—'}</td>
+                    <td className="py-1.5 px-3 font-mono text-xs">{p.cpu_percent.toFixed(1)}</td>
+                    <td className="py-1.5 px-3 font-mono text-xs">{formatBytes(p.memory_bytes)}</td>
+                    <td className="py-1.5 px-3 text-xs">{new Date(p.start_time * 1000).toLocaleString()}</td>
+                    <td className="py-1.5 px-3 text-xs">{p.status}</td>
+                    <td className="py-1.5 px-3 font-mono text-xs truncate max-w-[320px]" title={p.cmdline ?? ''}>
+                      {p.cmdline ?? p.exe ?? '—'}
+                    </td>
+                    <td className="py-1.5 px-3">
+                      <button
+                        onClick={() => handleKill(p)}
+                        className="p-1 rounded hover:bg-red-500/20 text-surface-500 hover:text-red-400"
+                        title="Terminate (SIGTERM)"
+                      >
+                        <XCircle className="w-4 h-4" />
+                      </button>
+                    </td>
+                  </tr>
+                ))}
+              </tbody>
+            </table>
+          </div>
+        </div>
+      )}
+
+      {!snapshot && !error && (
+        <div className="flex justify-center py-8">
+          <div className="animate-spin w-6 h-6 border-2 border-brand-500 border-t-transparent rounded-full" />
+        </div>
+      )}
+    </div>
+  );
+}
@@ -2,7 +2,7 @@
 
 import { useEffect, useRef, useState, useCallback } from 'react';
 import { useCreateJob } from '@/hooks/useApi';
-import { useWebSocketEvent, useWebSocketSend } from '@/hooks/useWebSocket';
+import { useShellSubscription, useWebSocketEvent, useWebSocketSend } from '@/hooks/useWebSocket';
 import { Terminal as TerminalIcon, X, Maximize2, Minimize2 } from 'lucide-react';
 
 interface RemoteShellPanelProps {
@@ -18,6 +18,7 @@ export default function RemoteShellPanel({ agentId, isOnline }: RemoteShellPanel
   const [isFullscreen, setIsFullscreen] = useState(false);
   const createJob = useCreateJob();
   const sendWs = useWebSocketSend();
+  useShellSubscription(sessionId);
 
   // Start remote shell session
   const startSession = useCallback(() => {
@@ -136,6 +137,14 @@ export default function RemoteShellPanel({ agentId, isOnline }: RemoteShellPanel
         const resizeObserver = new ResizeObserver(() => {
           try {
             fitAddon.fit();
+            if (sessionId) {
+              sendWs({
+                action: 'remote_shell_resize',
+                session_id: sessionId,
+                cols: terminal.cols,
+                rows: terminal.rows,
+              });
+            }
           } catch {
             // ignore
           }
@@ -7,6 +7,7 @@
 import { useQuery, useMutation, useQueryClient } from '@tanstack/react-query';
 import { api } from '@/lib/api';
 import toast from 'react-hot-toast';
+import type { ProcessListPayload } from '@massvision/shared';
 
 // ═══════════════════════════════════════════════════════════════
 // Dashboard
@@ -130,6 +131,17 @@ export function useCancelJob() {
   });
 }
 
+/** Starts a process_list job; no success toast since it is polled. */
+export function useListProcesses() {
+  return useMutation({
+    mutationFn: (data: { agent_id: string; payload: ProcessListPayload }) =>
+      api.createJob({ agent_id: data.agent_id, type: 'process_list', payload: { ...data.payload } }),
+    onError: (error: Error) => {
+      toast.error(`Failed to list processes: ${error.message}`);
+    },
+  });
+}
+
 // ═══════════════════════════════════════════════════════════════
 // Audit Logs
 // ═══════════════════════════════════════════════════════════════
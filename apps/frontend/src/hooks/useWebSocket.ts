@@ -49,6 +49,18 @@ export function useAgentSubscription(agentId: string) {
   }, [agentId]);
 }
 
+/** Shell output is only relayed to clients subscribed to the session. */
+export function useShellSubscription(sessionId: string | null) {
+  useEffect(() => {
+    if (!sessionId) return;
+
+    wsClient.subscribe(`shell:${sessionId}`);
+    return () => {
+      wsClient.unsubscribe(`shell:${sessionId}`);
+    };
+  }, [sessionId]);
+}
+
 export function useWsConnection() {
   const connect = useCallback((token: string) => {
     wsClient.connect(token);
@@ -52,6 +52,7 @@ export function getStatusColor(status: string): string {
     case 'queued': return 'text-blue-300';
     case 'running': return 'text-cyan-400';
     case 'cancelled': return 'text-surface-400';
+    case 'unsupported': return 'text-orange-400';
     default: return 'text-surface-400';
   }
 }
@@ -67,6 +68,7 @@ export function getStatusBadge(status: string): string {
     case 'degraded':
     case 'timeout':
     case 'pending':
+    case 'unsupported':
       return 'badge-warning';
     case 'running':
     case 'queued':
@@ -28,6 +28,7 @@ export const PERMISSIONS = [
 
   // Service/process management
   'services.manage',
+  'processes.view',
   'processes.kill',
 
   // Agent updates
@@ -82,7 +83,7 @@ export const BUILT_IN_ROLES = {
       'scripts.run', 'scripts.manage',
       'remote.shell', 'remote.desktop',
       'power.reboot', 'power.shutdown', 'power.wol',
-      'services.manage', 'processes.kill',
+      'services.manage', 'processes.view', 'processes.kill',
       'agent.update',
       'artifacts.upload', 'artifacts.download',
       'jobs.view', 'jobs.create', 'jobs.cancel',
@@ -100,7 +101,7 @@ export const BUILT_IN_ROLES = {
       'scripts.run',
       'remote.shell', 'remote.desktop',
       'power.reboot', 'power.wol',
-      'services.manage', 'processes.kill',
+      'services.manage', 'processes.view', 'processes.kill',
       'artifacts.upload', 'artifacts.download',
       'jobs.view', 'jobs.create', 'jobs.cancel',
       'audit.view',
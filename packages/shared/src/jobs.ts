@@ -2,6 +2,8 @@
 // MASSVISION Reap3r - Job Types (Complete Enum + Payloads)
 // ─────────────────────────────────────────────────────────────
 
+import type { MetricsAggregates } from './protocol.js';
+
 // ═══════════════════════════════════════════════════════════════
 // Job Type Enum
 // ═══════════════════════════════════════════════════════════════
@@ -24,6 +26,8 @@ export type JobType =
   | 'service_stop'
   | 'service_start'
   | 'process_kill'
+  | 'process_list'
+  | 'metrics_history'
   | 'artifact_upload'
   | 'artifact_download'
   | 'webcam_capture';
@@ -36,7 +40,8 @@ export type JobStatus =
   | 'failed'
   | 'timeout'
   | 'cancelled'
-  | 'agent_offline';
+  | 'agent_offline'
+  | 'unsupported';
 
 export type JobPriority = 'low' | 'normal' | 'high' | 'critical';
 
@@ -80,6 +85,30 @@ export interface JobArtifact {
   url?: string;
 }
 
+// ═══════════════════════════════════════════════════════════════
+// Job Output Stream (Agent → Backend, while the job runs)
+// ═══════════════════════════════════════════════════════════════
+
+export interface JobOutputChunk {
+  job_id: string;
+  /** Shared across stdout and stderr; gaps mean chunks were not delivered */
+  seq: number;
+  stream: 'stdout' | 'stderr';
+  data: string;
+  /** Epoch milliseconds when the chunk was flushed */
+  timestamp: number;
+}
+
+/** `result_data.output` of a streamed run_script result */
+export interface JobOutputSummary {
+  /** Chunks sent; the full stream is seq 0..chunks-1 */
+  chunks: number;
+  stdout_bytes: number;
+  stderr_bytes: number;
+  /** False if some chunks were not delivered; the result still carries the last 1 MiB of each stream */
+  complete: boolean;
+}
+
 // ═══════════════════════════════════════════════════════════════
 // Job Payloads by Type
 // ═══════════════════════════════════════════════════════════════
@@ -89,8 +118,46 @@ export interface RunScriptPayload {
   script: string;
   working_dir?: string;
   env_vars?: Record<string, string>;
+  /** Local account to run as; must be in the agent's run_as_allowed_users (Linux only) */
   run_as?: string;
   capture_output: boolean;
+  /** Capped by the agent's local job_limits */
+  limits?: ResourceLimits;
+  /** Base64 Ed25519 signature over scriptSigningMessage(jobId, payload) */
+  signature?: string;
+  /** Unix seconds after which agents refuse the signature; required when signed */
+  signature_expires_at?: number;
+}
+
+/**
+ * The exact string an organization signing key signs for a
+ * run_script job. Agents with script_signing_keys verify it
+ * before launching the interpreter. The job ID and expiry keep a
+ * signature from being replayed as another job.
+ */
+export function scriptSigningMessage(jobId: string, payload: RunScriptPayload): string {
+  const env = payload.env_vars ?? {};
+  return JSON.stringify([
+    'reap3r-script-v2',
+    jobId,
+    payload.signature_expires_at ?? null,
+    payload.language,
+    payload.script,
+    payload.working_dir ?? null,
+    payload.run_as ?? null,
+    Object.keys(env).sort().map((key) => [key, env[key]]),
+  ]);
+}
+
+export interface ResourceLimits {
+  /** CPU seconds for the whole job */
+  cpu_time_sec?: number;
+  memory_mb?: number;
+  max_processes?: number;
+  /** Per process */
+  max_open_files?: number;
+  /** stdout and stderr together */
+  max_output_bytes?: number;
 }
 
 export interface RemoteShellStartPayload {
@@ -153,14 +220,21 @@ export interface WakeOnLanPayload {
   target_agent_id?: string;
   broadcast_address?: string;
   port?: number;
+  /** SecureOn password: 4 or 6 bytes of hex, e.g. "01:02:03:04:05:06" */
+  secureon_password?: string;
 }
 
 export interface AgentUpdatePayload {
   version: string;
+  /** Signed together with version and sha256; defaults to 'stable' */
+  channel?: 'stable' | 'beta' | 'canary';
   url: string;
   sha256: string;
+  /** Base64 Ed25519 signature over `${version}|${channel}|${sha256}` */
   signature: string;
   force: boolean;
+  /** Required to install a version older than the running one */
+  allow_downgrade?: boolean;
   rollback_on_failure: boolean;
 }
 
@@ -181,8 +255,81 @@ export interface ServiceActionPayload {
 }
 
 export interface ProcessKillPayload {
+  /** Exactly one of pid, name, exe_path and cmdline_pattern */
+  pid?: number;
+  name?: string;
+  exe_path?: string;
+  /** Regex over the space-joined command line */
+  cmdline_pattern?: string;
+  signal?: string; // SIGTERM, SIGKILL, etc. Defaults to SIGKILL
+  kill_children?: boolean;
+  /** Nothing is signalled when more processes match (default 10, at most 100) */
+  max_matches?: number;
+}
+
+export type ProcessSortKey = 'cpu' | 'memory' | 'pid' | 'name' | 'start_time';
+
+export interface ProcessListPayload {
+  sort_by?: ProcessSortKey; // Defaults to cpu
+  /** Defaults to true for cpu, memory and start_time */
+  descending?: boolean;
+  /** Case-insensitive substring of the process name */
+  name?: string;
+  /** Exact owner user name */
+  user?: string;
+  /** Top N after sorting (default 50, at most 1000) */
+  limit?: number;
+}
+
+/** One row of a process_list result */
+export interface ProcessInfo {
   pid: number;
-  signal?: string; // SIGTERM, SIGKILL, etc.
+  ppid: number | null;
+  name: string;
+  user: string | null;
+  cmdline: string | null;
+  exe: string | null;
+  start_time: number; // Unix seconds
+  cpu_percent: number; // Per core, as in top: 200 = two busy cores
+  memory_bytes: number; // Resident set size
+  virtual_memory_bytes: number;
+  status: string;
+}
+
+/** result_data of a process_list job */
+export interface ProcessListResult {
+  total: number;
+  matched: number;
+  returned: number;
+  sort_by: ProcessSortKey;
+  descending: boolean;
+  cpu_count: number;
+  processes: ProcessInfo[];
+}
+
+export interface MetricsHistoryPayload {
+  /** Raw samples of the last N minutes (default 5, at most the agent's metrics_history_minutes) */
+  minutes?: number;
+}
+
+/** One high-resolution sample; throughput is summed over physical disks and non-loopback interfaces */
+export interface MetricsSample {
+  timestamp: number; // Unix milliseconds
+  cpu_percent: number;
+  memory_percent: number;
+  disk_read_bytes_sec: number;
+  disk_write_bytes_sec: number;
+  net_rx_bytes_sec: number;
+  net_tx_bytes_sec: number;
+}
+
+/** result_data of a metrics_history job */
+export interface MetricsHistoryResult {
+  minutes: number;
+  sample_interval_sec: number;
+  count: number;
+  aggregates: MetricsAggregates | null;
+  samples: MetricsSample[];
 }
 
 export interface ArtifactUploadPayload {
@@ -226,6 +373,8 @@ export interface JobPayloadMap {
   service_stop: ServiceActionPayload;
   service_start: ServiceActionPayload;
   process_kill: ProcessKillPayload;
+  process_list: ProcessListPayload;
+  metrics_history: MetricsHistoryPayload;
   artifact_upload: ArtifactUploadPayload;
   artifact_download: ArtifactDownloadPayload;
   webcam_capture: WebcamCapturePayload;
@@ -253,6 +402,8 @@ export const JOB_TYPE_PERMISSION: Record<JobType, string> = {
   service_stop: 'services.manage',
   service_start: 'services.manage',
   process_kill: 'processes.kill',
+  process_list: 'processes.view',
+  metrics_history: 'agents.view',
   artifact_upload: 'artifacts.upload',
   artifact_download: 'artifacts.download',
   webcam_capture: 'webcam.capture',
@@ -280,6 +431,8 @@ export const JOB_TYPE_CAPABILITY: Record<JobType, string> = {
   service_stop: 'service_management',
   service_start: 'service_management',
   process_kill: 'process_management',
+  process_list: 'process_management',
+  metrics_history: 'metrics',
   artifact_upload: 'artifact_transfer',
   artifact_download: 'artifact_transfer',
   webcam_capture: 'webcam_capture',
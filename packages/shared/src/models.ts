@@ -110,6 +110,7 @@ export type AuditAction =
   | 'agent.enrolled'
   | 'agent.deleted'
   | 'agent.updated'
+  | 'agent.policy_updated'
   | 'agent.went_offline'
   | 'agent.came_online'
   | 'job.created'
@@ -193,6 +194,7 @@ export interface PaginationQuery {
 export type WsEventType =
   | 'agent.status_changed'
   | 'agent.metrics'
+  | 'agent.alert'
   | 'job.status_changed'
   | 'job.output'
   | 'remote_shell.data'
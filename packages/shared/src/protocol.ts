@@ -2,7 +2,7 @@
 // MASSVISION Reap3r - Protocol V2 Types (Source of Truth)
 // ─────────────────────────────────────────────────────────────
 
-import type { JobType } from './jobs.js';
+import type { JobRequest, JobType } from './jobs.js';
 
 // ═══════════════════════════════════════════════════════════════
 // Agent Envelope - Every message agent <-> backend
@@ -24,7 +24,16 @@ export type AgentMessageType =
   | 'job_result'
   | 'capabilities'
   | 'enroll_request'
-  | 'enroll_response';
+  | 'enroll_response'
+  | 'shell_output'
+  | 'shell_poll'
+  | 'alert'
+  // WebSocket channel (/agent-v2/ws)
+  | 'hello'
+  | 'hello_ack'
+  | 'heartbeat_ack'
+  | 'job'
+  | 'shell_event';
 
 // ═══════════════════════════════════════════════════════════════
 // Enrollment
@@ -54,6 +63,56 @@ export interface AgentPolicy {
   allowed_job_types: JobType[];
   max_concurrent_jobs: number;
   update_channel: 'stable' | 'beta' | 'canary';
+  /** Threshold rules the agent evaluates against every metrics sample */
+  alert_rules?: AlertRule[];
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Alerts
+// ═══════════════════════════════════════════════════════════════
+
+export type AlertOperator = '>' | '>=' | '<' | '<=';
+export type AlertSeverity = 'info' | 'warning' | 'critical';
+export type AlertState = 'firing' | 'resolved';
+
+/**
+ * `metric` is a dotted path into MetricsPayload: cpu.usage_percent,
+ * memory.used_percent, disk.available_percent (per mount point),
+ * network.rx_bytes_sec (per interface), load.5, pressure.io.some.avg60...
+ */
+export interface AlertRule {
+  id: string;
+  name?: string;
+  metric: string;
+  operator: AlertOperator;
+  threshold: number;
+  /** The value must get back past this before the alert resolves (hysteresis); defaults to threshold */
+  clear_threshold?: number;
+  /** Consecutive samples the condition must hold, to fire and to resolve */
+  for_samples?: number;
+  /** Seconds the condition must hold, to fire and to resolve */
+  for_sec?: number;
+  /** Only this mount point, interface or sensor; every one otherwise */
+  instance?: string;
+  /** Defaults to warning */
+  severity?: AlertSeverity;
+}
+
+/** Sent by the agent as soon as a rule fires or resolves */
+export interface AlertPayload {
+  rule_id: string;
+  rule_name: string | null;
+  state: AlertState;
+  severity: AlertSeverity;
+  metric: string;
+  instance: string | null;
+  /** null when the instance or the rule went away while firing */
+  value: number | null;
+  operator: AlertOperator;
+  threshold: number;
+  /** When the condition first held (Unix ms) */
+  started_at: number;
+  timestamp: number;
 }
 
 // ═══════════════════════════════════════════════════════════════
@@ -68,6 +127,15 @@ export interface HeartbeatPayload {
   capabilities: AgentCapabilityName[];
 }
 
+export interface HeartbeatResponse {
+  ack: boolean;
+  pending_job: JobRequest | null;
+  /** Jobs the agent should stop; running scripts report `cancelled` */
+  cancel_jobs?: string[];
+  /** The agent's current policy; the agent applies it when it changed */
+  policy?: AgentPolicy;
+}
+
 export type AgentStatus = 'online' | 'offline' | 'degraded' | 'updating';
 
 // ═══════════════════════════════════════════════════════════════
@@ -82,6 +150,54 @@ export interface MetricsPayload {
   network: NetworkMetrics[];
   processes_count: number;
   uptime_sec: number;
+  // Absent from older agents and on platforms that do not expose them
+  /** 1, 5 and 15 minute load averages (not on Windows) */
+  load_average?: [number, number, number];
+  /** Linux pressure stall information */
+  pressure?: PressureMetrics;
+  temperatures?: TemperatureMetrics[];
+  file_descriptors?: FileDescriptorMetrics;
+  context_switches_sec?: number;
+  /** Summary of the agent's fine-grained samples since its previous report */
+  aggregates?: MetricsAggregates;
+  /** Heaviest processes, on every `metrics_top_processes_every`th report */
+  top_processes?: TopProcesses;
+  /** Running containers found in cgroup v2 (Linux) */
+  containers?: ContainerMetrics[];
+}
+
+export interface TopProcesses {
+  by_cpu: ProcessUsage[];
+  by_memory: ProcessUsage[];
+}
+
+export interface ProcessUsage {
+  pid: number;
+  name: string;
+  user: string | null;
+  /** Average since the previous report, per core (200 = two cores busy) */
+  cpu_percent: number;
+  /** Resident memory */
+  memory_bytes: number;
+}
+
+export interface ContainerMetrics {
+  /** Full container ID */
+  id: string;
+  /** Container name, or the short ID when the runtime's state is unreadable */
+  name: string;
+  runtime: 'docker' | 'podman' | 'containerd' | 'cri-o';
+  /** Since the previous report, per core (200 = two cores busy) */
+  cpu_percent: number;
+  /** CPU quota in cores; absent when unlimited */
+  cpu_limit_cores?: number;
+  /** In use, without reclaimable page cache */
+  memory_bytes: number;
+  /** Absent when unlimited */
+  memory_limit_bytes?: number;
+  io_read_bytes_sec: number;
+  io_write_bytes_sec: number;
+  pids: number;
 }
 
 export interface CpuMetrics {
@@ -90,6 +206,57 @@ export interface CpuMetrics {
   model: string;
   frequency_mhz: number;
   per_core_usage: number[];
+  /** Share of CPU time since the previous sample (Linux) */
+  iowait_percent?: number;
+  steal_percent?: number;
+}
+
+export interface PressureMetrics {
+  cpu?: PressureStall;
+  memory?: PressureStall;
+  io?: PressureStall;
+}
+
+export interface PressureStall {
+  some?: PressureLine; // Some tasks stalled
+  full?: PressureLine; // All non-idle tasks stalled
+}
+
+export interface PressureLine {
+  /** Percent of time stalled over the last 10, 60 and 300 seconds */
+  avg10: number;
+  avg60: number;
+  avg300: number;
+  total_us: number;
+}
+
+export interface TemperatureMetrics {
+  label: string;
+  celsius: number;
+  critical_celsius?: number;
+}
+
+export interface MetricsAggregates {
+  samples: number;
+  sample_interval_sec: number;
+  cpu_percent: Aggregate;
+  memory_percent: Aggregate;
+  disk_read_bytes_sec: Aggregate;
+  disk_write_bytes_sec: Aggregate;
+  net_rx_bytes_sec: Aggregate;
+  net_tx_bytes_sec: Aggregate;
+}
+
+export interface Aggregate {
+  min: number;
+  max: number;
+  avg: number;
+  p95: number;
+}
+
+export interface FileDescriptorMetrics {
+  open: number;
+  max: number;
 }
 
 export interface MemoryMetrics {
@@ -107,6 +274,7 @@ export interface DiskMetrics {
   total_bytes: number;
   used_bytes: number;
   available_bytes: number;
+  /** Per-second rates since the previous sample; 0 on the first one and off Linux */
   read_bytes_sec: number;
   write_bytes_sec: number;
 }
@@ -115,6 +283,7 @@ export interface NetworkMetrics {
   interface_name: string;
   ip_address: string;
   mac_address: string;
+  /** Per-second rates since the previous sample; 0 on the first one or after a counter reset */
   rx_bytes_sec: number;
   tx_bytes_sec: number;
   rx_packets_sec: number;
@@ -133,6 +302,16 @@ export interface InventoryPayload {
   services: ServiceInfo[];
   users: LocalUser[];
   network_config: NetworkConfig[];
+  /** Docker, Podman, containerd or CRI-O installs found on the host (Linux) */
+  container_runtimes?: ContainerRuntime[];
+}
+
+export interface ContainerRuntime {
+  name: 'docker' | 'podman' | 'containerd' | 'cri-o';
+  version: string | null;
+  /** API socket, when present */
+  socket: string | null;
+  running_containers: number;
 }
 
 export interface OsInfo {
@@ -209,6 +388,80 @@ export interface NetworkConfig {
 
 export type OsType = 'windows' | 'linux' | 'macos';
 
+// ═══════════════════════════════════════════════════════════════
+// WebSocket Channel
+// ═══════════════════════════════════════════════════════════════
+
+export interface WsHelloPayload {
+  agent_version: string;
+  capabilities: AgentCapabilityName[];
+}
+
+export interface WsHelloAckPayload {
+  server_time: number;
+}
+
+/** Server → agent `job_cancel` frame */
+export interface JobCancelPayload {
+  job_id: string;
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Artifact Upload (resumable, agent → backend)
+// ═══════════════════════════════════════════════════════════════
+
+/** POST /agent-v2/artifacts/init */
+export interface ArtifactUploadInit {
+  job_id: string;
+  filename: string;
+  mime_type: string;
+  size_bytes: number;
+  sha256: string;
+}
+
+export interface ArtifactUploadSession {
+  artifact_id: string;
+  /** Resume point: bytes the server already holds */
+  received_bytes: number;
+  chunk_size: number;
+  complete: boolean;
+}
+
+/** POST /agent-v2/artifacts/chunk */
+export interface ArtifactChunk {
+  artifact_id: string;
+  offset: number;
+  /** base64 */
+  data: string;
+}
+
+/** POST /agent-v2/artifacts/complete; answered with the JobArtifact */
+export interface ArtifactUploadComplete {
+  artifact_id: string;
+}
+
+// ═══════════════════════════════════════════════════════════════
+// Remote Shell
+// ═══════════════════════════════════════════════════════════════
+
+export interface ShellOutputPayload {
+  session_id: string;
+  seq: number;
+  data: string;
+  closed: boolean;
+  close_reason?: string;
+  exit_code?: number;
+}
+
+export interface ShellPollPayload {
+  session_ids: string[];
+}
+
+export type ShellEvent =
+  | { type: 'input'; session_id: string; data: string }
+  | { type: 'resize'; session_id: string; cols: number; rows: number }
+  | { type: 'stop'; session_id: string };
+
 // ═══════════════════════════════════════════════════════════════
 // Capabilities
 // ═══════════════════════════════════════════════════════════════